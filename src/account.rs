@@ -1,173 +1,1508 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use crate::{
+    admin::Verified,
+    amount::Amount,
+    counterparty::Counterparties,
+    error::LedgerError,
+    group::AccountGroups,
+    hash::{ClientMap, ClientSet},
+    journal::{Journal, PostingAccount},
+    snapshot::{AccountSnapshot, Snapshot, TransactionSnapshot},
+    store::{TransactionKind, TransactionState, TransactionStore},
+    structs,
+    tag::Tags,
+};
+
+/// The escrow bucket that [`Ledger::open_account`] restores escrowed funds
+/// into.
+pub const OPENING_ESCROW: &str = "opening";
+
+/// Controls how much transaction history is kept in memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Keep every deposit and withdrawal.
+    #[default]
+    All,
+    /// Keep only the given number of most recent deposits per customer,
+    /// plus any disputed ones. Withdrawals are not retained.
+    DisputeWindow(usize),
+    /// Keep no history at all, which makes every dispute fail.
+    None,
+}
+
+/// Controls what happens to accounts that were never funded, like those
+/// created by a failed dispute for an unknown client.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyAccountPolicy {
+    /// List them with all-zero balances.
+    Keep,
+    /// Keep them in the ledger, but leave them out of the account listings.
+    #[default]
+    Omit,
+    /// Like `Omit`, and remove them from the ledger when the record that
+    /// touched them fails, so they do not end up in snapshots either.
+    Remove,
+}
 
-use anyhow::anyhow;
+/// Conditions under which an account is frozen, i.e. locked.
+///
+/// The chargeback rule is checked after every chargeback, the held rule
+/// after every dispute. The default freezes an account for good on its first
+/// chargeback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FreezePolicy {
+    /// Freeze after this many chargebacks.
+    pub chargebacks: Option<u32>,
+    /// Freeze when the held funds exceed this amount.
+    pub held_above: Option<Amount>,
+    /// Unfreeze accounts this many seconds after they were frozen, measured
+    /// by the timestamps of their transactions. Without it, or as long as no
+    /// transaction of the account had a timestamp, a freeze is permanent.
+    pub cooling_off: Option<u64>,
+}
 
-use crate::structs;
+impl Default for FreezePolicy {
+    fn default() -> Self {
+        Self {
+            chargebacks: Some(1),
+            held_above: None,
+            cooling_off: None,
+        }
+    }
+}
+
+impl FreezePolicy {
+    /// Sets rules from a list like `chargebacks=3,held=1000,cooling-off=86400`.
+    /// A value of 0 disables the chargeback rule or the cooling-off period.
+    pub fn set_rules(&mut self, rules: &str) -> anyhow::Result<()> {
+        for setting in rules.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (rule, value) = setting.split_once('=').ok_or(anyhow::anyhow!(
+                "Malformed freeze rule {setting}, expected rule=value"
+            ))?;
+            let value = value.trim();
+            let invalid = || anyhow::anyhow!("Invalid value for freeze rule {rule}: {value}");
+            match rule.trim() {
+                "chargebacks" => {
+                    self.chargebacks = match value.parse().map_err(|_| invalid())? {
+                        0 => None,
+                        count => Some(count),
+                    }
+                }
+                "held" => {
+                    let limit: Amount = value.parse().map_err(|_| invalid())?;
+                    if limit < Amount::ZERO {
+                        return Err(invalid());
+                    }
+                    self.held_above = Some(limit);
+                }
+                "cooling-off" => {
+                    self.cooling_off = match value.parse().map_err(|_| invalid())? {
+                        0 => None,
+                        seconds => Some(seconds),
+                    }
+                }
+                other => {
+                    return Err(anyhow::anyhow!(
+                    "Unknown freeze rule {other}, expected one of: chargebacks, held, cooling-off"
+                ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The rule of a [`FreezePolicy`] that froze an account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FreezeRule {
+    Chargebacks(u32),
+    HeldAbove(Amount),
+}
+
+impl std::fmt::Display for FreezeRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FreezeRule::Chargebacks(count) => write!(f, "chargebacks>={count}"),
+            FreezeRule::HeldAbove(limit) => write!(f, "held>{limit}"),
+        }
+    }
+}
+
+/// The order in which [`Ledger::query`] returns accounts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AccountSort {
+    /// Ascending by client id.
+    #[default]
+    Client,
+    /// Descending by available funds.
+    Available,
+    /// Descending by held funds.
+    Held,
+    /// Descending by total funds.
+    Total,
+}
+
+/// Filtering, sorting and pagination options for [`Ledger::query`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AccountFilter {
+    /// Only return accounts with this locked status.
+    pub locked: Option<bool>,
+    /// Only return accounts with at least this total balance.
+    pub min_balance: Option<Amount>,
+    pub sort: AccountSort,
+    /// Number of matching accounts to skip.
+    pub offset: usize,
+    /// Maximum number of accounts to return.
+    pub limit: Option<usize>,
+}
+
+/// Everything known about a single transaction, see [`Ledger::get_transaction`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransactionInfo {
+    pub client: u16,
+    pub kind: TransactionKind,
+    pub amount: Amount,
+    pub state: TransactionState,
+}
+
+/// What applying a record did to the ledger, see [`Ledger::apply`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Applied {
+    Deposited(Amount),
+    Withdrawn(Amount),
+    /// Funds were held because of a dispute.
+    Held(Amount),
+    /// Held funds were released because a dispute was resolved.
+    Released(Amount),
+    /// Held funds were removed, which may have frozen the account.
+    ChargedBack(Amount),
+    /// The account was unfrozen by an admin.
+    Unlocked,
+    /// An admin added the positive or negative amount to the account.
+    Adjusted(Amount),
+    /// An admin wrote off the negative total of the account, adding the
+    /// amount.
+    WrittenOff(Amount),
+    /// Available funds were moved into an escrow bucket.
+    EscrowHeld(Amount),
+    /// Funds were moved from an escrow bucket back to the available funds.
+    EscrowReleased(Amount),
+}
+
+/// Result of [`Ledger::simulate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    /// What applying the record would do, or why it would be rejected.
+    pub outcome: Result<Applied, LedgerError>,
+    /// The account of the record's client as it would be afterwards.
+    pub account: structs::ClientRecord,
+}
+
+/// Running totals of all funds that moved into or out of the ledger.
+#[derive(Debug, Default, Clone)]
+struct Totals {
+    opening: Amount,
+    deposits: Amount,
+    withdrawals: Amount,
+    charged_back: Amount,
+    adjustments: Amount,
+    /// Negative balances written off as unrecoverable.
+    losses: Amount,
+}
 
 pub struct Ledger {
-    customer_map: HashMap<u16, Customer>,
+    customer_map: ClientMap<Customer>,
+    retention: RetentionPolicy,
+    freeze: FreezePolicy,
+    empty_accounts: EmptyAccountPolicy,
+    omit_zero_accounts: bool,
+    groups: AccountGroups,
+    counterparties: Counterparties,
+    tags: Tags,
+    /// Adjustments and write-offs applied with [`Ledger::apply`].
+    adjustments: Vec<structs::AdjustmentRecord>,
+    /// Tx ids of the applied adjustments and write-offs, so a signed admin
+    /// record cannot be replayed.
+    admin_txs: BTreeSet<u32>,
+    journal: Option<Journal>,
+    totals: Totals,
+    /// Whether a restored snapshot lacked part of the history.
+    truncated: bool,
+    /// Clients touched since the last delta snapshot.
+    modified: ClientSet,
 }
 
 impl Ledger {
     pub fn new() -> Self {
         Self {
-            customer_map: HashMap::new(),
+            customer_map: ClientMap::default(),
+            retention: RetentionPolicy::default(),
+            freeze: FreezePolicy::default(),
+            empty_accounts: EmptyAccountPolicy::default(),
+            omit_zero_accounts: false,
+            groups: AccountGroups::default(),
+            counterparties: Counterparties::default(),
+            tags: Tags::default(),
+            adjustments: Vec::new(),
+            admin_txs: BTreeSet::new(),
+            journal: None,
+            totals: Totals::default(),
+            truncated: false,
+            modified: ClientSet::default(),
         }
     }
 
-    pub fn get_or_insert_customer(&mut self, client_id: u16) -> &mut Customer {
-        self.customer_map.entry(client_id).or_default()
+    /// Preallocates room for the given number of clients, so the maps of
+    /// the ledger do not grow while the records are applied.
+    pub fn with_capacity(mut self, clients: usize) -> Self {
+        self.customer_map.reserve(clients);
+        self.modified.reserve(clients);
+        self
+    }
+
+    /// Sets the retention policy used for customers created from now on.
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Sets the freeze policy used for customers created from now on.
+    pub fn with_freeze_policy(mut self, freeze: FreezePolicy) -> Self {
+        self.freeze = freeze;
+        self
+    }
+
+    /// Sets what happens to accounts that were never funded.
+    pub fn with_empty_accounts(mut self, empty_accounts: EmptyAccountPolicy) -> Self {
+        self.empty_accounts = empty_accounts;
+        self
+    }
+
+    /// Leaves accounts without a total or held balance that are not locked
+    /// out of the account listings.
+    pub fn with_omit_zero_accounts(mut self, omit_zero_accounts: bool) -> Self {
+        self.omit_zero_accounts = omit_zero_accounts;
+        self
+    }
+
+    /// Pools the available funds of grouped clients for withdrawals.
+    pub fn with_groups(mut self, groups: AccountGroups) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    /// Records every balance movement as double-entry postings.
+    pub fn with_journal(mut self) -> Self {
+        self.journal = Some(Journal::default());
+        self
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
     }
 
-    pub fn client_records(&self) -> Vec<structs::ClientRecord> {
+    pub fn get_or_insert_customer(&mut self, client_id: u16) -> &mut Customer {
+        let retention = self.retention;
+        let freeze = self.freeze;
+        self.modified.insert(client_id);
         self.customer_map
+            .entry(client_id)
+            .or_insert_with(|| Customer {
+                retention,
+                freeze,
+                ..Default::default()
+            })
+    }
+
+    /// Seeds an account with a closing balance of a previous run.
+    ///
+    /// Held funds carried over this way can only be resolved or charged back
+    /// if the disputed transactions are restored with [`Ledger::open_transaction`].
+    /// Escrowed funds are restored into the [`OPENING_ESCROW`] bucket, as the
+    /// account output does not name the buckets.
+    pub fn open_account(&mut self, record: &structs::ClientRecord) -> Result<(), LedgerError> {
+        if self.customer_map.contains_key(&record.client) {
+            return Err(LedgerError::OpeningBalanceExists(record.client));
+        }
+        if record.held < Amount::ZERO
+            || record.escrowed < Amount::ZERO
+            || record
+                .available
+                .checked_add(record.held)?
+                .checked_add(record.escrowed)?
+                != record.total
+        {
+            return Err(LedgerError::InconsistentOpeningBalance(record.client));
+        }
+
+        let (total, held) = (record.total, record.held);
+        let opening = self.totals.opening.checked_add(total)?;
+
+        let customer = self.get_or_insert_customer(record.client);
+        customer.total_balance = total;
+        customer.held_balance = held;
+        customer.is_locked = record.locked;
+        customer.funded = true;
+        if record.escrowed != Amount::ZERO {
+            customer
+                .escrowed
+                .insert(OPENING_ESCROW.to_string(), record.escrowed);
+        }
+        self.totals.opening = opening;
+
+        self.post(
+            0,
+            PostingAccount::Opening,
+            PostingAccount::Available(record.client),
+            record.available,
+        )?;
+        if record.held != Amount::ZERO {
+            self.post(
+                0,
+                PostingAccount::Opening,
+                PostingAccount::Held(record.client),
+                record.held,
+            )?;
+        }
+        if record.escrowed != Amount::ZERO {
+            self.post(
+                0,
+                PostingAccount::Opening,
+                PostingAccount::Escrow(record.client),
+                record.escrowed,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Captures the balances, retained transactions and totals of the ledger.
+    /// The journal is not part of a snapshot.
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot_of(|_| true, false)
+    }
+
+    /// Captures only the accounts touched since the previous delta snapshot,
+    /// or since the ledger was created or restored. Deltas can be merged into
+    /// a full snapshot with [`Snapshot::merge`].
+    pub fn delta_snapshot(&mut self) -> Snapshot {
+        let snapshot = self.snapshot_of(|client| self.modified.contains(&client), true);
+        self.modified.clear();
+        snapshot
+    }
+
+    fn snapshot_of(&self, include: impl Fn(u16) -> bool, delta: bool) -> Snapshot {
+        let mut accounts: Vec<_> = self
+            .customer_map
             .iter()
-            .map(|(&client, customer)| structs::ClientRecord {
+            .filter(|(&client, _)| include(client))
+            .map(|(&client, customer)| AccountSnapshot {
                 client,
-                // This is mostly for clipping of anything past four points of the decimal point
-                available: ((customer.total_balance - customer.held_balance) * 10000.).round()
-                    / 10000.,
-                held: (customer.held_balance * 10000.).round() / 10000.,
-                total: (customer.total_balance * 10000.).round() / 10000.,
+                total: customer.total_balance,
+                held: customer.held_balance,
                 locked: customer.is_locked,
+                escrow: customer
+                    .escrowed
+                    .iter()
+                    .map(|(escrow, &amount)| (escrow.clone(), amount))
+                    .collect(),
+                transactions: customer
+                    .records
+                    .iter()
+                    .map(|(tx, kind, amount, state)| TransactionSnapshot {
+                        tx,
+                        kind,
+                        amount,
+                        state,
+                    })
+                    .collect(),
+                forgotten: customer.records.forgotten().to_vec(),
+            })
+            .collect();
+        accounts.sort_by_key(|account| account.client);
+
+        Snapshot {
+            opening: self.totals.opening,
+            deposits: self.totals.deposits,
+            withdrawals: self.totals.withdrawals,
+            charged_back: self.totals.charged_back,
+            adjustments: self.totals.adjustments,
+            losses: self.totals.losses,
+            delta,
+            truncated: self.truncated
+                || self
+                    .customer_map
+                    .values()
+                    .any(|customer| customer.retention != RetentionPolicy::All),
+            accounts,
+            admin_txs: self.admin_txs.iter().copied().collect(),
+        }
+    }
+
+    /// Continues from a snapshot of a previous run. Restored balances are
+    /// posted from the opening account, like opening balances.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), LedgerError> {
+        let totals = Totals {
+            opening: self.totals.opening.checked_add(snapshot.opening)?,
+            deposits: self.totals.deposits.checked_add(snapshot.deposits)?,
+            withdrawals: self.totals.withdrawals.checked_add(snapshot.withdrawals)?,
+            charged_back: self
+                .totals
+                .charged_back
+                .checked_add(snapshot.charged_back)?,
+            adjustments: self.totals.adjustments.checked_add(snapshot.adjustments)?,
+            losses: self.totals.losses.checked_add(snapshot.losses)?,
+        };
+
+        for account in &snapshot.accounts {
+            if self.customer_map.contains_key(&account.client) {
+                return Err(LedgerError::OpeningBalanceExists(account.client));
+            }
+            let customer = self.get_or_insert_customer(account.client);
+            customer.total_balance = account.total;
+            customer.held_balance = account.held;
+            customer.is_locked = account.locked;
+            customer.escrowed = account.escrow.iter().cloned().collect();
+            customer.funded = true;
+            for transaction in &account.transactions {
+                customer
+                    .records
+                    .insert(transaction.tx, transaction.kind, transaction.amount);
+                customer
+                    .records
+                    .set_state(transaction.tx, transaction.state);
+                if matches!(customer.retention, RetentionPolicy::DisputeWindow(_))
+                    && transaction.kind == TransactionKind::Deposit
+                {
+                    customer.records.enqueue(transaction.tx);
+                }
+            }
+            for &tx in &account.forgotten {
+                customer.records.forget(tx);
+            }
+
+            let available = customer.available()?;
+            let escrowed = customer.escrowed()?;
+            self.post(
+                0,
+                PostingAccount::Opening,
+                PostingAccount::Available(account.client),
+                available,
+            )?;
+            if account.held != Amount::ZERO {
+                self.post(
+                    0,
+                    PostingAccount::Opening,
+                    PostingAccount::Held(account.client),
+                    account.held,
+                )?;
+            }
+            if escrowed != Amount::ZERO {
+                self.post(
+                    0,
+                    PostingAccount::Opening,
+                    PostingAccount::Escrow(account.client),
+                    escrowed,
+                )?;
+            }
+            self.modified.remove(&account.client);
+        }
+
+        self.totals = totals;
+        self.truncated |= snapshot.truncated;
+        self.admin_txs.extend(&snapshot.admin_txs);
+        Ok(())
+    }
+
+    /// Restores a transaction of a previous run, so that disputes in this run
+    /// can reference it. Balances are not affected.
+    pub fn open_transaction(&mut self, record: &structs::Record) -> Result<(), LedgerError> {
+        self.get_or_insert_customer(record.client).restore(record)
+    }
+
+    /// Applies a single record to the ledger.
+    ///
+    /// Admin operations are never authorized this way, whether signed or not:
+    /// unlocks and write-offs fail with [`LedgerError::Unauthorized`] and
+    /// adjustments get the checks of customer transactions. Verify them with
+    /// an [`AdminKey`](crate::admin::AdminKey) and use
+    /// [`Ledger::apply_verified`] instead.
+    pub fn apply(&mut self, record: &structs::Record) -> Result<Applied, LedgerError> {
+        self.apply_as(record, false)
+    }
+
+    /// Applies a record with a verified admin signature, granting admin
+    /// operations their powers.
+    pub fn apply_verified(&mut self, verified: Verified) -> Result<Applied, LedgerError> {
+        self.apply_as(verified.record(), true)
+    }
+
+    fn apply_as(&mut self, record: &structs::Record, admin: bool) -> Result<Applied, LedgerError> {
+        let applied = self.apply_record(record, admin);
+        if applied.is_err() && self.empty_accounts == EmptyAccountPolicy::Remove {
+            self.remove_empty_account(record.client);
+        }
+        applied
+    }
+
+    fn apply_record(
+        &mut self,
+        record: &structs::Record,
+        admin: bool,
+    ) -> Result<Applied, LedgerError> {
+        if let Some(timestamp) = record.timestamp {
+            self.get_or_insert_customer(record.client)
+                .advance_time(timestamp);
+        }
+        let applied = match record.record_type {
+            structs::RecordType::Deposit => {
+                let amount = record.amount.ok_or(LedgerError::MissingAmount)?;
+                self.deposit(record.client, record.tx, amount)
+            }
+            structs::RecordType::Withdrawal => {
+                let amount = record.amount.ok_or(LedgerError::MissingAmount)?;
+                self.withdraw(record.client, record.tx, amount)
+            }
+            structs::RecordType::Dispute => self.dispute(record.client, record.tx),
+            structs::RecordType::Resolve => self.resolve(record.client, record.tx),
+            structs::RecordType::Chargeback => self.chargeback(record.client, record.tx),
+            structs::RecordType::Unlock if admin => Ok(self.unlock(record.client)),
+            structs::RecordType::Adjustment => {
+                let amount = record.amount.ok_or(LedgerError::MissingAmount)?;
+                if admin {
+                    self.adjust(record.client, record.tx, amount)
+                } else {
+                    self.adjust_checked(record.client, record.tx, amount)
+                }
+            }
+            structs::RecordType::WriteOff if admin => self.write_off(record.client, record.tx),
+            structs::RecordType::Unlock | structs::RecordType::WriteOff => {
+                Err(LedgerError::Unauthorized)
+            }
+            structs::RecordType::EscrowHold | structs::RecordType::EscrowRelease => {
+                let amount = record.amount.ok_or(LedgerError::MissingAmount)?;
+                let escrow = record.escrow.as_deref().unwrap_or_default();
+                if record.record_type == structs::RecordType::EscrowHold {
+                    self.escrow_hold(record.client, record.tx, escrow, amount)
+                } else {
+                    self.escrow_release(record.client, record.tx, escrow, amount)
+                }
+            }
+        }?;
+        self.counterparties.record(record, applied)?;
+        self.tags.record(record, applied)?;
+        if let Applied::Adjusted(amount) | Applied::WrittenOff(amount) = applied {
+            self.adjustments.push(structs::AdjustmentRecord {
+                client: record.client,
+                tx: record.tx,
+                record_type: record.record_type,
+                amount,
+                reason_code: record.reason_code.clone(),
+                admin,
+            });
+        }
+        Ok(applied)
+    }
+
+    /// The adjustments and write-offs applied with [`Ledger::apply`], in
+    /// order.
+    pub fn adjustments(&self) -> &[structs::AdjustmentRecord] {
+        &self.adjustments
+    }
+
+    /// Exposure per counterparty of the records applied with [`Ledger::apply`].
+    pub fn counterparties(&self) -> &Counterparties {
+        &self.counterparties
+    }
+
+    /// Totals per client and tag of the records applied with [`Ledger::apply`].
+    pub fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    /// Reports what applying the record would do without changing the ledger.
+    ///
+    /// Only the record's client and the other members of its group are
+    /// copied along with the running totals and the applied admin tx ids, so
+    /// this is cheap regardless of the number of accounts. The journal is
+    /// simulated empty, as postings never depend on earlier ones.
+    pub fn simulate(&self, record: &structs::Record) -> Result<Simulation, LedgerError> {
+        self.simulate_as(record, false)
+    }
+
+    /// Like [`Ledger::simulate`], for a record with a verified admin
+    /// signature, see [`Ledger::apply_verified`].
+    pub fn simulate_verified(&self, verified: Verified) -> Result<Simulation, LedgerError> {
+        self.simulate_as(verified.record(), true)
+    }
+
+    fn simulate_as(
+        &self,
+        record: &structs::Record,
+        admin: bool,
+    ) -> Result<Simulation, LedgerError> {
+        let customer = self
+            .customer_map
+            .get(&record.client)
+            .cloned()
+            .unwrap_or_else(|| Customer {
+                retention: self.retention,
+                freeze: self.freeze,
+                ..Default::default()
+            });
+        let mut scratch = Ledger::new()
+            .with_retention(self.retention)
+            .with_freeze_policy(self.freeze)
+            .with_empty_accounts(self.empty_accounts)
+            .with_omit_zero_accounts(self.omit_zero_accounts);
+        scratch.admin_txs = self.admin_txs.clone();
+        scratch.totals = self.totals.clone();
+        scratch.journal = self.journal.as_ref().map(|_| Journal::default());
+        scratch.customer_map.insert(record.client, customer);
+        if let Some(members) = self.groups.members_of(record.client) {
+            for member in members.iter().filter(|&&member| member != record.client) {
+                if let Some(customer) = self.customer_map.get(member) {
+                    scratch.customer_map.insert(*member, customer.clone());
+                }
+            }
+            scratch.groups = self.groups.clone();
+        }
+
+        let outcome = scratch.apply_as(record, admin);
+        // The empty account policy may have removed the account again.
+        let account = match scratch.client_record(record.client)? {
+            Some(account) => account,
+            None => Customer::default().client_record(record.client)?,
+        };
+        Ok(Simulation { outcome, account })
+    }
+
+    pub fn deposit(
+        &mut self,
+        client: u16,
+        tx: u32,
+        amount: Amount,
+    ) -> Result<Applied, LedgerError> {
+        let deposits = self.totals.deposits.checked_add(amount)?;
+        self.get_or_insert_customer(client).deposit(tx, amount)?;
+        self.totals.deposits = deposits;
+        self.post(
+            tx,
+            PostingAccount::Clearing,
+            PostingAccount::Available(client),
+            amount,
+        )?;
+        Ok(Applied::Deposited(amount))
+    }
+
+    /// Withdraws funds of the client, or of its whole group if it is in one,
+    /// in which case its own available funds may become negative.
+    pub fn withdraw(
+        &mut self,
+        client: u16,
+        tx: u32,
+        amount: Amount,
+    ) -> Result<Applied, LedgerError> {
+        let pooled = match self.groups.members_of(client) {
+            Some(members) => Some(
+                members
+                    .iter()
+                    .filter_map(|member| self.customer_map.get(member))
+                    .try_fold(Amount::ZERO, |pooled, customer| {
+                        pooled.checked_add(customer.available()?)
+                    })?,
+            ),
+            None => None,
+        };
+        let withdrawals = self.totals.withdrawals.checked_add(amount)?;
+        let customer = self.get_or_insert_customer(client);
+        match pooled {
+            Some(available) => customer.withdraw_from(tx, amount, available)?,
+            None => customer.withdraw(tx, amount)?,
+        }
+        self.totals.withdrawals = withdrawals;
+        self.post(
+            tx,
+            PostingAccount::Available(client),
+            PostingAccount::Clearing,
+            amount,
+        )?;
+        Ok(Applied::Withdrawn(amount))
+    }
+
+    pub fn dispute(&mut self, client: u16, tx: u32) -> Result<Applied, LedgerError> {
+        let customer = self.get_or_insert_customer(client);
+        customer.dispute(tx)?;
+        let amount = customer.get_transaction_amount(tx)?;
+        self.post(
+            tx,
+            PostingAccount::Available(client),
+            PostingAccount::Held(client),
+            amount,
+        )?;
+        Ok(Applied::Held(amount))
+    }
+
+    pub fn resolve(&mut self, client: u16, tx: u32) -> Result<Applied, LedgerError> {
+        let customer = self.get_or_insert_customer(client);
+        customer.resolve(tx)?;
+        let amount = customer.get_transaction_amount(tx)?;
+        self.post(
+            tx,
+            PostingAccount::Held(client),
+            PostingAccount::Available(client),
+            amount,
+        )?;
+        Ok(Applied::Released(amount))
+    }
+
+    pub fn chargeback(&mut self, client: u16, tx: u32) -> Result<Applied, LedgerError> {
+        let amount = self
+            .get_or_insert_customer(client)
+            .get_transaction_amount(tx)?;
+        let charged_back = self.totals.charged_back.checked_add(amount)?;
+        self.get_or_insert_customer(client).chargeback(tx)?;
+        self.totals.charged_back = charged_back;
+        self.post(
+            tx,
+            PostingAccount::Held(client),
+            PostingAccount::Clearing,
+            amount,
+        )?;
+        Ok(Applied::ChargedBack(amount))
+    }
+
+    /// Unfreezes the account, regardless of the rule that froze it.
+    pub fn unlock(&mut self, client: u16) -> Applied {
+        self.get_or_insert_customer(client).unlock();
+        Applied::Unlocked
+    }
+
+    /// Adds a positive or negative amount to the available funds of the
+    /// client, even if the account is locked. Fails if an adjustment or
+    /// write-off with the same tx id was already applied.
+    pub fn adjust(&mut self, client: u16, tx: u32, amount: Amount) -> Result<Applied, LedgerError> {
+        if self.admin_txs.contains(&tx) {
+            return Err(LedgerError::DuplicateTransaction);
+        }
+        let adjustments = self.totals.adjustments.checked_add(amount)?;
+        let customer = self.get_or_insert_customer(client);
+        customer.total_balance = customer.total_balance.checked_add(amount)?;
+        customer.funded = true;
+        self.totals.adjustments = adjustments;
+        self.admin_txs.insert(tx);
+        if amount < Amount::ZERO {
+            self.post(
+                tx,
+                PostingAccount::Available(client),
+                PostingAccount::Adjustments,
+                amount.checked_neg()?,
+            )?;
+        } else {
+            self.post(
+                tx,
+                PostingAccount::Adjustments,
+                PostingAccount::Available(client),
+                amount,
+            )?;
+        }
+        Ok(Applied::Adjusted(amount))
+    }
+
+    /// Like [`Ledger::adjust`], but with the checks of customer transactions:
+    /// fails on locked accounts and if a negative amount exceeds the
+    /// available funds. Positive amounts always fail, so a customer feed
+    /// cannot create funds.
+    pub fn adjust_checked(
+        &mut self,
+        client: u16,
+        tx: u32,
+        amount: Amount,
+    ) -> Result<Applied, LedgerError> {
+        if amount > Amount::ZERO {
+            return Err(LedgerError::UnsignedCredit);
+        }
+        let customer = self.get_or_insert_customer(client);
+        customer.validate_account_not_locked()?;
+        if amount.checked_neg()? > customer.available()? {
+            return Err(LedgerError::InsufficientFunds);
+        }
+        self.adjust(client, tx, amount)
+    }
+
+    /// Brings a negative total of the client, e.g. after chargebacks of
+    /// withdrawn funds, back to zero and books the amount as a loss. Like
+    /// adjustments, every tx id is written off at most once. Fails while
+    /// funds are held by a dispute or in escrow, as writing off the total
+    /// would leave the available funds negative.
+    pub fn write_off(&mut self, client: u16, tx: u32) -> Result<Applied, LedgerError> {
+        if self.admin_txs.contains(&tx) {
+            return Err(LedgerError::DuplicateTransaction);
+        }
+        let customer = self.get_or_insert_customer(client);
+        let total = customer.total_balance;
+        if total >= Amount::ZERO {
+            return Err(LedgerError::NothingToWriteOff);
+        }
+        if customer.held_balance != Amount::ZERO || customer.escrowed()? != Amount::ZERO {
+            return Err(LedgerError::FundsOnHold);
+        }
+        let amount = Amount::ZERO.checked_sub(total)?;
+        self.totals.losses = self.totals.losses.checked_add(amount)?;
+        self.get_or_insert_customer(client).total_balance = Amount::ZERO;
+        self.admin_txs.insert(tx);
+        self.post(
+            tx,
+            PostingAccount::Losses,
+            PostingAccount::Available(client),
+            amount,
+        )?;
+        Ok(Applied::WrittenOff(amount))
+    }
+
+    /// Moves available funds of the client into the named escrow bucket,
+    /// e.g. until a marketplace order is fulfilled.
+    pub fn escrow_hold(
+        &mut self,
+        client: u16,
+        tx: u32,
+        escrow: &str,
+        amount: Amount,
+    ) -> Result<Applied, LedgerError> {
+        self.get_or_insert_customer(client)
+            .escrow_hold(tx, escrow, amount)?;
+        self.post(
+            tx,
+            PostingAccount::Available(client),
+            PostingAccount::Escrow(client),
+            amount,
+        )?;
+        Ok(Applied::EscrowHeld(amount))
+    }
+
+    /// Moves funds from the named escrow bucket back to the available funds
+    /// of the client.
+    pub fn escrow_release(
+        &mut self,
+        client: u16,
+        tx: u32,
+        escrow: &str,
+        amount: Amount,
+    ) -> Result<Applied, LedgerError> {
+        self.get_or_insert_customer(client)
+            .escrow_release(tx, escrow, amount)?;
+        self.post(
+            tx,
+            PostingAccount::Escrow(client),
+            PostingAccount::Available(client),
+            amount,
+        )?;
+        Ok(Applied::EscrowReleased(amount))
+    }
+
+    /// Checks that the journal, if enabled, is balanced and agrees with
+    /// the balances of every customer.
+    pub fn verify_journal(&self) -> Result<(), LedgerError> {
+        let Some(journal) = &self.journal else {
+            return Ok(());
+        };
+        if !journal.is_balanced() {
+            return Err(LedgerError::UnbalancedJournal);
+        }
+
+        let balances = journal.balances()?;
+        for (&client, customer) in &self.customer_map {
+            let held = balances
+                .get(&PostingAccount::Held(client))
+                .copied()
+                .unwrap_or_default();
+            let available = balances
+                .get(&PostingAccount::Available(client))
+                .copied()
+                .unwrap_or_default();
+            let escrowed = balances
+                .get(&PostingAccount::Escrow(client))
+                .copied()
+                .unwrap_or_default();
+            if held != customer.held_balance
+                || escrowed != customer.escrowed()?
+                || available.checked_add(held)?.checked_add(escrowed)? != customer.total_balance
+            {
+                return Err(LedgerError::JournalMismatch(client));
+            }
+        }
+        Ok(())
+    }
+
+    fn post(
+        &mut self,
+        tx: u32,
+        from: PostingAccount,
+        to: PostingAccount,
+        amount: Amount,
+    ) -> Result<(), LedgerError> {
+        match &mut self.journal {
+            Some(journal) => journal.transfer(tx, from, to, amount),
+            None => Ok(()),
+        }
+    }
+
+    fn remove_empty_account(&mut self, client: u16) {
+        if self
+            .customer_map
+            .get(&client)
+            .is_some_and(Customer::is_empty)
+        {
+            self.customer_map.remove(&client);
+            self.modified.remove(&client);
+        }
+    }
+
+    /// Whether the account shows up in the account listings under the empty
+    /// account policy and the zero account suppression.
+    fn is_listed(&self, customer: &Customer) -> bool {
+        (self.empty_accounts == EmptyAccountPolicy::Keep || !customer.is_empty())
+            && !(self.omit_zero_accounts && customer.is_zero())
+    }
+
+    pub fn client_records(&self) -> Result<Vec<structs::ClientRecord>, LedgerError> {
+        self.customer_map
+            .iter()
+            .filter(|(_, customer)| self.is_listed(customer))
+            .map(|(&client, customer)| customer.client_record(client))
+            .collect()
+    }
+
+    /// The accounts with their groups, followed by one row per group with the
+    /// sums of its members, ordered by group name.
+    pub fn grouped_records(&self) -> Result<Vec<structs::GroupedClientRecord>, LedgerError> {
+        let mut records: Vec<_> = self
+            .client_records()?
+            .into_iter()
+            .map(|record| structs::GroupedClientRecord {
+                client: Some(record.client),
+                group: self.groups.group_of(record.client).map(str::to_string),
+                available: record.available,
+                held: record.held,
+                total: record.total,
+                locked: record.locked,
+            })
+            .collect();
+
+        let mut groups: Vec<_> = self.groups.iter().collect();
+        groups.sort_unstable_by_key(|(group, _)| *group);
+        for (group, members) in groups {
+            let (mut available, mut held, mut total, mut locked) =
+                (Amount::ZERO, Amount::ZERO, Amount::ZERO, false);
+            for customer in members
+                .iter()
+                .filter_map(|member| self.customer_map.get(member))
+            {
+                available = available.checked_add(customer.available()?)?;
+                held = held.checked_add(customer.held_balance)?;
+                total = total.checked_add(customer.total_balance)?;
+                locked |= customer.is_locked;
+            }
+            records.push(structs::GroupedClientRecord {
+                client: None,
+                group: Some(group.to_string()),
+                available,
+                held,
+                total,
+                locked,
+            });
+        }
+        Ok(records)
+    }
+
+    pub fn client_record(&self, client: u16) -> Result<Option<structs::ClientRecord>, LedgerError> {
+        self.customer_map
+            .get(&client)
+            .map(|customer| customer.client_record(client))
+            .transpose()
+    }
+
+    /// Removes the account of the client with its transaction history,
+    /// returning its final balances. The running totals of the ledger keep
+    /// the funds that moved. Accounts left out of the listings by the empty
+    /// account policy are removed without returning them.
+    pub fn take_client_record(
+        &mut self,
+        client: u16,
+    ) -> Result<Option<structs::ClientRecord>, LedgerError> {
+        let Some(record) = self.client_record(client)? else {
+            return Ok(None);
+        };
+        let customer = self.customer_map.remove(&client);
+        self.modified.remove(&client);
+        Ok(customer
+            .filter(|customer| self.is_listed(customer))
+            .map(|_| record))
+    }
+
+    /// Like [`Ledger::client_records`], including chargeback counts and the
+    /// freeze rules that locked accounts.
+    pub fn extended_records(&self) -> Result<Vec<structs::ExtendedClientRecord>, LedgerError> {
+        self.customer_map
+            .iter()
+            .filter(|(_, customer)| self.is_listed(customer))
+            .map(|(&client, customer)| {
+                Ok(structs::ExtendedClientRecord {
+                    client,
+                    available: customer.available()?,
+                    held: customer.held_balance,
+                    total: customer.total_balance,
+                    locked: customer.is_locked,
+                    chargebacks: customer.chargebacks,
+                    frozen_by: customer.frozen_by.map(|rule| rule.to_string()),
+                    frozen_until: customer.frozen_until,
+                    escrowed: customer.escrowed()?,
+                })
             })
             .collect()
     }
+
+    pub fn is_locked(&self, client: u16) -> bool {
+        self.customer_map
+            .get(&client)
+            .is_some_and(|customer| customer.is_locked)
+    }
+
+    /// Summarizes the funds that moved through the ledger across all clients.
+    pub fn trial_balance(&self) -> Result<structs::TrialBalance, LedgerError> {
+        let (mut held, mut net) = (Amount::ZERO, Amount::ZERO);
+        for customer in self.customer_map.values() {
+            held = held.checked_add(customer.held_balance)?;
+            net = net.checked_add(customer.total_balance)?;
+        }
+
+        Ok(structs::TrialBalance {
+            opening: self.totals.opening,
+            deposits: self.totals.deposits,
+            withdrawals: self.totals.withdrawals,
+            charged_back: self.totals.charged_back,
+            adjustments: self.totals.adjustments,
+            written_off: self.totals.losses,
+            held,
+            net,
+        })
+    }
+
+    /// The `top` accounts by total and by held funds, with the share of the
+    /// funds of all accounts they hold.
+    pub fn concentration(
+        &self,
+        top: usize,
+    ) -> Result<Vec<structs::ConcentrationRecord>, LedgerError> {
+        let trial_balance = self.trial_balance()?;
+        let mut report = Vec::new();
+        for (by, sort, all) in [
+            ("total", AccountSort::Total, trial_balance.net),
+            ("held", AccountSort::Held, trial_balance.held),
+        ] {
+            let accounts = self.query(AccountFilter {
+                sort,
+                limit: Some(top),
+                ..Default::default()
+            })?;
+            let mut cumulative = 0.;
+            for (index, account) in accounts.enumerate() {
+                let amount = if sort == AccountSort::Held {
+                    account.held
+                } else {
+                    account.total
+                };
+                let share = if all == Amount::ZERO {
+                    0.
+                } else {
+                    (amount.units() as f64 / all.units() as f64) as f32
+                };
+                cumulative += share;
+                report.push(structs::ConcentrationRecord {
+                    by,
+                    rank: index + 1,
+                    client: account.client,
+                    amount,
+                    share: round(share),
+                    cumulative_share: round(cumulative),
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// Looks up a retained transaction by its id across all customers.
+    pub fn get_transaction(&self, tx: u32) -> Option<TransactionInfo> {
+        self.customer_map.iter().find_map(|(&client, customer)| {
+            Some(TransactionInfo {
+                client,
+                kind: customer.records.kind(tx)?,
+                amount: customer.records.amount(tx)?,
+                state: customer.records.state(tx)?,
+            })
+        })
+    }
+
+    /// Returns the accounts matching the filter, sorted and paginated.
+    pub fn query(
+        &self,
+        filter: AccountFilter,
+    ) -> Result<impl Iterator<Item = structs::ClientRecord>, LedgerError> {
+        let mut records: Vec<_> = self
+            .client_records()?
+            .into_iter()
+            .filter(|record| filter.locked.is_none_or(|locked| record.locked == locked))
+            .filter(|record| filter.min_balance.is_none_or(|min| record.total >= min))
+            .collect();
+
+        // Ties are ordered by client id.
+        records.sort_by_key(|record| record.client);
+        match filter.sort {
+            AccountSort::Client => {}
+            AccountSort::Available => records.sort_by_key(|record| Reverse(record.available)),
+            AccountSort::Held => records.sort_by_key(|record| Reverse(record.held)),
+            AccountSort::Total => records.sort_by_key(|record| Reverse(record.total)),
+        }
+
+        Ok(records
+            .into_iter()
+            .skip(filter.offset)
+            .take(filter.limit.unwrap_or(usize::MAX)))
+    }
 }
 
-#[derive(Debug, Default)]
-pub struct Customer {
-    total_balance: f32,
-    held_balance: f32,
-    is_locked: bool,
+/// This is mostly for clipping of anything past four points of the decimal point
+pub(crate) fn round(amount: f32) -> f32 {
+    (amount * 10000.).round() / 10000.
+}
 
-    /// Records is a map of transactions.
-    /// A positive amount indicates a deposit,
-    /// while a 0 amount indicates a withdrawal.
-    records: HashMap<u32, f32>,
+impl Default for Ledger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    disputed_transactions: Vec<u32>,
+#[derive(Debug, Default, Clone)]
+pub struct Customer {
+    total_balance: Amount,
+    held_balance: Amount,
+    is_locked: bool,
+    chargebacks: u32,
+    /// The rule that froze the account, if it was frozen by a rule.
+    frozen_by: Option<FreezeRule>,
+    /// When a temporary freeze ends.
+    frozen_until: Option<u64>,
+    /// Timestamp of the latest transaction of the account.
+    last_timestamp: Option<u64>,
+    /// Funds in escrow buckets by name, part of the total but neither
+    /// available nor held.
+    escrowed: BTreeMap<String, Amount>,
+    /// Whether funds ever moved into or out of the account, as opposed to
+    /// accounts that only failed records created.
+    funded: bool,
+
+    /// Records is a store of transactions.
+    records: TransactionStore,
+    retention: RetentionPolicy,
+    freeze: FreezePolicy,
 }
 
 impl Customer {
-    pub fn deposit(&mut self, tx: u32, amount: f32) -> anyhow::Result<()> {
+    /// Whether the account was never funded and holds no transactions.
+    fn is_empty(&self) -> bool {
+        !self.funded && self.records.is_empty()
+    }
+
+    /// Whether the account has neither a total nor held balance and is not
+    /// locked, regardless of its history.
+    fn is_zero(&self) -> bool {
+        self.total_balance == Amount::ZERO && self.held_balance == Amount::ZERO && !self.is_locked
+    }
+
+    pub fn deposit(&mut self, tx: u32, amount: Amount) -> Result<(), LedgerError> {
         self.validate_amount_and_tx_id(amount, tx)?;
         self.validate_account_not_locked()?;
 
-        self.total_balance += amount;
-        self.records.insert(tx, amount);
+        self.total_balance = self.total_balance.checked_add(amount)?;
+        self.funded = true;
+        self.retain_transaction(tx, TransactionKind::Deposit, amount);
 
         Ok(())
     }
 
-    pub fn withdraw(&mut self, tx: u32, amount: f32) -> anyhow::Result<()> {
+    pub fn withdraw(&mut self, tx: u32, amount: Amount) -> Result<(), LedgerError> {
+        self.withdraw_from(tx, amount, self.available()?)
+    }
+
+    /// Withdraws funds, checking them against the given available funds,
+    /// e.g. those of a group, instead of the own ones.
+    fn withdraw_from(
+        &mut self,
+        tx: u32,
+        amount: Amount,
+        available: Amount,
+    ) -> Result<(), LedgerError> {
         self.validate_amount_and_tx_id(amount, tx)?;
         self.validate_account_not_locked()?;
-        self.validate_sufficient_funds(amount)?;
+        if amount > available {
+            return Err(LedgerError::InsufficientFunds);
+        }
 
-        self.total_balance -= amount;
-        // Inserting a zero here, so that transaction checks
-        // can still work. If this were to be a negative number
-        // a user could dispute a deposit and withdrawal at the same time
-        // and get to a positive balance potentially.
-        self.records.insert(tx, 0.);
+        self.total_balance = self.total_balance.checked_sub(amount)?;
+        self.funded = true;
+        self.retain_transaction(tx, TransactionKind::Withdrawal, amount);
 
         Ok(())
     }
 
-    pub fn dispute(&mut self, tx: u32) -> anyhow::Result<()> {
+    pub fn dispute(&mut self, tx: u32) -> Result<(), LedgerError> {
         self.validate_transaction_exists(tx)?;
         self.validate_transaction_not_disputed(tx)?;
 
         let amount = self.get_transaction_amount(tx)?;
-        self.held_balance += amount;
-        self.disputed_transactions.push(tx);
+        self.held_balance = self.held_balance.checked_add(amount)?;
+        self.records.set_state(tx, TransactionState::Disputed);
+        self.apply_freeze_policy(false);
 
         Ok(())
     }
 
-    pub fn resolve(&mut self, tx: u32) -> anyhow::Result<()> {
+    pub fn resolve(&mut self, tx: u32) -> Result<(), LedgerError> {
         self.validate_transaction_exists(tx)?;
         self.validate_transaction_disputed(tx)?;
 
         let amount = self.get_transaction_amount(tx)?;
-        self.held_balance -= amount;
-        self.remove_disputed_transaction(tx);
+        self.held_balance = self.held_balance.checked_sub(amount)?;
+        self.records.set_state(tx, TransactionState::Processed);
 
         Ok(())
     }
 
-    pub fn chargeback(&mut self, tx: u32) -> anyhow::Result<()> {
+    pub fn chargeback(&mut self, tx: u32) -> Result<(), LedgerError> {
         self.validate_transaction_exists(tx)?;
         self.validate_transaction_disputed(tx)?;
 
         let amount = self.get_transaction_amount(tx)?;
-        self.held_balance -= amount;
-        self.total_balance -= amount;
+        let held_balance = self.held_balance.checked_sub(amount)?;
+        self.total_balance = self.total_balance.checked_sub(amount)?;
+        self.held_balance = held_balance;
+        self.chargebacks += 1;
+        self.records.set_state(tx, TransactionState::ChargedBack);
+        self.apply_freeze_policy(true);
+
+        Ok(())
+    }
+
+    fn unlock(&mut self) {
+        self.is_locked = false;
+        self.frozen_by = None;
+        self.frozen_until = None;
+    }
+
+    /// Moves the clock of the account forward to the timestamp of the
+    /// transaction being processed, which ends an expired cooling-off period.
+    pub fn advance_time(&mut self, timestamp: u64) {
+        self.last_timestamp = self.last_timestamp.max(Some(timestamp));
+        if self.frozen_until.is_some_and(|until| timestamp >= until) {
+            self.is_locked = false;
+            self.frozen_by = None;
+            self.frozen_until = None;
+        }
+    }
+
+    /// Freezes the account if any rule of its freeze policy is met.
+    fn apply_freeze_policy(&mut self, charged_back: bool) {
+        if self.is_locked {
+            return;
+        }
+        let rule = match self.freeze {
+            FreezePolicy {
+                chargebacks: Some(count),
+                ..
+            } if charged_back && self.chargebacks >= count => FreezeRule::Chargebacks(count),
+            FreezePolicy {
+                held_above: Some(limit),
+                ..
+            } if self.held_balance > limit => FreezeRule::HeldAbove(limit),
+            _ => return,
+        };
         self.is_locked = true;
+        self.frozen_by = Some(rule);
+        self.frozen_until = self
+            .freeze
+            .cooling_off
+            .zip(self.last_timestamp)
+            .map(|(cooling_off, now)| now.saturating_add(cooling_off));
+    }
 
+    /// Remembers a transaction of a previous run without touching any balances.
+    /// Disputes, resolves and chargebacks only update the state of the
+    /// referenced transaction.
+    fn restore(&mut self, record: &structs::Record) -> Result<(), LedgerError> {
+        let amount = record.amount.unwrap_or_default();
+        match record.record_type {
+            structs::RecordType::Deposit => {
+                self.validate_amount_and_tx_id(amount, record.tx)?;
+                self.retain_transaction(record.tx, TransactionKind::Deposit, amount);
+            }
+            structs::RecordType::Withdrawal => {
+                self.validate_amount_and_tx_id(amount, record.tx)?;
+                self.retain_transaction(record.tx, TransactionKind::Withdrawal, amount);
+            }
+            structs::RecordType::Dispute => {
+                self.validate_transaction_exists(record.tx)?;
+                self.validate_transaction_not_disputed(record.tx)?;
+                self.records
+                    .set_state(record.tx, TransactionState::Disputed);
+            }
+            structs::RecordType::Resolve => {
+                self.validate_transaction_exists(record.tx)?;
+                self.validate_transaction_disputed(record.tx)?;
+                self.records
+                    .set_state(record.tx, TransactionState::Processed);
+            }
+            structs::RecordType::Chargeback => {
+                self.validate_transaction_exists(record.tx)?;
+                self.validate_transaction_disputed(record.tx)?;
+                self.records
+                    .set_state(record.tx, TransactionState::ChargedBack);
+            }
+            // Escrow operations leave only their tx ids behind, admin
+            // operations nothing at all.
+            structs::RecordType::EscrowHold | structs::RecordType::EscrowRelease => {
+                self.validate_amount_and_tx_id(amount, record.tx)?;
+                self.records.forget(record.tx);
+            }
+            structs::RecordType::Unlock
+            | structs::RecordType::Adjustment
+            | structs::RecordType::WriteOff => {}
+        }
         Ok(())
     }
 
-    fn validate_amount_and_tx_id(&self, amount: f32, tx: u32) -> anyhow::Result<()> {
-        if amount < 0. {
-            return Err(anyhow!("amount has to be positive"));
+    /// Stores a transaction as far as the retention policy allows it, and
+    /// otherwise only remembers its id to reject replays.
+    fn retain_transaction(&mut self, tx: u32, kind: TransactionKind, amount: Amount) {
+        match (self.retention, kind) {
+            (RetentionPolicy::All, _) => {
+                self.records.insert(tx, kind, amount);
+            }
+            (RetentionPolicy::DisputeWindow(window), TransactionKind::Deposit) => {
+                self.records.insert_latest(tx, kind, amount, window);
+            }
+            _ => self.records.forget(tx),
+        }
+    }
+
+    fn validate_amount_and_tx_id(&self, amount: Amount, tx: u32) -> Result<(), LedgerError> {
+        if amount < Amount::ZERO {
+            return Err(LedgerError::NegativeAmount);
         }
-        if self.records.contains_key(&tx) {
-            return Err(anyhow!(
-                "Customer already has a transaction with this tx id"
-            ));
+        if self.records.seen(tx) {
+            return Err(LedgerError::DuplicateTransaction);
         }
         Ok(())
     }
 
-    fn validate_account_not_locked(&self) -> anyhow::Result<()> {
+    fn validate_account_not_locked(&self) -> Result<(), LedgerError> {
         if self.is_locked {
-            return Err(anyhow!("This account is locked"));
+            return Err(LedgerError::AccountLocked);
         }
         Ok(())
     }
 
-    fn validate_sufficient_funds(&self, amount: f32) -> anyhow::Result<()> {
-        if amount > (self.total_balance - self.held_balance) {
-            return Err(anyhow!("Insufficient funds"));
+    fn available(&self) -> Result<Amount, LedgerError> {
+        (self.escrowed.values()).try_fold(
+            self.total_balance.checked_sub(self.held_balance)?,
+            |available, &amount| available.checked_sub(amount),
+        )
+    }
+
+    /// The funds in all escrow buckets.
+    fn escrowed(&self) -> Result<Amount, LedgerError> {
+        (self.escrowed.values()).try_fold(Amount::ZERO, |escrowed, &amount| {
+            escrowed.checked_add(amount)
+        })
+    }
+
+    fn client_record(&self, client: u16) -> Result<structs::ClientRecord, LedgerError> {
+        Ok(structs::ClientRecord {
+            client,
+            available: self.available()?,
+            held: self.held_balance,
+            total: self.total_balance,
+            locked: self.is_locked,
+            escrowed: self.escrowed()?,
+        })
+    }
+
+    fn escrow_hold(&mut self, tx: u32, escrow: &str, amount: Amount) -> Result<(), LedgerError> {
+        self.validate_amount_and_tx_id(amount, tx)?;
+        self.validate_account_not_locked()?;
+        if amount > self.available()? {
+            return Err(LedgerError::InsufficientFunds);
         }
+        let escrowed = self.escrowed.entry(escrow.to_string()).or_default();
+        *escrowed = escrowed.checked_add(amount)?;
+        self.records.forget(tx);
         Ok(())
     }
 
-    fn validate_transaction_exists(&self, tx: u32) -> anyhow::Result<()> {
-        if !self.records.contains_key(&tx) {
-            return Err(anyhow!(
-                "Customer does not has a transaction with this tx id"
-            ));
+    fn escrow_release(&mut self, tx: u32, escrow: &str, amount: Amount) -> Result<(), LedgerError> {
+        self.validate_amount_and_tx_id(amount, tx)?;
+        let escrowed = self
+            .escrowed
+            .get_mut(escrow)
+            .filter(|escrowed| amount <= **escrowed)
+            .ok_or(LedgerError::InsufficientEscrow)?;
+        *escrowed = escrowed.checked_sub(amount)?;
+        if *escrowed == Amount::ZERO {
+            self.escrowed.remove(escrow);
         }
+        self.records.forget(tx);
         Ok(())
     }
 
-    fn validate_transaction_not_disputed(&self, tx: u32) -> anyhow::Result<()> {
-        if self.disputed_transactions.contains(&tx) {
-            return Err(anyhow!("Transaction is already disputed"));
+    fn validate_transaction_exists(&self, tx: u32) -> Result<(), LedgerError> {
+        if !self.records.contains(tx) {
+            return Err(LedgerError::UnknownTransaction);
         }
         Ok(())
     }
 
-    fn validate_transaction_disputed(&self, tx: u32) -> anyhow::Result<()> {
-        if !self.disputed_transactions.contains(&tx) {
-            return Err(anyhow!("Transaction is not disputed"));
+    fn validate_transaction_not_disputed(&self, tx: u32) -> Result<(), LedgerError> {
+        match self.records.state(tx) {
+            Some(TransactionState::Disputed) => {
+                return Err(LedgerError::AlreadyDisputed);
+            }
+            Some(TransactionState::ChargedBack) => {
+                return Err(LedgerError::AlreadyChargedBack);
+            }
+            _ => {}
         }
         Ok(())
     }
 
-    fn get_transaction_amount(&self, tx: u32) -> anyhow::Result<f32> {
-        match self.records.get(&tx) {
-            Some(amount) => Ok(*amount),
-            None => Err(anyhow!("No transaction record found for the given id")),
+    fn validate_transaction_disputed(&self, tx: u32) -> Result<(), LedgerError> {
+        if self.records.state(tx) != Some(TransactionState::Disputed) {
+            return Err(LedgerError::NotDisputed);
         }
+        Ok(())
     }
 
-    fn remove_disputed_transaction(&mut self, tx: u32) {
-        if let Some(index) = self.disputed_transactions.iter().position(|a| a == &tx) {
-            self.disputed_transactions.swap_remove(index);
+    /// Returns the amount that is held while the transaction is disputed.
+    fn get_transaction_amount(&self, tx: u32) -> Result<Amount, LedgerError> {
+        match (self.records.kind(tx), self.records.amount(tx)) {
+            (Some(TransactionKind::Deposit), Some(amount)) => Ok(amount),
+            // Withdrawals hold nothing. If this were to be a negative amount
+            // a user could dispute a deposit and withdrawal at the same time
+            // and get to a positive balance potentially.
+            (Some(TransactionKind::Withdrawal), Some(_)) => Ok(Amount::ZERO),
+            _ => Err(LedgerError::UnknownTransaction),
         }
     }
 }
@@ -175,13 +1510,14 @@ impl Customer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::admin::AdminKey;
 
     #[test]
     fn test_deposit() -> anyhow::Result<()> {
         let mut customer = Customer::default();
-        customer.deposit(1, 2.)?;
+        customer.deposit(1, Amount::new(2, 0))?;
 
-        assert_eq!(customer.total_balance, 2.);
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
 
         Ok(())
     }
@@ -192,19 +1528,54 @@ mod tests {
             is_locked: true,
             ..Default::default()
         };
-        let is_err = customer.deposit(1, 2.).is_err();
+        let is_err = customer.deposit(1, Amount::new(2, 0)).is_err();
 
         assert!(is_err);
     }
 
+    #[test]
+    fn test_deposit_overflow() -> anyhow::Result<()> {
+        let mut customer = Customer {
+            total_balance: Amount::MAX.checked_sub(Amount::from_units(1))?,
+            ..Default::default()
+        };
+        customer.deposit(1, Amount::new(1, 4))?;
+        assert_eq!(customer.total_balance, Amount::MAX);
+
+        assert_eq!(
+            customer.deposit(2, Amount::new(1, 4)),
+            Err(LedgerError::Overflow)
+        );
+        assert_eq!(customer.total_balance, Amount::MAX);
+        assert_eq!(
+            customer.get_transaction_amount(2),
+            Err(LedgerError::UnknownTransaction)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispute_overflow() -> anyhow::Result<()> {
+        let mut customer = Customer::default();
+        customer.deposit(1, Amount::new(1, 0))?;
+        customer.held_balance = Amount::MAX;
+
+        assert_eq!(customer.dispute(1), Err(LedgerError::Overflow));
+        assert_eq!(customer.held_balance, Amount::MAX);
+        assert!(customer.resolve(1).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_withdrawal() -> anyhow::Result<()> {
         let mut customer = Customer::default();
-        customer.deposit(1, 2.)?;
-        assert_eq!(customer.total_balance, 2.);
+        customer.deposit(1, Amount::new(2, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
 
-        customer.withdraw(2, 1.)?;
-        assert_eq!(customer.total_balance, 1.);
+        customer.withdraw(2, Amount::new(1, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(1, 0));
 
         Ok(())
     }
@@ -212,19 +1583,19 @@ mod tests {
     #[test]
     fn test_withdrawal_2() {
         let mut customer = Customer::default();
-        let outcome = customer.withdraw(2, 1.).is_err();
+        let outcome = customer.withdraw(2, Amount::new(1, 0)).is_err();
         assert!(outcome);
     }
 
     #[test]
     fn test_withdrawal_3() -> anyhow::Result<()> {
         let mut customer = Customer::default();
-        customer.deposit(1, 2.)?;
-        assert_eq!(customer.total_balance, 2.);
+        customer.deposit(1, Amount::new(2, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
 
         customer.is_locked = true;
 
-        let is_err = customer.withdraw(2, 1.).is_err();
+        let is_err = customer.withdraw(2, Amount::new(1, 0)).is_err();
         assert!(is_err);
 
         Ok(())
@@ -233,11 +1604,11 @@ mod tests {
     #[test]
     fn test_withdrawal_4() -> anyhow::Result<()> {
         let mut customer = Customer::default();
-        customer.deposit(1, 2.)?;
-        assert_eq!(customer.total_balance, 2.);
+        customer.deposit(1, Amount::new(2, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
 
-        customer.withdraw(2, 1.)?;
-        let is_err = customer.withdraw(2, 1.).is_err();
+        customer.withdraw(2, Amount::new(1, 0))?;
+        let is_err = customer.withdraw(2, Amount::new(1, 0)).is_err();
         assert!(is_err);
 
         Ok(())
@@ -246,12 +1617,12 @@ mod tests {
     #[test]
     fn test_dispute() -> anyhow::Result<()> {
         let mut customer = Customer::default();
-        customer.deposit(1, 2.)?;
-        assert_eq!(customer.total_balance, 2.);
+        customer.deposit(1, Amount::new(2, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
 
         customer.dispute(1)?;
-        assert_eq!(customer.total_balance, 2.);
-        assert_eq!(customer.held_balance, 2.);
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
+        assert_eq!(customer.held_balance, Amount::new(2, 0));
 
         Ok(())
     }
@@ -259,18 +1630,18 @@ mod tests {
     #[test]
     fn test_dispute_withdrawal() -> anyhow::Result<()> {
         let mut customer = Customer::default();
-        customer.deposit(1, 2.)?;
-        assert_eq!(customer.total_balance, 2.);
-        customer.deposit(2, 1.)?;
-        assert_eq!(customer.total_balance, 3.);
+        customer.deposit(1, Amount::new(2, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
+        customer.deposit(2, Amount::new(1, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(3, 0));
 
         customer.dispute(1)?;
-        assert_eq!(customer.total_balance, 3.);
-        assert_eq!(customer.held_balance, 2.);
+        assert_eq!(customer.total_balance, Amount::new(3, 0));
+        assert_eq!(customer.held_balance, Amount::new(2, 0));
 
-        customer.withdraw(3, 1.)?;
-        assert_eq!(customer.total_balance, 2.);
-        assert_eq!(customer.held_balance, 2.);
+        customer.withdraw(3, Amount::new(1, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
+        assert_eq!(customer.held_balance, Amount::new(2, 0));
 
         Ok(())
     }
@@ -278,14 +1649,14 @@ mod tests {
     #[test]
     fn test_dispute_fail_withdrawal() -> anyhow::Result<()> {
         let mut customer = Customer::default();
-        customer.deposit(1, 2.)?;
-        assert_eq!(customer.total_balance, 2.);
+        customer.deposit(1, Amount::new(2, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
 
         customer.dispute(1)?;
-        assert_eq!(customer.total_balance, 2.);
-        assert_eq!(customer.held_balance, 2.);
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
+        assert_eq!(customer.held_balance, Amount::new(2, 0));
 
-        let is_err = customer.withdraw(2, 1.).is_err();
+        let is_err = customer.withdraw(2, Amount::new(1, 0)).is_err();
         assert!(is_err);
 
         Ok(())
@@ -303,23 +1674,248 @@ mod tests {
     fn test_resolve() -> anyhow::Result<()> {
         let mut customer = Customer::default();
 
-        customer.deposit(1, 2.)?;
-        customer.deposit(2, 3.)?;
-        assert_eq!(customer.total_balance, 5.);
+        customer.deposit(1, Amount::new(2, 0))?;
+        customer.deposit(2, Amount::new(3, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(5, 0));
 
         customer.dispute(1)?;
-        assert_eq!(customer.total_balance, 5.);
-        assert_eq!(customer.held_balance, 2.);
+        assert_eq!(customer.total_balance, Amount::new(5, 0));
+        assert_eq!(customer.held_balance, Amount::new(2, 0));
 
         customer.resolve(1)?;
-        assert_eq!(customer.total_balance, 5.);
-        assert_eq!(customer.held_balance, 0.);
-        assert_eq!(customer.disputed_transactions.len(), 0);
+        assert_eq!(customer.total_balance, Amount::new(5, 0));
+        assert_eq!(customer.held_balance, Amount::ZERO);
+        assert_eq!(customer.records.state(1), Some(TransactionState::Processed));
         assert!(!customer.is_locked);
 
         Ok(())
     }
 
+    #[test]
+    fn test_freeze_policy() -> anyhow::Result<()> {
+        let mut customer = Customer {
+            freeze: FreezePolicy {
+                chargebacks: Some(2),
+                held_above: Some(Amount::new(5, 0)),
+                cooling_off: None,
+            },
+            ..Default::default()
+        };
+        for tx in 1..=3 {
+            customer.deposit(tx, Amount::new(2, 0))?;
+        }
+
+        customer.dispute(1)?;
+        customer.chargeback(1)?;
+        assert!(!customer.is_locked);
+        customer.dispute(2)?;
+        customer.chargeback(2)?;
+        assert!(customer.is_locked);
+        assert_eq!(customer.frozen_by, Some(FreezeRule::Chargebacks(2)));
+
+        let mut customer = Customer {
+            freeze: FreezePolicy {
+                chargebacks: None,
+                held_above: Some(Amount::new(3, 0)),
+                cooling_off: None,
+            },
+            ..Default::default()
+        };
+        customer.deposit(1, Amount::new(2, 0))?;
+        customer.deposit(2, Amount::new(2, 0))?;
+        customer.dispute(1)?;
+        customer.chargeback(1)?;
+        assert!(!customer.is_locked);
+        customer.dispute(2)?;
+        customer.deposit(3, Amount::new(2, 0))?;
+        assert!(!customer.is_locked);
+
+        customer.deposit(4, Amount::new(2, 0))?;
+        customer.dispute(3)?;
+        customer.dispute(4)?;
+        assert!(customer.is_locked);
+        assert_eq!(
+            customer.frozen_by,
+            Some(FreezeRule::HeldAbove(Amount::new(3, 0)))
+        );
+        assert_eq!(
+            customer.frozen_by.map(|rule| rule.to_string()).as_deref(),
+            Some("held>3")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_freeze_cooling_off() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_freeze_policy(FreezePolicy {
+            cooling_off: Some(100),
+            ..Default::default()
+        });
+        let record = |record_type, tx, amount, timestamp| structs::Record {
+            record_type,
+            client: 1,
+            tx,
+            amount,
+            timestamp,
+            ..Default::default()
+        };
+
+        tracker.apply(&record(
+            structs::RecordType::Deposit,
+            1,
+            Some(Amount::new(5, 0)),
+            Some(1_000),
+        ))?;
+        tracker.apply(&record(
+            structs::RecordType::Deposit,
+            2,
+            Some(Amount::new(5, 0)),
+            Some(1_010),
+        ))?;
+        tracker.apply(&record(structs::RecordType::Dispute, 1, None, Some(1_020)))?;
+        tracker.apply(&record(
+            structs::RecordType::Chargeback,
+            1,
+            None,
+            Some(1_030),
+        ))?;
+        assert!(tracker.is_locked(1));
+        assert_eq!(tracker.extended_records()?[0].frozen_until, Some(1_130));
+
+        let deposit = record(
+            structs::RecordType::Deposit,
+            3,
+            Some(Amount::new(1, 0)),
+            Some(1_129),
+        );
+        assert_eq!(tracker.apply(&deposit), Err(LedgerError::AccountLocked));
+        // Without a timestamp the cooling-off period cannot end.
+        let deposit = record(
+            structs::RecordType::Deposit,
+            3,
+            Some(Amount::new(1, 0)),
+            None,
+        );
+        assert_eq!(tracker.apply(&deposit), Err(LedgerError::AccountLocked));
+
+        let deposit = record(
+            structs::RecordType::Deposit,
+            3,
+            Some(Amount::new(1, 0)),
+            Some(1_130),
+        );
+        assert_eq!(
+            tracker.apply(&deposit),
+            Ok(Applied::Deposited(Amount::new(1, 0)))
+        );
+        assert!(!tracker.is_locked(1));
+        assert_eq!(tracker.extended_records()?[0].frozen_by, None);
+
+        // Without a timestamp, the period starts at the latest known one.
+        tracker.apply(&record(structs::RecordType::Dispute, 2, None, None))?;
+        tracker.apply(&record(structs::RecordType::Chargeback, 2, None, None))?;
+        let deposit = record(
+            structs::RecordType::Deposit,
+            4,
+            Some(Amount::new(1, 0)),
+            Some(1_229),
+        );
+        assert_eq!(tracker.apply(&deposit), Err(LedgerError::AccountLocked));
+        let deposit = record(
+            structs::RecordType::Deposit,
+            4,
+            Some(Amount::new(1, 0)),
+            Some(1_230),
+        );
+        assert_eq!(
+            tracker.apply(&deposit),
+            Ok(Applied::Deposited(Amount::new(1, 0)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_groups() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_groups("smith=1,2".parse()?);
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+        tracker.deposit(2, 2, Amount::new(3, 0))?;
+        tracker.deposit(3, 3, Amount::new(1, 0))?;
+
+        let withdrawal = structs::Record {
+            record_type: structs::RecordType::Withdrawal,
+            client: 2,
+            tx: 4,
+            amount: Some(Amount::new(7, 0)),
+            ..Default::default()
+        };
+        assert_eq!(
+            tracker.simulate(&withdrawal)?.account.available,
+            Amount::new(-4, 0)
+        );
+        assert_eq!(
+            tracker.withdraw(2, 4, Amount::new(7, 0)),
+            Ok(Applied::Withdrawn(Amount::new(7, 0)))
+        );
+        assert_eq!(
+            tracker.withdraw(1, 5, Amount::new(2, 0)),
+            Err(LedgerError::InsufficientFunds)
+        );
+        assert_eq!(
+            tracker.withdraw(3, 6, Amount::new(2, 0)),
+            Err(LedgerError::InsufficientFunds)
+        );
+
+        let records = tracker.grouped_records()?;
+        assert_eq!(records.len(), 4);
+        let group = records.last().expect("group row");
+        assert_eq!(group.client, None);
+        assert_eq!(group.group.as_deref(), Some("smith"));
+        assert_eq!(group.available, Amount::new(1, 0));
+        let client = records
+            .iter()
+            .find(|record| record.client == Some(3))
+            .expect("client row");
+        assert_eq!(client.group, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_concentration() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        for (client, amount) in [(1, 10), (2, 50), (3, 30), (4, 10)] {
+            tracker.deposit(client, u32::from(client), Amount::new(amount, 0))?;
+        }
+        tracker.dispute(3, 3)?;
+
+        let report = tracker.concentration(2)?;
+        let rows: Vec<_> = report
+            .iter()
+            .map(|row| {
+                (
+                    row.by,
+                    row.rank,
+                    row.client,
+                    row.share,
+                    row.cumulative_share,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("total", 1, 2, 0.5, 0.5),
+                ("total", 2, 3, 0.3, 0.8),
+                ("held", 1, 3, 1., 1.),
+                ("held", 2, 1, 0., 1.),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_resolve_without_tx() {
         let mut customer = Customer::default();
@@ -332,18 +1928,21 @@ mod tests {
     fn test_chargeback() -> anyhow::Result<()> {
         let mut customer = Customer::default();
 
-        customer.deposit(1, 2.)?;
-        customer.deposit(2, 3.)?;
-        assert_eq!(customer.total_balance, 5.);
+        customer.deposit(1, Amount::new(2, 0))?;
+        customer.deposit(2, Amount::new(3, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(5, 0));
 
         customer.dispute(1)?;
-        assert_eq!(customer.total_balance, 5.);
-        assert_eq!(customer.held_balance, 2.);
+        assert_eq!(customer.total_balance, Amount::new(5, 0));
+        assert_eq!(customer.held_balance, Amount::new(2, 0));
 
         customer.chargeback(1)?;
-        assert_eq!(customer.total_balance, 3.);
-        assert_eq!(customer.held_balance, 0.);
-        assert_eq!(customer.disputed_transactions.len(), 1);
+        assert_eq!(customer.total_balance, Amount::new(3, 0));
+        assert_eq!(customer.held_balance, Amount::ZERO);
+        assert_eq!(
+            customer.records.state(1),
+            Some(TransactionState::ChargedBack)
+        );
         assert!(customer.is_locked);
 
         Ok(())
@@ -353,19 +1952,35 @@ mod tests {
     fn test_chargeback_without_dispute() -> anyhow::Result<()> {
         let mut customer = Customer::default();
 
-        customer.deposit(1, 2.)?;
-        customer.deposit(2, 3.)?;
-        assert_eq!(customer.total_balance, 5.);
+        customer.deposit(1, Amount::new(2, 0))?;
+        customer.deposit(2, Amount::new(3, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(5, 0));
 
         let is_err = customer.chargeback(1).is_err();
         assert!(is_err);
-        assert_eq!(customer.total_balance, 5.);
-        assert_eq!(customer.held_balance, 0.);
-        assert_eq!(customer.disputed_transactions.len(), 0);
+        assert_eq!(customer.total_balance, Amount::new(5, 0));
+        assert_eq!(customer.held_balance, Amount::ZERO);
+        assert_eq!(customer.records.state(1), Some(TransactionState::Processed));
         assert!(!customer.is_locked);
 
         Ok(())
     }
+    #[test]
+    fn test_chargeback_twice() -> anyhow::Result<()> {
+        let mut customer = Customer::default();
+
+        customer.deposit(1, Amount::new(2, 0))?;
+        customer.dispute(1)?;
+        customer.chargeback(1)?;
+
+        assert!(customer.chargeback(1).is_err());
+        assert!(customer.dispute(1).is_err());
+        assert_eq!(customer.total_balance, Amount::ZERO);
+        assert_eq!(customer.held_balance, Amount::ZERO);
+
+        Ok(())
+    }
+
     #[test]
     fn test_chargeback_without_tx() {
         let mut customer = Customer::default();
@@ -374,6 +1989,69 @@ mod tests {
         assert!(is_err);
     }
 
+    #[test]
+    fn test_retention_dispute_window() -> anyhow::Result<()> {
+        let mut customer = Customer {
+            retention: RetentionPolicy::DisputeWindow(1),
+            ..Default::default()
+        };
+
+        customer.deposit(1, Amount::new(1, 0))?;
+        customer.deposit(2, Amount::new(1, 0))?;
+        customer.withdraw(3, Amount::new(1, 0))?;
+        customer.deposit(4, Amount::new(1, 0))?;
+
+        assert!(customer.dispute(1).is_err());
+        assert!(customer.dispute(3).is_err());
+        customer.dispute(4)?;
+        assert_eq!(customer.held_balance, Amount::new(1, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_retention_none() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_retention(RetentionPolicy::None);
+        let customer = tracker.get_or_insert_customer(1);
+
+        customer.deposit(1, Amount::new(2, 0))?;
+        assert_eq!(customer.total_balance, Amount::new(2, 0));
+        assert!(customer.dispute(1).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_rejected_under_every_retention() -> anyhow::Result<()> {
+        for retention in [
+            RetentionPolicy::All,
+            RetentionPolicy::DisputeWindow(1),
+            RetentionPolicy::None,
+        ] {
+            let mut tracker = Ledger::new().with_retention(retention);
+            tracker.deposit(1, 1, Amount::new(5, 0))?;
+            tracker.withdraw(1, 2, Amount::new(1, 0))?;
+            tracker.deposit(1, 3, Amount::new(1, 0))?;
+            tracker.deposit(1, 4, Amount::new(1, 0))?;
+
+            for tx in 1..=4 {
+                assert!(tracker.deposit(1, tx, Amount::new(1, 0)).is_err());
+                assert!(tracker.withdraw(1, tx, Amount::new(1, 0)).is_err());
+            }
+            assert_eq!(
+                tracker.client_record(1)?.map(|account| account.total),
+                Some(Amount::new(6, 0))
+            );
+
+            let mut restored = Ledger::new().with_retention(retention);
+            restored.restore(&tracker.snapshot())?;
+            assert!(restored.deposit(1, 1, Amount::new(1, 0)).is_err());
+            assert!(restored.withdraw(1, 2, Amount::new(1, 0)).is_err());
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_new_tracker() {
         let tracker = Ledger::new();
@@ -385,27 +2063,768 @@ mod tests {
         let mut tracker = Ledger::new();
         let client_id = 1;
         let customer = tracker.get_or_insert_customer(client_id);
-        assert_eq!(customer.total_balance, 0.);
-        assert_eq!(customer.held_balance, 0.);
+        assert_eq!(customer.total_balance, Amount::ZERO);
+        assert_eq!(customer.held_balance, Amount::ZERO);
         assert!(!customer.is_locked);
-        assert!(customer.records.is_empty());
-        assert!(customer.disputed_transactions.is_empty());
+        assert_eq!(customer.records, TransactionStore::default());
     }
 
     #[test]
-    fn test_tracker_printable_accounts() {
-        let mut tracker = Ledger::new();
+    fn test_tracker_printable_accounts() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_empty_accounts(EmptyAccountPolicy::Keep);
         let client_id = 1;
         let customer = tracker.get_or_insert_customer(client_id);
-        customer.total_balance = 100.;
-        customer.held_balance = 50.;
-        let accounts = tracker.client_records();
+        customer.total_balance = Amount::new(100, 0);
+        customer.held_balance = Amount::new(50, 0);
+        let accounts = tracker.client_records()?;
         assert_eq!(accounts.len(), 1);
         let account = &accounts[0];
         assert_eq!(account.client, client_id);
-        assert_eq!(account.available, 50.);
-        assert_eq!(account.held, 50.);
-        assert_eq!(account.total, 100.);
+        assert_eq!(account.available, Amount::new(50, 0));
+        assert_eq!(account.held, Amount::new(50, 0));
+        assert_eq!(account.total, Amount::new(100, 0));
         assert!(!account.locked);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_total_overflow() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        tracker.deposit(1, 1, Amount::new(2, 0))?;
+        tracker.totals.deposits = Amount::MAX.checked_sub(Amount::from_units(10_000))?;
+        tracker.deposit(1, 2, Amount::new(1, 0))?;
+
+        assert_eq!(
+            tracker.deposit(2, 3, Amount::new(1, 0)),
+            Err(LedgerError::Overflow)
+        );
+        assert_eq!(tracker.totals.deposits, Amount::MAX);
+        assert_eq!(tracker.client_records()?.len(), 1);
+        assert_eq!(tracker.client_records()?[0].total, Amount::new(3, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_apply() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        let record = |record_type, tx, amount| structs::Record {
+            record_type,
+            client: 1,
+            tx,
+            amount,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            tracker.apply(&record(
+                structs::RecordType::Deposit,
+                1,
+                Some(Amount::new(5, 0))
+            ))?,
+            Applied::Deposited(Amount::new(5, 0))
+        );
+        assert_eq!(
+            tracker.apply(&record(
+                structs::RecordType::Withdrawal,
+                2,
+                Some(Amount::new(1, 0))
+            ))?,
+            Applied::Withdrawn(Amount::new(1, 0))
+        );
+        assert_eq!(
+            tracker.apply(&record(structs::RecordType::Dispute, 1, None))?,
+            Applied::Held(Amount::new(5, 0))
+        );
+        assert_eq!(
+            tracker.apply(&record(structs::RecordType::Resolve, 1, None))?,
+            Applied::Released(Amount::new(5, 0))
+        );
+        assert_eq!(
+            tracker.apply(&record(structs::RecordType::Dispute, 1, None))?,
+            Applied::Held(Amount::new(5, 0))
+        );
+        assert_eq!(
+            tracker.apply(&record(structs::RecordType::Chargeback, 1, None))?,
+            Applied::ChargedBack(Amount::new(5, 0))
+        );
+
+        assert_eq!(
+            tracker.apply(&record(structs::RecordType::Deposit, 3, None)),
+            Err(LedgerError::MissingAmount)
+        );
+        assert_eq!(
+            tracker.apply(&record(
+                structs::RecordType::Deposit,
+                3,
+                Some(Amount::new(1, 0))
+            )),
+            Err(LedgerError::AccountLocked)
+        );
+        assert_eq!(
+            tracker.apply(&record(structs::RecordType::Resolve, 9, None)),
+            Err(LedgerError::UnknownTransaction)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_empty_accounts() -> anyhow::Result<()> {
+        let record = |record_type, client, amount| structs::Record {
+            record_type,
+            client,
+            tx: 1,
+            amount,
+            ..Default::default()
+        };
+        let apply = |tracker: &mut Ledger| {
+            tracker.apply(&record(
+                structs::RecordType::Deposit,
+                1,
+                Some(Amount::new(2, 0)),
+            ))?;
+            tracker.apply(&structs::Record {
+                tx: 2,
+                ..record(structs::RecordType::Withdrawal, 1, Some(Amount::new(2, 0)))
+            })?;
+            assert!(tracker
+                .apply(&record(structs::RecordType::Dispute, 2, None))
+                .is_err());
+            assert!(tracker
+                .apply(&record(
+                    structs::RecordType::Withdrawal,
+                    3,
+                    Some(Amount::new(1, 0))
+                ))
+                .is_err());
+            anyhow::Ok(())
+        };
+        let clients = |records: Vec<structs::ClientRecord>| {
+            let mut clients: Vec<_> = records.iter().map(|record| record.client).collect();
+            clients.sort_unstable();
+            clients
+        };
+
+        let mut tracker = Ledger::new()
+            .with_retention(RetentionPolicy::None)
+            .with_empty_accounts(EmptyAccountPolicy::Keep);
+        apply(&mut tracker)?;
+        assert_eq!(clients(tracker.client_records()?), [1, 2, 3]);
+
+        let mut tracker = Ledger::new().with_retention(RetentionPolicy::None);
+        apply(&mut tracker)?;
+        assert_eq!(clients(tracker.client_records()?), [1]);
+        assert_eq!(tracker.extended_records()?.len(), 1);
+        assert_eq!(tracker.snapshot().accounts.len(), 3);
+        assert_eq!(tracker.take_client_record(2)?, None);
+
+        let mut tracker = Ledger::new()
+            .with_retention(RetentionPolicy::None)
+            .with_empty_accounts(EmptyAccountPolicy::Remove);
+        apply(&mut tracker)?;
+        assert_eq!(clients(tracker.client_records()?), [1]);
+        assert_eq!(tracker.snapshot().accounts.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_omit_zero_accounts() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_omit_zero_accounts(true);
+        tracker.deposit(1, 1, Amount::new(2, 0))?;
+        tracker.withdraw(1, 2, Amount::new(2, 0))?;
+        tracker.deposit(2, 3, Amount::new(1, 0))?;
+        tracker.deposit(3, 4, Amount::new(1, 0))?;
+        tracker.dispute(3, 4)?;
+        tracker.chargeback(3, 4)?;
+        tracker.deposit(4, 5, Amount::new(1, 0))?;
+        tracker.dispute(4, 5)?;
+
+        let mut clients: Vec<_> = tracker
+            .client_records()?
+            .iter()
+            .map(|account| account.client)
+            .collect();
+        clients.sort_unstable();
+        assert_eq!(clients, [2, 3, 4]);
+        assert_eq!(tracker.extended_records()?.len(), 3);
+        assert_eq!(tracker.take_client_record(1)?, None);
+        assert!(tracker.take_client_record(2)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_simulate() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+
+        let withdrawal = structs::Record {
+            record_type: structs::RecordType::Withdrawal,
+            client: 1,
+            tx: 2,
+            amount: Some(Amount::new(2, 0)),
+            ..Default::default()
+        };
+        let simulation = tracker.simulate(&withdrawal)?;
+        assert_eq!(
+            simulation.outcome,
+            Ok(Applied::Withdrawn(Amount::new(2, 0)))
+        );
+        assert_eq!(simulation.account.available, Amount::new(3, 0));
+        assert_eq!(tracker.client_records()?[0].available, Amount::new(5, 0));
+
+        let simulation = tracker.simulate(&structs::Record {
+            amount: Some(Amount::new(6, 0)),
+            ..withdrawal.clone()
+        })?;
+        assert_eq!(simulation.outcome, Err(LedgerError::InsufficientFunds));
+        assert_eq!(simulation.account.available, Amount::new(5, 0));
+
+        let simulation = tracker.simulate(&structs::Record {
+            client: 2,
+            ..withdrawal
+        })?;
+        assert_eq!(simulation.outcome, Err(LedgerError::InsufficientFunds));
+        assert_eq!(simulation.account.client, 2);
+        assert_eq!(tracker.client_records()?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_simulate_agrees_with_apply() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new()
+            .with_journal()
+            .with_empty_accounts(EmptyAccountPolicy::Remove);
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+        tracker.adjust(1, 2, Amount::new(1, 0))?;
+        tracker.deposit(3, 5, Amount::new(100, 0))?;
+
+        let key = AdminKey::new(b"secret")?;
+        let mut replayed = structs::Record {
+            record_type: structs::RecordType::Adjustment,
+            client: 1,
+            tx: 2,
+            amount: Some(Amount::new(1, 0)),
+            ..Default::default()
+        };
+        replayed.signature = Some(key.sign(&replayed));
+        let verified = key.verify(&replayed)?;
+        let simulation = tracker.simulate_verified(verified)?;
+        assert_eq!(simulation.outcome, Err(LedgerError::DuplicateTransaction));
+        assert_eq!(simulation.outcome, tracker.apply_verified(verified));
+        assert_eq!(simulation.account.total, Amount::new(6, 0));
+
+        let records = [
+            structs::Record {
+                record_type: structs::RecordType::Adjustment,
+                client: 1,
+                tx: 2,
+                amount: Some(Amount::new(-1, 0)),
+                ..Default::default()
+            },
+            structs::Record {
+                record_type: structs::RecordType::Withdrawal,
+                client: 2,
+                tx: 3,
+                amount: Some(Amount::new(1, 0)),
+                ..Default::default()
+            },
+            structs::Record {
+                record_type: structs::RecordType::Deposit,
+                client: 1,
+                tx: 4,
+                // Fits the account, but not the running total of deposits.
+                amount: Some(Amount::MAX.checked_sub(Amount::new(50, 0))?),
+                ..Default::default()
+            },
+        ];
+        for record in &records {
+            let simulation = tracker.simulate(record)?;
+            assert!(simulation.outcome.is_err());
+            assert_eq!(simulation.outcome, tracker.apply(record));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_snapshot() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+        tracker.withdraw(1, 2, Amount::new(1, 0))?;
+        tracker.dispute(1, 1)?;
+        tracker.deposit(2, 3, Amount::new(2, 0))?;
+
+        let snapshot = tracker.snapshot();
+        let mut restored = Ledger::new().with_journal();
+        restored.restore(&snapshot)?;
+
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(
+            restored
+                .query(AccountFilter::default())?
+                .collect::<Vec<_>>(),
+            tracker.query(AccountFilter::default())?.collect::<Vec<_>>()
+        );
+        restored.resolve(1, 1)?;
+        restored.verify_journal()?;
+        assert!(restored.restore(&snapshot).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_delta_snapshot() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+        tracker.deposit(2, 2, Amount::new(2, 0))?;
+        let mut base = tracker.delta_snapshot();
+        assert_eq!(base.accounts.len(), 2);
+
+        tracker.withdraw(2, 3, Amount::new(1, 0))?;
+        let delta = tracker.delta_snapshot();
+        assert!(delta.delta);
+        assert_eq!(delta.accounts.len(), 1);
+        assert_eq!(delta.accounts[0].client, 2);
+
+        base.merge(&delta);
+        assert_eq!(base.accounts, tracker.snapshot().accounts);
+        assert_eq!(tracker.delta_snapshot().accounts, vec![]);
+
+        let mut restored = Ledger::new();
+        restored.restore(&base)?;
+        assert_eq!(restored.delta_snapshot().accounts, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_journal() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_journal();
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+        tracker.deposit(1, 2, Amount::new(3, 0))?;
+        tracker.withdraw(1, 3, Amount::new(1, 0))?;
+        tracker.dispute(1, 1)?;
+        tracker.resolve(1, 1)?;
+        tracker.dispute(1, 2)?;
+        tracker.chargeback(1, 2)?;
+        assert!(tracker.deposit(1, 4, Amount::new(1, 0)).is_err());
+
+        tracker.verify_journal()?;
+        let journal = tracker.journal().expect("journal is enabled");
+        assert_eq!(journal.postings().len(), 14);
+        assert_eq!(
+            journal.balances()?[&PostingAccount::Clearing],
+            Amount::new(-4, 0)
+        );
+
+        tracker.get_or_insert_customer(1).total_balance = Amount::new(1, 0);
+        assert!(tracker.verify_journal().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_open_account() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_journal();
+        tracker.open_account(&structs::ClientRecord {
+            client: 1,
+            available: Amount::new(3, 0),
+            held: Amount::new(2, 0),
+            total: Amount::new(5, 0),
+            locked: false,
+            escrowed: Amount::ZERO,
+        })?;
+        tracker.withdraw(1, 1, Amount::new(3, 0))?;
+        assert!(tracker.withdraw(1, 2, Amount::new(1, 0)).is_err());
+
+        let account = &tracker.client_records()?[0];
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.held, Amount::new(2, 0));
+        assert_eq!(account.total, Amount::new(2, 0));
+        assert_eq!(tracker.trial_balance()?.opening, Amount::new(5, 0));
+        tracker.verify_journal()?;
+
+        let duplicate = structs::ClientRecord {
+            client: 1,
+            available: Amount::new(1, 0),
+            held: Amount::ZERO,
+            total: Amount::new(1, 0),
+            locked: false,
+            escrowed: Amount::ZERO,
+        };
+        assert!(tracker.open_account(&duplicate).is_err());
+
+        let inconsistent = structs::ClientRecord {
+            client: 2,
+            available: Amount::new(1, 0),
+            held: Amount::ZERO,
+            total: Amount::new(2, 0),
+            locked: false,
+            escrowed: Amount::ZERO,
+        };
+        assert!(tracker.open_account(&inconsistent).is_err());
+
+        tracker.open_account(&structs::ClientRecord {
+            client: 3,
+            available: Amount::new(1, 0),
+            held: Amount::ZERO,
+            total: Amount::new(3, 0),
+            locked: false,
+            escrowed: Amount::new(2, 0),
+        })?;
+        assert_eq!(
+            tracker.client_record(3)?.map(|account| account.escrowed),
+            Some(Amount::new(2, 0))
+        );
+        tracker.escrow_release(3, 3, OPENING_ESCROW, Amount::new(2, 0))?;
+        assert_eq!(
+            tracker.client_record(3)?.map(|account| account.available),
+            Some(Amount::new(3, 0))
+        );
+        assert_eq!(tracker.trial_balance()?.opening, Amount::new(8, 0));
+        tracker.verify_journal()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_open_transaction() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        tracker.open_account(&structs::ClientRecord {
+            client: 1,
+            available: Amount::new(1, 0),
+            held: Amount::new(2, 0),
+            total: Amount::new(3, 0),
+            locked: false,
+            escrowed: Amount::ZERO,
+        })?;
+        for (record_type, tx, amount) in [
+            (structs::RecordType::Deposit, 1, Some(Amount::new(2, 0))),
+            (structs::RecordType::Deposit, 2, Some(Amount::new(1, 0))),
+            (structs::RecordType::Dispute, 1, None),
+        ] {
+            tracker.open_transaction(&structs::Record {
+                record_type,
+                client: 1,
+                tx,
+                amount,
+                ..Default::default()
+            })?;
+        }
+
+        tracker.resolve(1, 1)?;
+        tracker.dispute(1, 2)?;
+
+        let account = &tracker.client_records()?[0];
+        assert_eq!(account.available, Amount::new(2, 0));
+        assert_eq!(account.held, Amount::new(1, 0));
+        assert_eq!(account.total, Amount::new(3, 0));
+        assert!(tracker.deposit(1, 2, Amount::new(1, 0)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_trial_balance() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+        tracker.deposit(2, 2, Amount::new(3, 0))?;
+        tracker.deposit(2, 3, Amount::new(2, 0))?;
+        tracker.withdraw(1, 4, Amount::new(1, 0))?;
+        tracker.dispute(1, 1)?;
+        tracker.dispute(2, 2)?;
+        tracker.chargeback(2, 2)?;
+        assert!(tracker.withdraw(2, 5, Amount::new(10, 0)).is_err());
+
+        let trial_balance = tracker.trial_balance()?;
+        assert_eq!(trial_balance.deposits, Amount::new(10, 0));
+        assert_eq!(trial_balance.withdrawals, Amount::new(1, 0));
+        assert_eq!(trial_balance.charged_back, Amount::new(3, 0));
+        assert_eq!(trial_balance.held, Amount::new(5, 0));
+        assert_eq!(trial_balance.net, Amount::new(6, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_admin_operations() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_journal();
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+        tracker.withdraw(1, 2, Amount::new(4, 0))?;
+        tracker.dispute(1, 1)?;
+        tracker.chargeback(1, 1)?;
+        assert!(tracker.is_locked(1));
+        assert_eq!(tracker.client_records()?[0].available, Amount::new(-4, 0));
+
+        assert_eq!(
+            tracker.adjust_checked(1, 3, Amount::new(-15, 1)),
+            Err(LedgerError::AccountLocked)
+        );
+        // Adjustments and write-offs also apply to locked accounts.
+        assert_eq!(
+            tracker.adjust(1, 3, Amount::new(15, 1))?,
+            Applied::Adjusted(Amount::new(15, 1))
+        );
+        assert_eq!(
+            tracker.write_off(1, 4)?,
+            Applied::WrittenOff(Amount::new(25, 1))
+        );
+        assert_eq!(tracker.write_off(1, 5), Err(LedgerError::NothingToWriteOff));
+        assert_eq!(
+            tracker.adjust(1, 6, Amount::new(-5, 1))?,
+            Applied::Adjusted(Amount::new(-5, 1))
+        );
+        // Admin tx ids are applied once, also across adjustments and write-offs.
+        assert_eq!(
+            tracker.adjust(1, 6, Amount::new(-5, 1)),
+            Err(LedgerError::DuplicateTransaction)
+        );
+        assert_eq!(
+            tracker.adjust(1, 4, Amount::new(1, 0)),
+            Err(LedgerError::DuplicateTransaction)
+        );
+        assert_eq!(tracker.client_records()?[0].available, Amount::new(-5, 1));
+        assert!(tracker.is_locked(1));
+
+        assert_eq!(tracker.unlock(1), Applied::Unlocked);
+        assert!(!tracker.is_locked(1));
+        tracker.deposit(1, 7, Amount::new(1, 0))?;
+        assert_eq!(
+            tracker.adjust_checked(1, 8, Amount::new(-1, 0)),
+            Err(LedgerError::InsufficientFunds)
+        );
+        assert_eq!(
+            tracker.adjust_checked(1, 8, Amount::new(5000, 0)),
+            Err(LedgerError::UnsignedCredit)
+        );
+
+        let trial_balance = tracker.trial_balance()?;
+        assert_eq!(trial_balance.adjustments, Amount::new(1, 0));
+        assert_eq!(trial_balance.written_off, Amount::new(25, 1));
+        assert_eq!(trial_balance.net, Amount::new(5, 1));
+        tracker.verify_journal()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_apply_admin_requires_verification() -> anyhow::Result<()> {
+        let key = AdminKey::new(b"secret")?;
+        let mut tracker = Ledger::new();
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+        tracker.withdraw(1, 2, Amount::new(5, 0))?;
+        tracker.dispute(1, 1)?;
+        tracker.chargeback(1, 1)?;
+
+        let forged = |record_type, tx, amount| structs::Record {
+            record_type,
+            client: 1,
+            tx,
+            amount,
+            signature: Some("forged".to_string()),
+            ..Default::default()
+        };
+        let credit = forged(structs::RecordType::Adjustment, 3, Some(Amount::new(9, 0)));
+        let write_off = forged(structs::RecordType::WriteOff, 4, None);
+        let unlock = forged(structs::RecordType::Unlock, 5, None);
+
+        // A signature alone does not make a record an admin operation.
+        assert_eq!(tracker.apply(&credit), Err(LedgerError::UnsignedCredit));
+        assert_eq!(tracker.apply(&write_off), Err(LedgerError::Unauthorized));
+        assert_eq!(
+            tracker.simulate(&write_off)?.outcome,
+            Err(LedgerError::Unauthorized)
+        );
+        assert_eq!(tracker.apply(&unlock), Err(LedgerError::Unauthorized));
+        assert!(tracker.is_locked(1));
+        assert!(key.verify(&write_off).is_err());
+
+        let mut write_off = write_off.clone();
+        write_off.signature = Some(key.sign(&write_off));
+        assert_eq!(
+            tracker.simulate_verified(key.verify(&write_off)?)?.outcome,
+            Ok(Applied::WrittenOff(Amount::new(5, 0)))
+        );
+        assert_eq!(
+            tracker.apply_verified(key.verify(&write_off)?)?,
+            Applied::WrittenOff(Amount::new(5, 0))
+        );
+        assert!(tracker.adjustments()[0].admin);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_write_off_with_funds_on_hold() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_journal();
+        tracker.deposit(1, 1, Amount::new(5, 0))?;
+        tracker.deposit(1, 2, Amount::new(5, 0))?;
+        tracker.withdraw(1, 3, Amount::new(10, 0))?;
+        tracker.dispute(1, 1)?;
+        tracker.dispute(1, 2)?;
+        tracker.chargeback(1, 1)?;
+        let before = tracker.client_records()?;
+        assert_eq!(before[0].total, Amount::new(-5, 0));
+        assert_eq!(before[0].held, Amount::new(5, 0));
+
+        // The dispute of tx 2 is still open.
+        assert_eq!(tracker.write_off(1, 4), Err(LedgerError::FundsOnHold));
+        assert_eq!(tracker.client_records()?, before);
+
+        tracker.deposit(2, 5, Amount::new(5, 0))?;
+        tracker.deposit(2, 6, Amount::new(5, 0))?;
+        tracker.escrow_hold(2, 7, "order-1", Amount::new(3, 0))?;
+        tracker.withdraw(2, 8, Amount::new(7, 0))?;
+        tracker.dispute(2, 5)?;
+        tracker.chargeback(2, 5)?;
+        assert_eq!(tracker.write_off(2, 9), Err(LedgerError::FundsOnHold));
+        assert_eq!(tracker.trial_balance()?.written_off, Amount::ZERO);
+        tracker.verify_journal()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_escrow() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_journal();
+        tracker.deposit(1, 1, Amount::new(10, 0))?;
+        assert_eq!(
+            tracker.escrow_hold(1, 2, "order-1", Amount::new(4, 0))?,
+            Applied::EscrowHeld(Amount::new(4, 0))
+        );
+        tracker.escrow_hold(1, 3, "order-2", Amount::new(5, 0))?;
+        assert_eq!(
+            tracker.escrow_hold(1, 4, "order-3", Amount::new(2, 0)),
+            Err(LedgerError::InsufficientFunds)
+        );
+        assert_eq!(
+            tracker.withdraw(1, 5, Amount::new(2, 0)),
+            Err(LedgerError::InsufficientFunds)
+        );
+        let account = &tracker.extended_records()?[0];
+        assert_eq!(
+            (account.available, account.total, account.escrowed),
+            (Amount::new(1, 0), Amount::new(10, 0), Amount::new(9, 0))
+        );
+
+        assert_eq!(
+            tracker.escrow_release(1, 6, "order-1", Amount::new(5, 0)),
+            Err(LedgerError::InsufficientEscrow)
+        );
+        assert_eq!(
+            tracker.escrow_release(1, 7, "order-1", Amount::new(4, 0))?,
+            Applied::EscrowReleased(Amount::new(4, 0))
+        );
+        assert_eq!(
+            tracker.escrow_release(1, 8, "order-1", Amount::new(1, 0)),
+            Err(LedgerError::InsufficientEscrow)
+        );
+        // Escrow tx ids are applied once, and not reused by other transactions.
+        assert_eq!(
+            tracker.escrow_hold(1, 2, "order-1", Amount::new(1, 0)),
+            Err(LedgerError::DuplicateTransaction)
+        );
+        assert_eq!(
+            tracker.escrow_release(1, 7, "order-2", Amount::new(1, 0)),
+            Err(LedgerError::DuplicateTransaction)
+        );
+        assert_eq!(
+            tracker.deposit(1, 3, Amount::new(1, 0)),
+            Err(LedgerError::DuplicateTransaction)
+        );
+        tracker.verify_journal()?;
+
+        let mut restored = Ledger::new().with_journal();
+        restored.restore(&tracker.snapshot())?;
+        assert_eq!(restored.extended_records()?, tracker.extended_records()?);
+        assert_eq!(
+            restored.escrow_release(1, 7, "order-2", Amount::new(5, 0)),
+            Err(LedgerError::DuplicateTransaction)
+        );
+        restored.escrow_release(1, 9, "order-2", Amount::new(5, 0))?;
+        assert_eq!(restored.client_records()?[0].available, Amount::new(10, 0));
+        restored.verify_journal()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_get_transaction() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        tracker
+            .get_or_insert_customer(1)
+            .deposit(1, Amount::new(5, 0))?;
+        let customer = tracker.get_or_insert_customer(2);
+        customer.deposit(2, Amount::new(3, 0))?;
+        customer.withdraw(3, Amount::new(1, 0))?;
+        customer.dispute(2)?;
+
+        assert_eq!(
+            tracker.get_transaction(2),
+            Some(TransactionInfo {
+                client: 2,
+                kind: TransactionKind::Deposit,
+                amount: Amount::new(3, 0),
+                state: TransactionState::Disputed,
+            })
+        );
+        assert_eq!(
+            tracker.get_transaction(3),
+            Some(TransactionInfo {
+                client: 2,
+                kind: TransactionKind::Withdrawal,
+                amount: Amount::new(1, 0),
+                state: TransactionState::Processed,
+            })
+        );
+        assert_eq!(tracker.get_transaction(4), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_query() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new().with_empty_accounts(EmptyAccountPolicy::Keep);
+        for (client_id, total, locked) in [(1, 10, false), (2, 30, true), (3, 20, false)] {
+            let customer = tracker.get_or_insert_customer(client_id);
+            customer.total_balance = Amount::new(total, 0);
+            customer.is_locked = locked;
+        }
+
+        let clients = |filter| -> anyhow::Result<Vec<_>> {
+            Ok(tracker.query(filter)?.map(|record| record.client).collect())
+        };
+
+        assert_eq!(clients(AccountFilter::default())?, vec![1, 2, 3]);
+        assert_eq!(
+            clients(AccountFilter {
+                locked: Some(false),
+                ..Default::default()
+            })?,
+            vec![1, 3]
+        );
+        assert_eq!(
+            clients(AccountFilter {
+                min_balance: Some(Amount::new(15, 0)),
+                sort: AccountSort::Total,
+                ..Default::default()
+            })?,
+            vec![2, 3]
+        );
+        assert_eq!(
+            clients(AccountFilter {
+                offset: 1,
+                limit: Some(1),
+                ..Default::default()
+            })?,
+            vec![2]
+        );
+
+        Ok(())
     }
 }
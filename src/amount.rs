@@ -0,0 +1,335 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::{AmountError, LedgerError};
+
+/// Decimal places of all amounts.
+pub const DECIMALS: u32 = 4;
+/// Ten-thousandths per unit.
+const SCALE: i64 = 10_i64.pow(DECIMALS);
+
+/// A fixed-point amount of money with four decimal places.
+///
+/// Balances are kept as amounts so that they do not accumulate floating point
+/// errors. All arithmetic is checked and fails with [`LedgerError::Overflow`]
+/// instead of wrapping or saturating.
+///
+/// Amounts are parsed from and written as decimal numbers, never going
+/// through a float, see [`Amount::from_str`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(i64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(i64::MAX);
+    pub const MIN: Amount = Amount(i64::MIN);
+
+    /// Creates an amount of `mantissa` times ten to the power of minus
+    /// `decimals`, e.g. `Amount::new(15, 1)` is `1.5`.
+    ///
+    /// # Panics
+    ///
+    /// If `decimals` is more than [`DECIMALS`] or the amount does not fit,
+    /// which for constants fails the build.
+    pub const fn new(mantissa: i64, decimals: u32) -> Self {
+        assert!(decimals <= DECIMALS, "too many decimal places");
+        match mantissa.checked_mul(10_i64.pow(DECIMALS - decimals)) {
+            Some(units) => Amount(units),
+            None => panic!("amount out of range"),
+        }
+    }
+
+    /// Creates an amount from a number of ten-thousandths.
+    pub const fn from_units(units: i64) -> Self {
+        Amount(units)
+    }
+
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    /// Converts a float, rounded to four decimal places. The float is taken
+    /// as the shortest decimal that it represents, so `5621.2_f32` converts
+    /// to `5621.2` rather than `5621.2002`.
+    pub fn from_f32(value: f32) -> Result<Self, LedgerError> {
+        let value: f64 = value
+            .to_string()
+            .parse()
+            .map_err(|_| LedgerError::Overflow)?;
+        let units = (value * SCALE as f64).round();
+        // i64::MAX is not exactly representable, its f64 is 2^63 itself.
+        if !units.is_finite() || units < i64::MIN as f64 || units >= i64::MAX as f64 {
+            return Err(LedgerError::Overflow);
+        }
+        Ok(Amount(units as i64))
+    }
+
+    pub fn checked_add(self, other: Amount) -> Result<Self, LedgerError> {
+        self.0
+            .checked_add(other.0)
+            .map(Amount)
+            .ok_or(LedgerError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Result<Self, LedgerError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Amount)
+            .ok_or(LedgerError::Overflow)
+    }
+
+    pub fn checked_neg(self) -> Result<Self, LedgerError> {
+        self.0
+            .checked_neg()
+            .map(Amount)
+            .ok_or(LedgerError::Overflow)
+    }
+
+    pub fn checked_abs(self) -> Result<Self, LedgerError> {
+        self.0
+            .checked_abs()
+            .map(Amount)
+            .ok_or(LedgerError::Overflow)
+    }
+
+    /// Whether the amount has no more than the given number of decimal places.
+    pub fn has_decimals(self, decimals: u32) -> bool {
+        decimals >= DECIMALS || self.0 % 10_i64.pow(DECIMALS - decimals) == 0
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    /// Parses a decimal number like `-1234.5678` exactly. Trailing zeros
+    /// aside, it may not have more than four decimal places. Exponents and
+    /// values like `NaN` or `inf`, which floats accept, are rejected.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if integer.is_empty() && fraction.is_empty()
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(AmountError::Malformed);
+        }
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > DECIMALS as usize {
+            return Err(AmountError::TooManyDecimals(DECIMALS));
+        }
+
+        let too_large = || AmountError::TooLarge(Amount::MAX);
+        let mut units: i128 = 0;
+        for digit in integer.bytes().chain(fraction.bytes()) {
+            units = units
+                .checked_mul(10)
+                .and_then(|units| units.checked_add(i128::from(digit - b'0')))
+                .ok_or_else(too_large)?;
+        }
+        units = units
+            .checked_mul(10_i128.pow(DECIMALS - fraction.len() as u32))
+            .ok_or_else(too_large)?;
+        if negative {
+            units = -units;
+        }
+        i64::try_from(units).map(Amount).map_err(|_| too_large())
+    }
+}
+
+/// Writes the amount without trailing zeros, like a float, e.g. `1.5` or
+/// `3`, or with the given precision, rounding half to even.
+impl Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scale = SCALE.unsigned_abs();
+        let mut units = self.0.unsigned_abs();
+        let decimals = match f.precision() {
+            Some(precision) if precision < DECIMALS as usize => {
+                let divisor = 10_u64.pow(DECIMALS - precision as u32);
+                let (quotient, remainder) = (units / divisor, units % divisor);
+                let round_up = remainder > divisor / 2
+                    || remainder == divisor / 2 && !quotient.is_multiple_of(2);
+                units = (quotient + u64::from(round_up)) * divisor;
+                precision
+            }
+            Some(precision) => precision,
+            None if units.is_multiple_of(scale) => 0,
+            None => {
+                let fraction = format!("{:0width$}", units % scale, width = DECIMALS as usize);
+                fraction.trim_end_matches('0').len()
+            }
+        };
+
+        let mut digits = (units / scale).to_string();
+        if decimals > 0 {
+            let fraction = format!("{:0width$}", units % scale, width = DECIMALS as usize);
+            digits.push('.');
+            digits.push_str(&fraction[..decimals.min(fraction.len())]);
+            digits.extend(std::iter::repeat_n(
+                '0',
+                decimals.saturating_sub(fraction.len()),
+            ));
+        }
+        f.pad_integral(self.0 >= 0 || units == 0, "", &digits)
+    }
+}
+
+/// Writes the amount like csv writes floats, with at least one decimal
+/// place, e.g. `1.5` or `3.0`.
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0 % SCALE == 0 {
+            serializer.collect_str(&format_args!("{self:.1}"))
+        } else {
+            serializer.collect_str(self)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AmountVisitor;
+
+        impl de::Visitor<'_> for AmountVisitor {
+            type Value = Amount;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a decimal amount")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Amount, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(AmountVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_f32() -> anyhow::Result<()> {
+        assert_eq!(Amount::from_f32(1.2345)?, Amount::from_units(12_345));
+        assert_eq!(Amount::from_f32(-0.0001)?, Amount::from_units(-1));
+        assert_eq!(Amount::from_f32(5621.2)?, Amount::from_units(56_212_000));
+        assert_eq!(
+            Amount::from_f32(2f32.powi(49))?,
+            Amount::from_units(5_629_499_500_000_000_000)
+        );
+        assert_eq!(Amount::from_f32(1e15), Err(LedgerError::Overflow));
+        assert_eq!(Amount::from_f32(-1e15), Err(LedgerError::Overflow));
+        assert_eq!(Amount::from_f32(f32::NAN), Err(LedgerError::Overflow));
+        assert_eq!(Amount::from_f32(f32::INFINITY), Err(LedgerError::Overflow));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(Amount::new(15, 1), Amount::from_units(15_000));
+        assert_eq!(Amount::new(-3, 0), Amount::from_units(-30_000));
+        assert_eq!(Amount::new(12_345, 4), Amount::from_units(12_345));
+        assert_eq!(Amount::new(0, 2), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_checked_arithmetic() -> anyhow::Result<()> {
+        let one = Amount::from_units(1);
+        assert_eq!(Amount::MAX.checked_sub(one)?.checked_add(one)?, Amount::MAX);
+        assert_eq!(Amount::MAX.checked_add(one), Err(LedgerError::Overflow));
+        assert_eq!(Amount::MIN.checked_sub(one), Err(LedgerError::Overflow));
+        assert_eq!(
+            Amount::ZERO.checked_sub(Amount::MIN),
+            Err(LedgerError::Overflow)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Amount::from_units(12_345).to_string(), "1.2345");
+        assert_eq!(Amount::from_units(-50).to_string(), "-0.005");
+        assert_eq!(Amount::from_units(30_000).to_string(), "3");
+        assert_eq!(Amount::MIN.to_string(), "-922337203685477.5808");
+
+        assert_eq!(format!("{:.2}", Amount::from_units(12_350)), "1.24");
+        assert_eq!(format!("{:.2}", Amount::from_units(12_250)), "1.22");
+        assert_eq!(format!("{:.2}", Amount::from_units(-12_251)), "-1.23");
+        assert_eq!(format!("{:.0}", Amount::from_units(-4_000)), "0");
+        assert_eq!(format!("{:.6}", Amount::from_units(12_345)), "1.234500");
+        assert_eq!(format!("{:>8}", Amount::from_units(-15_000)), "    -1.5");
+    }
+
+    #[test]
+    fn test_from_str() -> anyhow::Result<()> {
+        assert_eq!("1.2345".parse::<Amount>()?, Amount::from_units(12_345));
+        assert_eq!("-0.5".parse::<Amount>()?, Amount::from_units(-5_000));
+        assert_eq!("+.5".parse::<Amount>()?, Amount::from_units(5_000));
+        assert_eq!("7.".parse::<Amount>()?, Amount::from_units(70_000));
+        assert_eq!("1.234500".parse::<Amount>()?, Amount::from_units(12_345));
+        assert_eq!(
+            "1012345.9".parse::<Amount>()?,
+            Amount::from_units(10_123_459_000)
+        );
+        assert_eq!("-922337203685477.5808".parse::<Amount>()?, Amount::MIN);
+
+        for malformed in ["", ".", "-", "1e3", "NaN", "inf", "1,5", " 1"] {
+            assert_eq!(
+                malformed.parse::<Amount>(),
+                Err(AmountError::Malformed),
+                "{malformed}"
+            );
+        }
+        assert_eq!(
+            "1.23456".parse::<Amount>(),
+            Err(AmountError::TooManyDecimals(4))
+        );
+        assert_eq!(
+            "922337203685477.5808".parse::<Amount>(),
+            Err(AmountError::TooLarge(Amount::MAX))
+        );
+        assert_eq!(
+            "1".repeat(40).parse::<Amount>(),
+            Err(AmountError::TooLarge(Amount::MAX))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde() -> anyhow::Result<()> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize((
+            Amount::from_units(15_000),
+            Amount::from_units(30_000),
+            Amount::from_units(-1),
+        ))?;
+        let output = String::from_utf8(writer.into_inner()?)?;
+        assert_eq!(output, "1.5,3.0,-0.0001\n");
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(output.as_bytes());
+        let amounts: (Amount, Amount, Option<Amount>) =
+            reader.deserialize().next().transpose()?.unwrap_or_default();
+        assert_eq!(
+            amounts,
+            (
+                Amount::from_units(15_000),
+                Amount::from_units(30_000),
+                Some(Amount::from_units(-1))
+            )
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use std::{io, path::Path};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    observer::Observer,
+    sha256::Sha256,
+    structs::{ClientRecord, Record},
+};
+
+const HEADER: [&str; 8] = [
+    "entry", "outcome", "type", "client", "tx", "amount", "detail", "hash",
+];
+
+/// The previous hash of the first entry.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Writes every processed record with its outcome as csv, as a trail of the
+/// processing.
+///
+/// Every entry ends with the SHA-256 of the previous hash and its other
+/// fields, so entries cannot be changed, inserted or removed without breaking
+/// the chain, which [`verify`] detects. Truncation at the end can only be
+/// detected by comparing the number of entries or the last hash with a copy.
+///
+/// [`AuditLog::finish`] must be called after the run to report errors, which
+/// cannot be returned from the observer callbacks.
+pub struct AuditLog<W: io::Write> {
+    writer: csv::Writer<W>,
+    entries: u64,
+    hash: String,
+    error: Option<csv::Error>,
+}
+
+impl<W: io::Write> AuditLog<W> {
+    pub fn new(mut writer: csv::Writer<W>) -> Self {
+        let error = writer.write_record(HEADER).err();
+        Self {
+            writer,
+            entries: 0,
+            hash: GENESIS.to_string(),
+            error,
+        }
+    }
+
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write(&mut self, outcome: &str, record: Option<&Record>, detail: &str) {
+        if self.error.is_some() {
+            return;
+        }
+        self.entries += 1;
+        let mut fields = vec![self.entries.to_string(), outcome.to_string()];
+        match record {
+            Some(record) => fields.extend([
+                record.record_type.to_string(),
+                record.client.to_string(),
+                record.tx.to_string(),
+                record
+                    .amount
+                    .map(|amount| amount.to_string())
+                    .unwrap_or_default(),
+            ]),
+            None => fields.extend([String::new(), String::new(), String::new(), String::new()]),
+        }
+        let extra = record
+            .and_then(|record| record.extra.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|(column, value)| format!("{column}={value}"));
+        let source = record
+            .and_then(|record| record.source.as_ref())
+            .map(|source| format!("source={source}"));
+        let detail: Vec<_> = Some(detail.to_string())
+            .filter(|detail| !detail.is_empty())
+            .into_iter()
+            .chain(source)
+            .chain(extra)
+            .collect();
+        fields.push(detail.join("; "));
+        self.hash = chain(&self.hash, &fields);
+        fields.push(self.hash.clone());
+        self.error = self.writer.write_record(&fields).err();
+    }
+}
+
+impl<W: io::Write> Observer for AuditLog<W> {
+    fn on_accepted(&mut self, record: &Record, _account: &ClientRecord) {
+        self.write("accepted", Some(record), "");
+    }
+
+    fn on_rejected(&mut self, row: u64, record: Option<&Record>, error: &anyhow::Error) {
+        self.write("rejected", record, &format!("row {row}: {error:#}"));
+    }
+
+    fn on_quarantined(&mut self, row: u64, record: &Record, reason: &str) {
+        self.write("quarantined", Some(record), &format!("row {row}: {reason}"));
+    }
+}
+
+/// Checks the hash chain of an audit log, returning the number of entries.
+pub fn verify(path: impl AsRef<Path>, delimiter: u8) -> anyhow::Result<u64> {
+    let path = path.as_ref();
+    let reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_path(path)
+        .with_context(|| format!("Failed to read audit log {}", path.display()))?;
+    verify_entries(reader)
+}
+
+fn verify_entries<R: io::Read>(mut reader: csv::Reader<R>) -> anyhow::Result<u64> {
+    if reader.headers()? != HEADER.as_slice() {
+        return Err(anyhow!("Not an audit log, expected the columns {HEADER:?}"));
+    }
+
+    let mut hash = GENESIS.to_string();
+    let mut entries = 0;
+    for entry in reader.records() {
+        let entry = entry?;
+        entries += 1;
+        let fields: Vec<_> = entry.iter().take(HEADER.len() - 1).collect();
+        if fields.first() != Some(&entries.to_string().as_str()) {
+            return Err(anyhow!(
+                "Audit log entry {entries} is missing or out of order"
+            ));
+        }
+        hash = chain(&hash, &fields);
+        if entry.get(HEADER.len() - 1) != Some(hash.as_str()) {
+            return Err(anyhow!("Audit log entry {entries} was tampered with"));
+        }
+    }
+    Ok(entries)
+}
+
+/// Hashes the fields of an entry together with the hash of the previous one.
+fn chain(previous: &str, fields: &[impl AsRef<str>]) -> String {
+    let mut sha256 = Sha256::default();
+    sha256.update(previous.as_bytes());
+    for field in fields {
+        // Separates the fields unambiguously, as they are free text.
+        sha256.update(&(field.as_ref().len() as u64).to_le_bytes());
+        sha256.update(field.as_ref().as_bytes());
+    }
+    sha256.finalize_hex()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{amount::Amount, engine::EngineBuilder, structs::RecordType};
+
+    fn record(record_type: RecordType, tx: u32, amount: Option<Amount>) -> anyhow::Result<Record> {
+        Ok(Record {
+            record_type,
+            client: 1,
+            tx,
+            amount,
+            ..Default::default()
+        })
+    }
+
+    fn audit_log() -> anyhow::Result<String> {
+        let audit_log = Rc::new(RefCell::new(AuditLog::new(csv::Writer::from_writer(
+            Vec::new(),
+        ))));
+        let mut engine = EngineBuilder::new().observer(audit_log.clone()).build();
+        engine.run([
+            record(RecordType::Deposit, 1, Some(Amount::new(2, 0))),
+            record(RecordType::Withdrawal, 2, Some(Amount::new(3, 0))),
+            record(RecordType::Dispute, 1, None),
+        ])?;
+        drop(engine);
+
+        let mut audit_log = Rc::try_unwrap(audit_log)
+            .map_err(|_| anyhow!("audit log is still shared"))?
+            .into_inner();
+        audit_log.finish()?;
+        Ok(String::from_utf8(audit_log.writer.into_inner()?)?)
+    }
+
+    fn verify_str(log: &str) -> anyhow::Result<u64> {
+        verify_entries(csv::Reader::from_reader(log.as_bytes()))
+    }
+
+    #[test]
+    fn test_audit_log() -> anyhow::Result<()> {
+        let log = audit_log()?;
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines[0], "entry,outcome,type,client,tx,amount,detail,hash");
+        assert!(lines[1].starts_with("1,accepted,deposit,1,1,2,,"));
+        assert!(lines[2].starts_with("2,rejected,withdrawal,1,2,3,row 2: "));
+        assert!(lines[3].starts_with("3,accepted,dispute,1,1,,,"));
+        assert_eq!(verify_str(&log)?, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_log_extra_columns() -> anyhow::Result<()> {
+        let audit_log = Rc::new(RefCell::new(AuditLog::new(csv::Writer::from_writer(
+            Vec::new(),
+        ))));
+        let mut engine = EngineBuilder::new().observer(audit_log.clone()).build();
+        let extra = Some([("memo".to_string(), "rent".to_string())].into());
+        engine.run([
+            record(RecordType::Deposit, 1, Some(Amount::new(2, 0))).map(|record| Record {
+                extra: extra.clone(),
+                ..record
+            }),
+            record(RecordType::Withdrawal, 2, Some(Amount::new(3, 0)))
+                .map(|record| Record { extra, ..record }),
+        ])?;
+        drop(engine);
+
+        let mut audit_log = Rc::try_unwrap(audit_log)
+            .map_err(|_| anyhow!("audit log is still shared"))?
+            .into_inner();
+        audit_log.finish()?;
+        let log = String::from_utf8(audit_log.writer.into_inner()?)?;
+        let lines: Vec<_> = log.lines().collect();
+        assert!(lines[1].starts_with("1,accepted,deposit,1,1,2,memo=rent,"));
+        assert!(lines[2].starts_with("2,rejected,withdrawal,1,2,3,row 2: "));
+        assert!(lines[2].contains("; memo=rent,"));
+        assert_eq!(verify_str(&log)?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_detects_tampering() -> anyhow::Result<()> {
+        let log = audit_log()?;
+        assert!(verify_str(&log.replacen("deposit,1,1,2,", "deposit,1,1,20,", 1)).is_err());
+
+        let lines: Vec<_> = log.lines().collect();
+        let removed = [lines[0], lines[1], lines[3]].join("\n");
+        assert!(verify_str(&removed).is_err());
+        assert!(verify_str("tx,amount\n1,2\n").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use serde::Serialize;
+
+use crate::{
+    amount::Amount,
+    error::LedgerError,
+    snapshot::{AccountSnapshot, Snapshot},
+    store::{TransactionKind, TransactionState},
+};
+
+/// A broken invariant of a snapshot, a row of the `check` command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    /// The client of the account, empty for invariants across all accounts.
+    pub client: Option<u16>,
+    pub invariant: &'static str,
+    pub expected: Amount,
+    pub actual: Amount,
+    /// The transactions the expected amount is derived from, separated by
+    /// spaces.
+    pub txs: String,
+}
+
+/// Verifies the invariants of the persisted state:
+///
+/// - `total`: the total of an account is the sum of its deposits that were
+///   not charged back, minus its withdrawals.
+/// - `held`: the held funds of an account are the sum of its disputed
+///   deposits.
+/// - `negative_held`: no account holds negative funds.
+/// - `net`: the totals of all accounts add up to the running totals of a full
+///   snapshot.
+///
+/// Amounts are summed up with checked arithmetic and compared exactly. The
+/// total of an account can only be derived from its transactions if the
+/// snapshot has the full history, so the `total` invariant is skipped for
+/// snapshots with opening balances, adjustments or write-offs and for those
+/// of runs that did not retain every transaction, see [`Snapshot::truncated`].
+/// Disputed deposits are retained under every policy, so the other
+/// invariants are always checked.
+pub fn check(snapshot: &Snapshot) -> Result<Vec<Violation>, LedgerError> {
+    let full_history = !snapshot.truncated
+        && snapshot.opening == Amount::ZERO
+        && snapshot.adjustments == Amount::ZERO
+        && snapshot.losses == Amount::ZERO;
+
+    let mut violations = Vec::new();
+    for account in &snapshot.accounts {
+        if full_history {
+            let total = derive(account, |kind, state| match kind {
+                TransactionKind::Deposit if state != TransactionState::ChargedBack => Some(1),
+                TransactionKind::Deposit => None,
+                TransactionKind::Withdrawal => Some(-1),
+            })?;
+            violations.extend(violation(account, "total", total, account.total));
+        }
+
+        let held = derive(account, |kind, state| {
+            (kind == TransactionKind::Deposit && state == TransactionState::Disputed).then_some(1)
+        })?;
+        violations.extend(violation(account, "held", held, account.held));
+
+        if account.held < Amount::ZERO {
+            violations.push(Violation {
+                client: Some(account.client),
+                invariant: "negative_held",
+                expected: Amount::ZERO,
+                actual: account.held,
+                txs: String::new(),
+            });
+        }
+    }
+
+    if !snapshot.delta {
+        let mut expected = Amount::ZERO;
+        for (value, sign) in [
+            (snapshot.opening, 1),
+            (snapshot.deposits, 1),
+            (snapshot.adjustments, 1),
+            (snapshot.losses, 1),
+            (snapshot.withdrawals, -1),
+            (snapshot.charged_back, -1),
+        ] {
+            expected = add(expected, value, sign)?;
+        }
+        let mut actual = Amount::ZERO;
+        for account in &snapshot.accounts {
+            actual = add(actual, account.total, 1)?;
+        }
+        if expected != actual {
+            violations.push(Violation {
+                client: None,
+                invariant: "net",
+                expected,
+                actual,
+                txs: String::new(),
+            });
+        }
+    }
+
+    Ok(violations)
+}
+
+/// Adds or, with a negative sign, subtracts an amount.
+fn add(sum: Amount, amount: Amount, sign: i8) -> Result<Amount, LedgerError> {
+    if sign < 0 {
+        sum.checked_sub(amount)
+    } else {
+        sum.checked_add(amount)
+    }
+}
+
+/// Sums up the transactions of the account that `sign` includes, with the
+/// sign it returns, together with their tx ids.
+fn derive(
+    account: &AccountSnapshot,
+    sign: impl Fn(TransactionKind, TransactionState) -> Option<i8>,
+) -> Result<(Amount, Vec<u32>), LedgerError> {
+    let mut sum = Amount::ZERO;
+    let mut txs = Vec::new();
+    for transaction in &account.transactions {
+        if let Some(sign) = sign(transaction.kind, transaction.state) {
+            sum = add(sum, transaction.amount, sign)?;
+            txs.push(transaction.tx);
+        }
+    }
+    Ok((sum, txs))
+}
+
+fn violation(
+    account: &AccountSnapshot,
+    invariant: &'static str,
+    (expected, txs): (Amount, Vec<u32>),
+    actual: Amount,
+) -> Option<Violation> {
+    (expected != actual).then(|| Violation {
+        client: Some(account.client),
+        invariant,
+        expected,
+        actual,
+        txs: txs.iter().map(u32::to_string).collect::<Vec<_>>().join(" "),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{Ledger, RetentionPolicy};
+
+    #[test]
+    fn test_check() -> anyhow::Result<()> {
+        let mut ledger = Ledger::new();
+        ledger.deposit(1, 1, Amount::new(10, 0))?;
+        ledger.deposit(1, 2, Amount::new(5, 0))?;
+        ledger.withdraw(1, 3, Amount::new(3, 0))?;
+        ledger.dispute(1, 2)?;
+        ledger.deposit(2, 4, Amount::new(7, 0))?;
+        ledger.dispute(2, 4)?;
+        ledger.chargeback(2, 4)?;
+
+        let mut snapshot = ledger.snapshot();
+        assert_eq!(check(&snapshot)?, []);
+
+        snapshot.accounts[0].held = Amount::new(-5, 0);
+        snapshot.accounts[1].total = Amount::new(1, 0);
+        let violations = check(&snapshot)?;
+        assert_eq!(
+            violations
+                .iter()
+                .map(|violation| (violation.client, violation.invariant))
+                .collect::<Vec<_>>(),
+            [
+                (Some(1), "held"),
+                (Some(1), "negative_held"),
+                (Some(2), "total"),
+                (None, "net"),
+            ]
+        );
+        assert_eq!(violations[0].txs, "2");
+        assert_eq!(violations[0].expected, Amount::new(5, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_exact() -> anyhow::Result<()> {
+        let mut ledger = Ledger::new();
+        for tx in 1..=10 {
+            ledger.deposit(1, tx, Amount::new(1, 1))?;
+        }
+        ledger.deposit(2, 11, Amount::new(10_123_459, 1))?;
+        let mut snapshot = ledger.snapshot();
+        assert_eq!(check(&snapshot)?, []);
+
+        // Off by the smallest amount.
+        snapshot.accounts[0].total = Amount::new(10_001, 4);
+        assert_eq!(
+            check(&snapshot)?
+                .iter()
+                .map(|violation| (violation.client, violation.invariant))
+                .collect::<Vec<_>>(),
+            [(Some(1), "total"), (None, "net")]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_truncated_history() -> anyhow::Result<()> {
+        let mut ledger = Ledger::new().with_retention(RetentionPolicy::DisputeWindow(1));
+        ledger.deposit(1, 1, Amount::new(10, 0))?;
+        ledger.deposit(1, 2, Amount::new(5, 0))?;
+        ledger.withdraw(1, 3, Amount::new(3, 0))?;
+        ledger.dispute(1, 2)?;
+
+        let mut snapshot = ledger.snapshot();
+        assert!(snapshot.truncated);
+        assert_eq!(check(&snapshot)?, []);
+
+        snapshot.accounts[0].held = Amount::new(1, 0);
+        assert_eq!(
+            check(&snapshot)?
+                .iter()
+                .map(|violation| (violation.client, violation.invariant))
+                .collect::<Vec<_>>(),
+            [(Some(1), "held")]
+        );
+
+        Ok(())
+    }
+}
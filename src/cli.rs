@@ -0,0 +1,1673 @@
+use anyhow::{anyhow, Context};
+
+use crate::{
+    account::{EmptyAccountPolicy, FreezePolicy, RetentionPolicy},
+    amount::{self, Amount},
+    client_ids::ClientIdRanges,
+    export::ExportFormat,
+    history::HistorySampling,
+    input::{InputFormat, InputOptions},
+    output::{FixedWidthLayout, OutputFormat, Precision},
+    payout::SettlementFormat,
+    replay,
+    structs::{AmountLimits, NumberFormat},
+    system::SystemAccounts,
+};
+
+/// Number of accounts listed by the `concentration` report, unless overridden.
+pub const DEFAULT_TOP: usize = 10;
+
+/// Number of most recent deposits per customer kept by the
+/// `dispute-window` retention policy, unless overridden.
+const DEFAULT_DISPUTE_WINDOW: usize = 1_000;
+
+/// What the engine outputs after processing the transactions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Print the final state of every account.
+    #[default]
+    Accounts,
+    /// Print a trial balance with control totals across all accounts.
+    Report,
+    /// Print the largest accounts and their share of all funds.
+    Concentration,
+    /// Print the exposure and chargeback rate per counterparty.
+    Counterparties,
+    /// Print the totals per client and tag.
+    Tags,
+    /// Print every adjustment and write-off with its reason code, for audit.
+    Adjustments,
+    /// Print the sections of a snapshot file instead of processing transactions.
+    InspectSnapshot,
+    /// Merge delta snapshots into the full snapshot they are based on.
+    CompactSnapshot,
+    /// Fold the transactions that can no longer be disputed under the
+    /// retention policy into opening balances.
+    FoldSnapshot,
+    /// Verify the invariants of a snapshot file.
+    CheckSnapshot,
+    /// Verify the hash chain of an audit log.
+    VerifyAudit,
+    /// Print the journal in a plain-text accounting format.
+    Export,
+    /// Print the balances of a client as of a point in time.
+    Balance,
+    /// Run the input through a second engine configuration and print the
+    /// accounts that differ.
+    Shadow,
+    /// Pay out funds by the payout instructions and print the settlement
+    /// file for the bank.
+    Payout,
+    /// Apply the approved records of a quarantine file and print the final
+    /// state of every account.
+    ApplyQuarantine,
+    /// Sign the admin records of a file with the admin key and print them.
+    SignAdmin,
+    /// Read ledger commands interactively, optionally starting from a
+    /// snapshot or transactions file.
+    Repl,
+}
+
+/// Command line options of the engine.
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub file_path: String,
+    pub retention: RetentionPolicy,
+    pub freeze: FreezePolicy,
+    /// What happens to accounts that were never funded, `omit` unless set.
+    pub empty_accounts: EmptyAccountPolicy,
+    /// Leave accounts without a total or held balance that are not locked
+    /// out of the accounts output.
+    pub omit_zero_accounts: bool,
+    pub amount_limits: AmountLimits,
+    /// The client ids records may use.
+    pub client_ids: ClientIdRanges,
+    /// Client ids of the internal accounts to list after the accounts.
+    pub system_accounts: Option<SystemAccounts>,
+    /// Where to write the double-entry postings journal to, if at all.
+    pub journal: Option<String>,
+    /// Accounts csv of a previous run to seed the ledger with.
+    pub opening_balances: Option<String>,
+    /// Transactions csv of a previous run that disputes may reference.
+    pub opening_transactions: Option<String>,
+    /// How the transaction input files are read.
+    pub input: InputOptions,
+    /// Manifest with the expected row count and hash of the input.
+    pub manifest: Option<String>,
+    /// Where to write the manifest of the run to, if at all.
+    pub run_manifest: Option<String>,
+    /// Where to write the control totals of the accounts output to, if at
+    /// all.
+    pub control_file: Option<String>,
+    /// Registry of the content hashes of previously processed inputs.
+    pub registry: Option<String>,
+    /// Abort on the first invalid or rejected record.
+    pub strict: bool,
+    /// Park disputes of transactions that have not arrived yet.
+    pub defer_disputes: bool,
+    /// Seconds by which records may be out of order and are reordered by
+    /// their timestamps, if at all.
+    pub reorder_window: Option<u64>,
+    /// Whether to check the schema of the whole input before applying any
+    /// record.
+    pub validate_schema: bool,
+    /// Snapshot of a previous run to continue from.
+    pub restore: Option<String>,
+    /// Where to write a snapshot of the final state to, if at all.
+    pub snapshot: Option<String>,
+    /// Only include accounts modified during this run in the snapshot.
+    pub delta: bool,
+    /// Delta snapshots to merge, in order, for `snapshot compact`.
+    pub deltas: Vec<String>,
+    pub export_format: ExportFormat,
+    pub output_format: OutputFormat,
+    /// Layout of the `fixed` output format.
+    pub fixed_width: FixedWidthLayout,
+    /// Decimal places of the amounts in the accounts output.
+    pub precision: Precision,
+    /// Separators of the amounts in the accounts output.
+    pub output_number_format: NumberFormat,
+    /// Key file to pseudonymize client ids in the output with.
+    pub pseudonymize: Option<String>,
+    /// Where to write the mapping of client ids to pseudonyms to, if at all.
+    pub pseudonym_map: Option<String>,
+    /// Template file to render every account with instead of writing csv.
+    pub template: Option<String>,
+    /// Commodity of exported amounts, if not the default one.
+    pub commodity: Option<String>,
+    /// Client of the `balance` command.
+    pub client: Option<u16>,
+    /// Replay the input up to this time, in seconds since the unix epoch.
+    pub at: Option<u64>,
+    /// Engine options of the shadow run of the `shadow` command.
+    pub against: Option<String>,
+    /// Payout instructions file of the `payout` command.
+    pub instructions: Option<String>,
+    pub settlement_format: SettlementFormat,
+    /// Include chargeback counts and freeze rules in the accounts csv.
+    pub extended: bool,
+    /// Account groups file whose clients pool their available funds.
+    pub groups: Option<String>,
+    /// Directory to write the accounts of every tenant to, if the input is
+    /// split into isolated ledgers by its `tenant` column.
+    pub tenants: Option<String>,
+    /// Configuration overrides per tenant.
+    pub tenant_settings: Option<String>,
+    /// Key file that admin records must be signed with, if they are accepted.
+    pub admin_key: Option<String>,
+    /// Where to write records that fail a risk check to, if they are to be
+    /// quarantined instead of rejected.
+    pub quarantine: Option<String>,
+    /// Where to write the balances of the clients over time to, if at all.
+    pub balance_history: Option<String>,
+    pub history_sampling: HistorySampling,
+    /// Where to write the hash-chained audit log of every processed record
+    /// to, if at all.
+    pub audit_log: Option<String>,
+    /// Where to write records that could not be read or were rejected to,
+    /// if at all.
+    pub dead_letter: Option<String>,
+    /// Number of accounts listed by the `concentration` report, if not the
+    /// default one.
+    pub top: Option<usize>,
+    /// The most transactions to apply per second, if limited.
+    pub max_tps: Option<u32>,
+    /// Where to write the processing latencies per stage to, if at all.
+    pub latency: Option<String>,
+    /// The number of clients to preallocate room for, if known.
+    pub expected_clients: Option<usize>,
+    /// Whether the input is grouped by client, so accounts are written as
+    /// soon as the records of the next client start.
+    pub grouped_by_client: bool,
+}
+
+impl Options {
+    /// Parses the given arguments, excluding the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut options = Self::default();
+        let mut file_path = None;
+        let mut retain = String::from("all");
+        let mut dispute_window = DEFAULT_DISPUTE_WINDOW;
+        let mut empty_accounts = None;
+
+        let mut args = args.into_iter().peekable();
+        match args.peek().map(String::as_str) {
+            Some("report") => {
+                options.command = Command::Report;
+                args.next();
+            }
+            Some("concentration") => {
+                options.command = Command::Concentration;
+                args.next();
+            }
+            Some("counterparties") => {
+                options.command = Command::Counterparties;
+                args.next();
+            }
+            Some("tags") => {
+                options.command = Command::Tags;
+                args.next();
+            }
+            Some("adjustments") => {
+                options.command = Command::Adjustments;
+                args.next();
+            }
+            Some("apply-quarantine") => {
+                options.command = Command::ApplyQuarantine;
+                args.next();
+            }
+            Some("sign-admin") => {
+                options.command = Command::SignAdmin;
+                args.next();
+            }
+            Some("repl") => {
+                options.command = Command::Repl;
+                args.next();
+            }
+            Some("export") => {
+                options.command = Command::Export;
+                args.next();
+            }
+            Some("balance") => {
+                options.command = Command::Balance;
+                args.next();
+            }
+            Some("verify-audit") => {
+                options.command = Command::VerifyAudit;
+                args.next();
+            }
+            Some("check") => {
+                options.command = Command::CheckSnapshot;
+                args.next();
+            }
+            Some("shadow") => {
+                options.command = Command::Shadow;
+                args.next();
+            }
+            Some("payout") => {
+                options.command = Command::Payout;
+                args.next();
+            }
+            Some("snapshot") => {
+                args.next();
+                match args.next().as_deref() {
+                    Some("inspect") => options.command = Command::InspectSnapshot,
+                    Some("compact") => options.command = Command::CompactSnapshot,
+                    Some("fold") => options.command = Command::FoldSnapshot,
+                    _ => {
+                        return Err(anyhow!(
+                            "Expected a snapshot subcommand, one of: inspect, compact, fold"
+                        ))
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if arg.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+
+            match flag.as_str() {
+                "--retain" => retain = flag_value(&flag, inline_value, &mut args)?,
+                "--dispute-window" => {
+                    dispute_window = flag_value(&flag, inline_value, &mut args)?
+                        .parse()
+                        .context("--dispute-window expects a number of transactions")?;
+                }
+                "--empty-accounts" => {
+                    empty_accounts = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--freeze" => {
+                    options
+                        .freeze
+                        .set_rules(&flag_value(&flag, inline_value, &mut args)?)?
+                }
+                "--max-decimals" => {
+                    let max_decimals: u32 = flag_value(&flag, inline_value, &mut args)?
+                        .parse()
+                        .context("--max-decimals expects a number of decimal places")?;
+                    if max_decimals > amount::DECIMALS {
+                        return Err(anyhow!(
+                            "--max-decimals cannot exceed {}, the decimal places of an amount",
+                            amount::DECIMALS
+                        ));
+                    }
+                    options.amount_limits.max_decimals = max_decimals;
+                }
+                "--allowed-clients" => {
+                    options
+                        .client_ids
+                        .set_allowed(&flag_value(&flag, inline_value, &mut args)?)?
+                }
+                "--reserved-clients" => {
+                    options
+                        .client_ids
+                        .reserve(&flag_value(&flag, inline_value, &mut args)?)?
+                }
+                "--system-accounts" => {
+                    options.system_accounts =
+                        Some(flag_value(&flag, inline_value, &mut args)?.parse()?)
+                }
+                "--max-amount" => {
+                    let max_amount: Amount = flag_value(&flag, inline_value, &mut args)?
+                        .parse()
+                        .context("--max-amount expects an amount")?;
+                    if max_amount < Amount::ZERO {
+                        return Err(anyhow!("--max-amount expects a positive amount"));
+                    }
+                    options.amount_limits.max_amount = max_amount;
+                }
+                "--extended" => options.extended = true,
+                "--grouped-by-client" => options.grouped_by_client = true,
+                "--omit-zero-accounts" => options.omit_zero_accounts = true,
+                "--balance-history" => {
+                    options.balance_history = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--dead-letter" => {
+                    options.dead_letter = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--expected-clients" => {
+                    options.expected_clients = Some(
+                        flag_value(&flag, inline_value, &mut args)?
+                            .parse()
+                            .context("--expected-clients expects a number of clients")?,
+                    );
+                }
+                "--latency" => options.latency = Some(flag_value(&flag, inline_value, &mut args)?),
+                "--audit-log" => {
+                    options.audit_log = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--history-sampling" => {
+                    options.history_sampling =
+                        flag_value(&flag, inline_value, &mut args)?.parse()?
+                }
+                "--top" => {
+                    options.top = Some(
+                        flag_value(&flag, inline_value, &mut args)?
+                            .parse()
+                            .context("--top expects a number of accounts")?,
+                    );
+                }
+                "--max-tps" => {
+                    let max_tps = flag_value(&flag, inline_value, &mut args)?
+                        .parse()
+                        .context("--max-tps expects a number of transactions per second")?;
+                    if max_tps == 0 {
+                        return Err(anyhow!(
+                            "--max-tps expects at least 1 transaction per second"
+                        ));
+                    }
+                    options.max_tps = Some(max_tps);
+                }
+                "--tenants" => options.tenants = Some(flag_value(&flag, inline_value, &mut args)?),
+                "--tenant-settings" => {
+                    options.tenant_settings = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--admin-key" => {
+                    options.admin_key = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--quarantine" => {
+                    options.quarantine = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--groups" => options.groups = Some(flag_value(&flag, inline_value, &mut args)?),
+                "--journal" => options.journal = Some(flag_value(&flag, inline_value, &mut args)?),
+                "--opening-balances" => {
+                    options.opening_balances = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--opening-transactions" => {
+                    options.opening_transactions = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--number-format" => {
+                    options.input.number_format =
+                        flag_value(&flag, inline_value, &mut args)?.parse()?
+                }
+                "--output-number-format" => {
+                    options.output_number_format =
+                        flag_value(&flag, inline_value, &mut args)?.parse()?
+                }
+                "--input-format" => {
+                    options.input.format = flag_value(&flag, inline_value, &mut args)?.parse()?
+                }
+                "--no-headers" => options.input.no_headers = true,
+                "--extra-columns" => options.input.extra_columns = true,
+                "--strict-types" => options.input.strict_types = true,
+                "--validate-schema" => options.validate_schema = true,
+                "--strict" => options.strict = true,
+                "--defer-disputes" => options.defer_disputes = true,
+                "--reorder-window" => {
+                    options.reorder_window = Some(
+                        flag_value(&flag, inline_value, &mut args)?
+                            .parse()
+                            .context("--reorder-window expects a number of seconds")?,
+                    );
+                }
+                "--format" => {
+                    options.export_format = flag_value(&flag, inline_value, &mut args)?.parse()?
+                }
+                "--output-format" => {
+                    options.output_format = flag_value(&flag, inline_value, &mut args)?.parse()?
+                }
+                "--widths" => {
+                    options
+                        .fixed_width
+                        .set_widths(&flag_value(&flag, inline_value, &mut args)?)?
+                }
+                "--precision" => {
+                    options
+                        .precision
+                        .set(&flag_value(&flag, inline_value, &mut args)?)?
+                }
+                "--padding" => {
+                    let padding = flag_value(&flag, inline_value, &mut args)?;
+                    let mut chars = padding.chars();
+                    options.fixed_width.padding = match (chars.next(), chars.next()) {
+                        (Some(padding), None) => padding,
+                        _ => return Err(anyhow!("--padding expects a single character")),
+                    };
+                }
+                "--pseudonymize" => {
+                    options.pseudonymize = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--pseudonym-map" => {
+                    options.pseudonym_map = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--template" => {
+                    options.template = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--client" => {
+                    options.client = Some(
+                        flag_value(&flag, inline_value, &mut args)?
+                            .parse()
+                            .context("--client expects a client id")?,
+                    );
+                }
+                "--at" => {
+                    options.at = Some(replay::parse_time(&flag_value(
+                        &flag,
+                        inline_value,
+                        &mut args,
+                    )?)?);
+                }
+                "--against" => options.against = Some(flag_value(&flag, inline_value, &mut args)?),
+                "--instructions" => {
+                    options.instructions = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--settlement-format" => {
+                    options.settlement_format =
+                        flag_value(&flag, inline_value, &mut args)?.parse()?
+                }
+                "--commodity" => {
+                    options.commodity = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--delta" => options.delta = true,
+                "--manifest" => {
+                    options.manifest = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--control-file" => {
+                    options.control_file = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--run-manifest" => {
+                    options.run_manifest = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--restore" => options.restore = Some(flag_value(&flag, inline_value, &mut args)?),
+                "--snapshot" => {
+                    options.snapshot = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--registry" => {
+                    options.registry = Some(flag_value(&flag, inline_value, &mut args)?)
+                }
+                "--delimiter" => {
+                    options
+                        .input
+                        .set_delimiter(&flag_value(&flag, inline_value, &mut args)?)?
+                }
+                "--map" => options.input.add_header_mappings(&flag_value(
+                    &flag,
+                    inline_value,
+                    &mut args,
+                )?)?,
+                "--map-file" => options.input.add_header_mapping_file(flag_value(
+                    &flag,
+                    inline_value,
+                    &mut args,
+                )?)?,
+                flag if flag.starts_with("--") => {
+                    return Err(anyhow!("Unknown option: {flag}"));
+                }
+                _ if file_path.is_none() => file_path = Some(arg),
+                _ if options.command == Command::CompactSnapshot => options.deltas.push(arg),
+                _ => return Err(anyhow!("Unexpected argument: {arg}")),
+            }
+        }
+
+        options.retention = match retain.as_str() {
+            "all" => RetentionPolicy::All,
+            "dispute-window" => RetentionPolicy::DisputeWindow(dispute_window),
+            "none" => RetentionPolicy::None,
+            other => {
+                return Err(anyhow!(
+                    "Unknown retention policy {other}, expected one of: all, dispute-window, none"
+                ))
+            }
+        };
+
+        options.empty_accounts = match empty_accounts.as_deref() {
+            None => EmptyAccountPolicy::default(),
+            Some("keep") => EmptyAccountPolicy::Keep,
+            Some("omit") => EmptyAccountPolicy::Omit,
+            Some("remove") => EmptyAccountPolicy::Remove,
+            Some(other) => {
+                return Err(anyhow!(
+                    "Unknown empty account policy {other}, expected one of: keep, omit, remove"
+                ))
+            }
+        };
+
+        if let Some(system_accounts) = &options.system_accounts {
+            for client in system_accounts.clients() {
+                options.client_ids.reserve_client(client);
+            }
+        }
+
+        if options.command == Command::Repl {
+            file_path = file_path.or(Some(String::new()));
+        }
+        options.file_path = file_path.ok_or(anyhow!(
+            "Expected exactly one argument: the path to the transaction csv file."
+        ))?;
+
+        if options.pseudonymize.is_some()
+            && (!matches!(
+                options.command,
+                Command::Accounts | Command::ApplyQuarantine
+            ) || options.output_format != OutputFormat::Csv
+                || options.template.is_some()
+                || options.journal.is_some()
+                || options.snapshot.is_some()
+                || options.balance_history.is_some()
+                || options.audit_log.is_some()
+                || options.dead_letter.is_some()
+                || options.quarantine.is_some()
+                || options.latency.is_some()
+                || options.control_file.is_some())
+        {
+            return Err(anyhow!(
+                "--pseudonymize only supports the csv accounts output, without other outputs that \
+                 reveal client ids"
+            ));
+        }
+        if options.extended
+            && (!matches!(
+                options.command,
+                Command::Accounts | Command::ApplyQuarantine
+            ) || options.output_format != OutputFormat::Csv
+                || options.template.is_some()
+                || options.pseudonymize.is_some())
+        {
+            return Err(anyhow!(
+                "--extended only supports the csv accounts output, without pseudonymization"
+            ));
+        }
+        if (options.precision.is_set() || options.output_number_format != NumberFormat::Standard)
+            && (!matches!(
+                options.command,
+                Command::Accounts | Command::ApplyQuarantine | Command::Balance
+            ) || options.template.is_some()
+                || options.extended
+                || options.groups.is_some())
+        {
+            return Err(anyhow!(
+                "--precision and --output-number-format only support the plain accounts output, \
+                 without templates, --extended or --groups"
+            ));
+        }
+        if options.groups.is_some() && (options.extended || options.pseudonymize.is_some()) {
+            return Err(anyhow!(
+                "--groups cannot be combined with --extended or --pseudonymize"
+            ));
+        }
+        if options.quarantine.is_some()
+            && matches!(options.command, Command::ApplyQuarantine | Command::Repl)
+        {
+            return Err(anyhow!(
+                "--quarantine cannot be combined with apply-quarantine or repl"
+            ));
+        }
+        if options.tenants.is_some()
+            && (options.command != Command::Accounts
+                || options.output_format != OutputFormat::Csv
+                || options.template.is_some()
+                || options.pseudonymize.is_some()
+                || options.extended
+                || options.groups.is_some()
+                || options.journal.is_some()
+                || options.restore.is_some()
+                || options.snapshot.is_some()
+                || options.opening_balances.is_some()
+                || options.opening_transactions.is_some()
+                || options.balance_history.is_some()
+                || options.audit_log.is_some()
+                || options.dead_letter.is_some()
+                || options.latency.is_some()
+                || options.run_manifest.is_some()
+                || options.control_file.is_some()
+                || options.quarantine.is_some())
+        {
+            return Err(anyhow!(
+                "--tenants only supports the csv accounts output, without options that read or \
+                 write other state"
+            ));
+        }
+        if options.grouped_by_client
+            && (options.command != Command::Accounts
+                || options.output_format != OutputFormat::Csv
+                || options.template.is_some()
+                || options.pseudonymize.is_some()
+                || options.extended
+                || options.groups.is_some()
+                || options.journal.is_some()
+                || options.restore.is_some()
+                || options.snapshot.is_some()
+                || options.opening_balances.is_some()
+                || options.opening_transactions.is_some()
+                || options.defer_disputes
+                || options.tenants.is_some())
+        {
+            return Err(anyhow!(
+                "--grouped-by-client only supports the csv accounts output, without options \
+                 that need all accounts at the end"
+            ));
+        }
+        if options.validate_schema
+            && (options.input.format != InputFormat::Csv
+                || matches!(
+                    options.command,
+                    Command::InspectSnapshot
+                        | Command::CompactSnapshot
+                        | Command::FoldSnapshot
+                        | Command::VerifyAudit
+                        | Command::CheckSnapshot
+                        | Command::ApplyQuarantine
+                        | Command::Repl
+                ))
+        {
+            return Err(anyhow!(
+                "--validate-schema only supports commands that read csv transactions"
+            ));
+        }
+        if options.control_file.is_some()
+            && (!matches!(
+                options.command,
+                Command::Accounts | Command::ApplyQuarantine
+            ) || options.precision.is_set()
+                || options.groups.is_some()
+                || options.template.is_some())
+        {
+            return Err(anyhow!(
+                "--control-file requires the accounts output with its exact amounts, without \
+                 --precision, --groups or --template"
+            ));
+        }
+        if options.system_accounts.is_some()
+            && (!matches!(
+                options.command,
+                Command::Accounts | Command::ApplyQuarantine
+            ) || options.output_format != OutputFormat::Csv
+                || options.template.is_some()
+                || options.pseudonymize.is_some()
+                || options.extended
+                || options.groups.is_some()
+                || options.grouped_by_client
+                || options.tenants.is_some())
+        {
+            return Err(anyhow!(
+                "--system-accounts only supports the plain csv accounts output"
+            ));
+        }
+        if options.omit_zero_accounts
+            && !matches!(
+                options.command,
+                Command::Accounts | Command::ApplyQuarantine
+            )
+        {
+            return Err(anyhow!("--omit-zero-accounts requires the accounts output"));
+        }
+        if options.tenant_settings.is_some() && options.tenants.is_none() {
+            return Err(anyhow!("--tenant-settings requires --tenants"));
+        }
+        if options.pseudonym_map.is_some() && options.pseudonymize.is_none() {
+            return Err(anyhow!("--pseudonym-map requires --pseudonymize"));
+        }
+
+        if (options.command == Command::Balance)
+            != (options.client.is_some() && options.at.is_some())
+        {
+            return Err(anyhow!(
+                "balance expects --client and --at, which only it uses"
+            ));
+        }
+
+        if (options.command == Command::Shadow) != options.against.is_some() {
+            return Err(anyhow!(
+                "shadow expects --against, which is only used by shadow"
+            ));
+        }
+
+        if (options.command == Command::Payout) != options.instructions.is_some() {
+            return Err(anyhow!(
+                "payout expects --instructions, which is only used by payout"
+            ));
+        }
+
+        if options.command == Command::SignAdmin && options.admin_key.is_none() {
+            return Err(anyhow!(
+                "sign-admin expects --admin-key with the key to sign with"
+            ));
+        }
+
+        if options.command == Command::CompactSnapshot && options.snapshot.is_none() {
+            return Err(anyhow!(
+                "snapshot compact expects --snapshot with the path of the compacted snapshot"
+            ));
+        }
+
+        if options.command == Command::FoldSnapshot && options.snapshot.is_none() {
+            return Err(anyhow!(
+                "snapshot fold expects --snapshot with the path of the folded snapshot"
+            ));
+        }
+
+        Ok(options)
+    }
+}
+
+fn flag_value(
+    flag: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = String>,
+) -> anyhow::Result<String> {
+    inline_value
+        .or_else(|| args.next())
+        .ok_or(anyhow!("Missing value for {flag}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Options> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_file_path() -> anyhow::Result<()> {
+        let options = parse(&["transactions.csv"])?;
+        assert_eq!(
+            options,
+            Options {
+                file_path: "transactions.csv".to_string(),
+                ..Default::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_missing_file_path() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["a.csv", "b.csv"]).is_err());
+        assert!(parse(&["--unknown", "a.csv"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_report() -> anyhow::Result<()> {
+        let options = parse(&["report", "a.csv"])?;
+        assert_eq!(options.command, Command::Report);
+        assert_eq!(options.file_path, "a.csv");
+
+        assert_eq!(parse(&["a.csv"])?.command, Command::Accounts);
+        assert_eq!(
+            parse(&["adjustments", "a.csv"])?.command,
+            Command::Adjustments
+        );
+        assert!(parse(&["report"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_quarantine() -> anyhow::Result<()> {
+        let options = parse(&["--quarantine", "quarantine.csv", "a.csv"])?;
+        assert_eq!(options.command, Command::Accounts);
+        assert_eq!(options.quarantine.as_deref(), Some("quarantine.csv"));
+
+        let options = parse(&["apply-quarantine", "--restore=a.snap", "quarantine.csv"])?;
+        assert_eq!(options.command, Command::ApplyQuarantine);
+        assert_eq!(options.file_path, "quarantine.csv");
+        assert!(parse(&["apply-quarantine", "--quarantine=q.csv", "quarantine.csv"]).is_err());
+        assert!(parse(&["repl", "--quarantine=q.csv"]).is_err());
+        assert!(parse(&["a.csv", "--quarantine"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_snapshot() -> anyhow::Result<()> {
+        let options = parse(&["snapshot", "inspect", "state.snap"])?;
+        assert_eq!(options.command, Command::InspectSnapshot);
+        assert_eq!(options.file_path, "state.snap");
+        assert!(parse(&["snapshot", "state.snap"]).is_err());
+
+        let options = parse(&["--restore", "old.snap", "--snapshot=new.snap", "a.csv"])?;
+        assert_eq!(options.restore.as_deref(), Some("old.snap"));
+        assert_eq!(options.snapshot.as_deref(), Some("new.snap"));
+        assert!(!options.delta);
+        assert!(parse(&["--delta", "a.csv"])?.delta);
+
+        let options = parse(&[
+            "snapshot",
+            "compact",
+            "--snapshot",
+            "c.snap",
+            "b.snap",
+            "1.snap",
+        ])?;
+        assert_eq!(options.command, Command::CompactSnapshot);
+        assert_eq!(options.file_path, "b.snap");
+        assert_eq!(options.deltas, vec!["1.snap"]);
+        assert!(parse(&["snapshot", "compact", "b.snap", "1.snap"]).is_err());
+
+        let options = parse(&[
+            "snapshot",
+            "fold",
+            "--retain",
+            "dispute-window",
+            "--snapshot",
+            "f.snap",
+            "b.snap",
+        ])?;
+        assert_eq!(options.command, Command::FoldSnapshot);
+        assert_eq!(options.file_path, "b.snap");
+        assert_eq!(
+            options.retention,
+            RetentionPolicy::DisputeWindow(DEFAULT_DISPUTE_WINDOW)
+        );
+        assert!(parse(&["snapshot", "fold", "b.snap"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_export() -> anyhow::Result<()> {
+        let options = parse(&["export", "--format", "ledger-cli", "a.csv"])?;
+        assert_eq!(options.command, Command::Export);
+        assert_eq!(options.export_format, ExportFormat::Ledger);
+        assert_eq!(options.commodity, None);
+
+        let options = parse(&["export", "--commodity=EUR", "a.csv"])?;
+        assert_eq!(options.export_format, ExportFormat::Beancount);
+        assert_eq!(options.commodity.as_deref(), Some("EUR"));
+        assert!(parse(&["export", "--format", "csv", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_balance() -> anyhow::Result<()> {
+        let options = parse(&[
+            "balance",
+            "--client",
+            "7",
+            "--at=2024-03-01T00:00:00Z",
+            "a.csv",
+        ])?;
+        assert_eq!(options.command, Command::Balance);
+        assert_eq!((options.client, options.at), (Some(7), Some(1_709_251_200)));
+
+        assert!(parse(&["balance", "--client=7", "a.csv"]).is_err());
+        assert!(parse(&["--client=7", "--at=0", "a.csv"]).is_err());
+        assert!(parse(&["balance", "--client=7", "--at=never", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_shadow() -> anyhow::Result<()> {
+        let options = parse(&["shadow", "--against", "--defer-disputes", "a.csv"])?;
+        assert_eq!(options.command, Command::Shadow);
+        assert_eq!(options.against.as_deref(), Some("--defer-disputes"));
+
+        assert!(parse(&["shadow", "a.csv"]).is_err());
+        assert!(parse(&["--against=--strict", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_payout() -> anyhow::Result<()> {
+        let options = parse(&[
+            "payout",
+            "--instructions=payouts.csv",
+            "--settlement-format",
+            "pain001",
+            "a.csv",
+        ])?;
+        assert_eq!(options.command, Command::Payout);
+        assert_eq!(options.instructions.as_deref(), Some("payouts.csv"));
+        assert_eq!(options.settlement_format, SettlementFormat::Pain001);
+
+        assert!(parse(&["payout", "a.csv"]).is_err());
+        assert!(parse(&["--instructions=payouts.csv", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_fixed_width() -> anyhow::Result<()> {
+        let options = parse(&[
+            "--output-format",
+            "fixed",
+            "--widths=client=8",
+            "--padding",
+            "0",
+            "a.csv",
+        ])?;
+        assert_eq!(options.output_format, OutputFormat::Fixed);
+        assert_eq!(
+            options.fixed_width,
+            FixedWidthLayout {
+                client: 8,
+                padding: '0',
+                ..Default::default()
+            }
+        );
+        assert!(parse(&["--padding", "ab", "a.csv"]).is_err());
+        assert!(parse(&["--output-format", "xml", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_pseudonymize() -> anyhow::Result<()> {
+        let options = parse(&["--pseudonymize", "key", "--pseudonym-map=map.csv", "a.csv"])?;
+        assert_eq!(options.pseudonymize.as_deref(), Some("key"));
+        assert_eq!(options.pseudonym_map.as_deref(), Some("map.csv"));
+
+        assert!(parse(&["--pseudonym-map", "map.csv", "a.csv"]).is_err());
+        assert!(parse(&["report", "--pseudonymize", "key", "a.csv"]).is_err());
+        assert!(parse(&["--pseudonymize", "key", "--journal", "j.csv", "a.csv"]).is_err());
+        for (flag, value) in [
+            ("--balance-history", "history.csv"),
+            ("--audit-log", "audit.log"),
+            ("--dead-letter", "dead.csv"),
+            ("--quarantine", "quarantine.csv"),
+            ("--latency", "latency.csv"),
+            ("--control-file", "control.csv"),
+        ] {
+            assert!(parse(&["--pseudonymize", "key", flag, value, "a.csv"]).is_err());
+        }
+        let options = parse(&["--pseudonymize", "key", "--precision=2", "a.csv"])?;
+        assert_eq!(options.precision.total, Some(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_freeze() -> anyhow::Result<()> {
+        let options = parse(&[
+            "--freeze",
+            "chargebacks=3, held=100,cooling-off=3600",
+            "--extended",
+            "a.csv",
+        ])?;
+        assert_eq!(
+            options.freeze,
+            FreezePolicy {
+                chargebacks: Some(3),
+                held_above: Some(Amount::new(100, 0)),
+                cooling_off: Some(3600),
+            }
+        );
+        assert!(options.extended);
+        assert_eq!(
+            parse(&["--freeze=chargebacks=0", "a.csv"])?
+                .freeze
+                .chargebacks,
+            None
+        );
+
+        assert!(parse(&["--freeze", "locks=1", "a.csv"]).is_err());
+        assert!(parse(&["--freeze", "held=-1", "a.csv"]).is_err());
+        assert!(parse(&["report", "--extended", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_groups() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--groups", "groups.txt", "a.csv"])?
+                .groups
+                .as_deref(),
+            Some("groups.txt")
+        );
+        assert!(parse(&["--groups", "groups.txt", "--extended", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_counterparties() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["counterparties", "a.csv"])?.command,
+            Command::Counterparties
+        );
+        assert_eq!(parse(&["tags", "a.csv"])?.command, Command::Tags);
+
+        let options = parse(&["concentration", "--top", "3", "a.csv"])?;
+        assert_eq!(options.command, Command::Concentration);
+        assert_eq!(options.top, Some(3));
+        assert!(parse(&["concentration", "--top", "x", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_balance_history() -> anyhow::Result<()> {
+        let options = parse(&[
+            "--balance-history",
+            "history.csv",
+            "--history-sampling=daily",
+            "a.csv",
+        ])?;
+        assert_eq!(options.balance_history.as_deref(), Some("history.csv"));
+        assert_eq!(options.history_sampling, HistorySampling::Daily);
+        assert!(parse(&["--history-sampling", "hourly", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_amount_limits() -> anyhow::Result<()> {
+        let options = parse(&["--max-decimals", "2", "--max-amount=1000", "a.csv"])?;
+        assert_eq!(
+            options.amount_limits,
+            AmountLimits {
+                max_decimals: 2,
+                max_amount: Amount::new(1000, 0),
+            }
+        );
+        assert!(parse(&["--max-amount", "NaN", "a.csv"]).is_err());
+        assert!(parse(&["--max-amount", "-1", "a.csv"]).is_err());
+        assert!(parse(&["--max-decimals", "-1", "a.csv"]).is_err());
+        assert_eq!(
+            parse(&["--max-decimals", "4", "a.csv"])?
+                .amount_limits
+                .max_decimals,
+            4
+        );
+        assert!(parse(&["--max-decimals", "5", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_repl() -> anyhow::Result<()> {
+        let options = parse(&["repl"])?;
+        assert_eq!(options.command, Command::Repl);
+        assert_eq!(options.file_path, "");
+        assert_eq!(parse(&["repl", "state.snap"])?.file_path, "state.snap");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_template() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--template", "layout.txt", "a.csv"])?
+                .template
+                .as_deref(),
+            Some("layout.txt")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_strict() -> anyhow::Result<()> {
+        assert!(parse(&["--strict", "a.csv"])?.strict);
+        assert!(!parse(&["a.csv"])?.strict);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_journal() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--journal", "postings.csv", "a.csv"])?.journal,
+            Some("postings.csv".to_string())
+        );
+        assert_eq!(
+            parse(&["--opening-balances=yesterday.csv", "a.csv"])?.opening_balances,
+            Some("yesterday.csv".to_string())
+        );
+        assert_eq!(
+            parse(&["--opening-transactions", "yesterday.csv", "a.csv"])?.opening_transactions,
+            Some("yesterday.csv".to_string())
+        );
+        assert_eq!(parse(&["a.csv"])?.journal, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_number_format() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--number-format", "de", "a.csv"])?
+                .input
+                .number_format,
+            NumberFormat::German
+        );
+        assert_eq!(
+            parse(&["a.csv"])?.input.number_format,
+            NumberFormat::Standard
+        );
+        assert!(parse(&["--number-format", "xx", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_header_map() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--map", "type=kind,tx=id", "--map=client=customer", "a.csv"])?
+                .input
+                .header_map,
+            vec![
+                ("type".to_string(), "kind".to_string()),
+                ("tx".to_string(), "id".to_string()),
+                ("client".to_string(), "customer".to_string()),
+            ]
+        );
+        assert!(parse(&["--map", "kind=type", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_retention() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--retain", "none", "a.csv"])?.retention,
+            RetentionPolicy::None
+        );
+        assert_eq!(
+            parse(&["a.csv", "--retain=dispute-window"])?.retention,
+            RetentionPolicy::DisputeWindow(DEFAULT_DISPUTE_WINDOW)
+        );
+        assert_eq!(
+            parse(&[
+                "--dispute-window",
+                "10",
+                "--retain",
+                "dispute-window",
+                "a.csv"
+            ])?
+            .retention,
+            RetentionPolicy::DisputeWindow(10)
+        );
+        assert!(parse(&["--retain", "some", "a.csv"]).is_err());
+        assert!(parse(&["a.csv", "--retain"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_empty_accounts() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--empty-accounts", "keep", "a.csv"])?.empty_accounts,
+            EmptyAccountPolicy::Keep
+        );
+        assert_eq!(
+            parse(&["--empty-accounts=remove", "a.csv"])?.empty_accounts,
+            EmptyAccountPolicy::Remove
+        );
+        assert_eq!(
+            parse(&["a.csv"])?.empty_accounts,
+            EmptyAccountPolicy::default()
+        );
+        assert!(parse(&["--empty-accounts", "drop", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_no_headers() -> anyhow::Result<()> {
+        assert!(parse(&["--no-headers", "a.csv"])?.input.no_headers);
+        assert!(!parse(&["a.csv"])?.input.no_headers);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_delimiter() -> anyhow::Result<()> {
+        assert_eq!(parse(&["--delimiter", ";", "a.csv"])?.input.delimiter, b';');
+        assert_eq!(parse(&["--delimiter=tab", "a.csv"])?.input.delimiter, b'\t');
+        assert_eq!(parse(&["a.csv"])?.input.delimiter, b',');
+        assert!(parse(&["--delimiter", ";;", "a.csv"]).is_err());
+        assert!(parse(&["a.csv", "--delimiter"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_input_format() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--input-format", "protobuf", "a.bin"])?
+                .input
+                .format,
+            InputFormat::Protobuf
+        );
+        assert_eq!(
+            parse(&["--input-format=ledger-cli", "a.ledger"])?
+                .input
+                .format,
+            InputFormat::PlainText
+        );
+        assert_eq!(parse(&["a.csv"])?.input.format, InputFormat::Csv);
+        assert!(parse(&["--input-format", "xml", "a.xml"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_manifest() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--manifest", "a.manifest", "a.csv"])?.manifest,
+            Some("a.manifest".to_string())
+        );
+        assert_eq!(parse(&["a.csv"])?.manifest, None);
+        assert!(parse(&["a.csv", "--manifest"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_registry() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--registry", "seen.txt", "a.csv"])?.registry,
+            Some("seen.txt".to_string())
+        );
+        assert_eq!(parse(&["a.csv"])?.registry, None);
+        assert!(parse(&["a.csv", "--registry"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_defer_disputes() -> anyhow::Result<()> {
+        assert!(parse(&["--defer-disputes", "a.csv"])?.defer_disputes);
+        assert!(!parse(&["a.csv"])?.defer_disputes);
+        assert!(parse(&["--defer-disputes", "--grouped-by-client", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_reorder_window() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--reorder-window=60", "a.csv"])?.reorder_window,
+            Some(60)
+        );
+        assert_eq!(parse(&["a.csv"])?.reorder_window, None);
+        assert!(parse(&["--reorder-window", "-1", "a.csv"]).is_err());
+        assert!(parse(&["--reorder-window", "soon", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_tenants() -> anyhow::Result<()> {
+        let options = parse(&["--tenants", "out", "--tenant-settings=t.txt", "a.csv"])?;
+        assert_eq!(options.tenants.as_deref(), Some("out"));
+        assert_eq!(options.tenant_settings.as_deref(), Some("t.txt"));
+        assert!(parse(&["--tenant-settings=t.txt", "a.csv"]).is_err());
+        assert!(parse(&["report", "--tenants=out", "a.csv"]).is_err());
+        for args in [
+            &["--output-format", "fixed"][..],
+            &["--template", "account.tpl"],
+            &["--pseudonymize", "key"],
+            &["--extended"],
+            &["--groups", "groups.txt"],
+            &["--journal", "postings.csv"],
+            &["--restore", "s.snap"],
+            &["--snapshot", "s.snap"],
+            &["--opening-balances", "yesterday.csv"],
+            &["--opening-transactions", "yesterday.csv"],
+            &["--balance-history", "history.csv"],
+            &["--audit-log", "audit.csv"],
+            &["--dead-letter", "dlq.csv"],
+            &["--latency", "latency.csv"],
+            &["--run-manifest", "run.manifest"],
+            &["--control-file", "control.csv"],
+            &["--quarantine", "quarantine.csv"],
+        ] {
+            let args: Vec<_> = ["--tenants=out"]
+                .iter()
+                .chain(args)
+                .chain(&["a.csv"])
+                .copied()
+                .collect();
+            assert!(parse(&args).is_err(), "{args:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_sign_admin() -> anyhow::Result<()> {
+        let options = parse(&["sign-admin", "--admin-key=key", "admin.csv"])?;
+        assert_eq!(options.command, Command::SignAdmin);
+        assert_eq!(options.admin_key.as_deref(), Some("key"));
+        assert!(parse(&["sign-admin", "admin.csv"]).is_err());
+        assert_eq!(
+            parse(&["--admin-key", "key", "a.csv"])?
+                .admin_key
+                .as_deref(),
+            Some("key")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_check() -> anyhow::Result<()> {
+        let options = parse(&["check", "state.snap"])?;
+        assert_eq!(options.command, Command::CheckSnapshot);
+        assert_eq!(options.file_path, "state.snap");
+        assert!(parse(&["check"]).is_err());
+        assert!(parse(&["check", "a.snap", "b.snap"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_audit_log() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--audit-log=audit.csv", "a.csv"])?
+                .audit_log
+                .as_deref(),
+            Some("audit.csv")
+        );
+        assert!(parse(&["a.csv", "--audit-log"]).is_err());
+
+        let options = parse(&["verify-audit", "audit.csv"])?;
+        assert_eq!(options.command, Command::VerifyAudit);
+        assert_eq!(options.file_path, "audit.csv");
+        assert!(parse(&["verify-audit"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_dead_letter() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--dead-letter", "dlq.csv", "a.csv"])?
+                .dead_letter
+                .as_deref(),
+            Some("dlq.csv")
+        );
+        assert_eq!(parse(&["a.csv"])?.dead_letter, None);
+        assert!(parse(&["a.csv", "--dead-letter"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_max_tps() -> anyhow::Result<()> {
+        assert_eq!(parse(&["--max-tps=100", "a.csv"])?.max_tps, Some(100));
+        assert_eq!(parse(&["a.csv"])?.max_tps, None);
+        assert!(parse(&["--max-tps", "0", "a.csv"]).is_err());
+        assert!(parse(&["--max-tps", "fast", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_latency() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--latency", "latency.csv", "a.csv"])?
+                .latency
+                .as_deref(),
+            Some("latency.csv")
+        );
+        assert_eq!(parse(&["a.csv"])?.latency, None);
+        assert!(parse(&["a.csv", "--latency"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_expected_clients() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--expected-clients=1000", "a.csv"])?.expected_clients,
+            Some(1000)
+        );
+        assert_eq!(parse(&["a.csv"])?.expected_clients, None);
+        assert!(parse(&["--expected-clients", "many", "a.csv"]).is_err());
+        assert!(parse(&["--expected-clients", "-1", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_grouped_by_client() -> anyhow::Result<()> {
+        assert!(parse(&["--grouped-by-client", "a.csv"])?.grouped_by_client);
+        assert!(!parse(&["a.csv"])?.grouped_by_client);
+        assert!(parse(&["report", "--grouped-by-client", "a.csv"]).is_err());
+        for args in [
+            &["--output-format", "fixed"][..],
+            &["--template", "account.tpl"],
+            &["--pseudonymize", "key"],
+            &["--extended"],
+            &["--groups", "groups.txt"],
+            &["--journal", "postings.csv"],
+            &["--restore", "s.snap"],
+            &["--snapshot", "s.snap"],
+            &["--opening-balances", "yesterday.csv"],
+            &["--opening-transactions", "yesterday.csv"],
+            &["--defer-disputes"],
+            &["--tenants", "out"],
+        ] {
+            let args: Vec<_> = ["--grouped-by-client"]
+                .iter()
+                .chain(args)
+                .chain(&["a.csv"])
+                .copied()
+                .collect();
+            assert!(parse(&args).is_err(), "{args:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_precision() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--precision", "total=2", "a.csv"])?.precision.total,
+            Some(2)
+        );
+        assert!(!parse(&["a.csv"])?.precision.is_set());
+        assert!(parse(&["--precision", "balance=2", "a.csv"]).is_err());
+        assert!(parse(&[
+            "balance",
+            "--client=7",
+            "--at=2024-03-01T00:00:00Z",
+            "--precision=2",
+            "a.csv",
+        ])
+        .is_ok());
+        assert!(parse(&["report", "--precision=2", "a.csv"]).is_err());
+        for args in [
+            &["--template", "account.tpl"][..],
+            &["--extended"],
+            &["--groups", "groups.txt"],
+        ] {
+            let args: Vec<_> = ["--precision=2"]
+                .iter()
+                .chain(args)
+                .chain(&["a.csv"])
+                .copied()
+                .collect();
+            assert!(parse(&args).is_err(), "{args:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_output_number_format() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--output-number-format", "de", "a.csv"])?.output_number_format,
+            NumberFormat::German
+        );
+        assert_eq!(
+            parse(&["a.csv"])?.output_number_format,
+            NumberFormat::Standard
+        );
+        assert!(parse(&["--output-number-format", "xx", "a.csv"]).is_err());
+        assert!(parse(&[
+            "balance",
+            "--client=7",
+            "--at=2024-03-01T00:00:00Z",
+            "--output-number-format=de",
+            "a.csv",
+        ])
+        .is_ok());
+        assert!(parse(&["tags", "--output-number-format=en", "a.csv"]).is_err());
+        for args in [
+            &["--template", "account.tpl"][..],
+            &["--extended"],
+            &["--groups", "groups.txt"],
+        ] {
+            let args: Vec<_> = ["--output-number-format=de"]
+                .iter()
+                .chain(args)
+                .chain(&["a.csv"])
+                .copied()
+                .collect();
+            assert!(parse(&args).is_err(), "{args:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_control_file() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--control-file", "control.csv", "a.csv"])?
+                .control_file
+                .as_deref(),
+            Some("control.csv")
+        );
+        assert_eq!(parse(&["a.csv"])?.control_file, None);
+        assert!(parse(&[
+            "apply-quarantine",
+            "--control-file=control.csv",
+            "quarantine.csv"
+        ])
+        .is_ok());
+        assert!(parse(&["report", "--control-file=control.csv", "a.csv"]).is_err());
+        for (flag, value) in [
+            ("--precision", "2"),
+            ("--groups", "groups.csv"),
+            ("--template", "account.tpl"),
+        ] {
+            assert!(parse(&["--control-file=control.csv", flag, value, "a.csv"]).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_extra_columns() -> anyhow::Result<()> {
+        assert!(parse(&["--extra-columns", "a.csv"])?.input.extra_columns);
+        assert!(!parse(&["a.csv"])?.input.extra_columns);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_validate_schema() -> anyhow::Result<()> {
+        assert!(parse(&["--validate-schema", "a.csv"])?.validate_schema);
+        assert!(!parse(&["a.csv"])?.validate_schema);
+        assert!(parse(&["report", "--validate-schema", "a.csv"])?.validate_schema);
+        assert!(parse(&["--validate-schema", "--input-format=protobuf", "a.bin"]).is_err());
+        assert!(parse(&[
+            "--validate-schema",
+            "--input-format=beancount",
+            "a.beancount"
+        ])
+        .is_err());
+        for args in [
+            &["snapshot", "inspect", "state.snap"][..],
+            &["snapshot", "compact", "--snapshot=c.snap", "b.snap"],
+            &["snapshot", "fold", "--snapshot=f.snap", "b.snap"],
+            &["verify-audit", "audit.csv"],
+            &["check", "state.snap"],
+            &["apply-quarantine", "quarantine.csv"],
+            &["repl"],
+        ] {
+            let args: Vec<_> = args.iter().chain(&["--validate-schema"]).copied().collect();
+            assert!(parse(&args).is_err(), "{args:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_client_ids() -> anyhow::Result<()> {
+        let options = parse(&[
+            "--allowed-clients",
+            "1-999",
+            "--reserved-clients=900-999",
+            "--reserved-clients=0",
+            "a.csv",
+        ])?;
+        assert!(options.client_ids.check(1).is_ok());
+        assert!(options.client_ids.check(900).is_err());
+        assert!(options.client_ids.check(1000).is_err());
+        assert!(parse(&["a.csv"])?.client_ids.check(65535).is_ok());
+        assert!(parse(&["--allowed-clients", "1-", "a.csv"]).is_err());
+        assert!(parse(&["--reserved-clients", "9-1", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_system_accounts() -> anyhow::Result<()> {
+        let system_accounts =
+            "--system-accounts=clearing=65535,opening=65534,adjustments=65533,losses=65532";
+        let options = parse(&[system_accounts, "a.csv"])?;
+        assert!(options.system_accounts.is_some());
+        assert!(options.client_ids.check(65533).is_err());
+        assert!(options.client_ids.check(65531).is_ok());
+        assert!(parse(&["--system-accounts", "clearing=65535", "a.csv"]).is_err());
+        assert!(parse(&["report", system_accounts, "a.csv"]).is_err());
+        for args in [
+            &["--output-format", "fixed"][..],
+            &["--template", "account.tpl"],
+            &["--pseudonymize", "key"],
+            &["--extended"],
+            &["--groups", "groups.txt"],
+            &["--grouped-by-client"],
+            &["--tenants", "out"],
+        ] {
+            let args: Vec<_> = [system_accounts]
+                .iter()
+                .chain(args)
+                .chain(&["a.csv"])
+                .copied()
+                .collect();
+            assert!(parse(&args).is_err(), "{args:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_run_manifest() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["--run-manifest=run.manifest", "a.csv"])?.run_manifest,
+            Some("run.manifest".to_string())
+        );
+        assert_eq!(parse(&["a.csv"])?.run_manifest, None);
+        assert!(parse(&["--tenants=out", "--run-manifest=run.manifest", "a.csv"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_strict_types() -> anyhow::Result<()> {
+        assert!(parse(&["--strict-types", "a.csv"])?.input.strict_types);
+        assert!(!parse(&["a.csv"])?.input.strict_types);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_omit_zero_accounts() -> anyhow::Result<()> {
+        assert!(parse(&["--omit-zero-accounts", "a.csv"])?.omit_zero_accounts);
+        assert!(!parse(&["a.csv"])?.omit_zero_accounts);
+        assert!(parse(&["apply-quarantine", "--omit-zero-accounts", "q.csv"])?.omit_zero_accounts);
+        assert!(parse(&["report", "--omit-zero-accounts", "a.csv"]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::{fmt::Display, ops::RangeInclusive};
+
+use anyhow::{anyhow, Context};
+
+/// Ranges of client ids that records may use, to keep corrupted feeds from
+/// writing into the id space of internal accounts.
+///
+/// Without allowed ranges every client id is allowed. Reserved ranges are
+/// rejected even if an allowed range contains them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientIdRanges {
+    allowed: Vec<RangeInclusive<u16>>,
+    reserved: Vec<RangeInclusive<u16>>,
+}
+
+impl ClientIdRanges {
+    /// Sets the allowed ranges from a list like `1-999,5000`, replacing
+    /// previous ones, e.g. those of the command line with those of a tenant.
+    pub fn set_allowed(&mut self, ranges: &str) -> anyhow::Result<()> {
+        self.allowed = parse_ranges(ranges)?;
+        Ok(())
+    }
+
+    /// Adds reserved ranges from a list like `60000-65535`.
+    pub fn reserve(&mut self, ranges: &str) -> anyhow::Result<()> {
+        self.reserved.extend(parse_ranges(ranges)?);
+        Ok(())
+    }
+
+    /// Reserves a single client id.
+    pub fn reserve_client(&mut self, client: u16) {
+        self.reserved.push(client..=client);
+    }
+
+    pub fn check(&self, client: u16) -> anyhow::Result<()> {
+        if let Some(range) = self.reserved.iter().find(|range| range.contains(&client)) {
+            return Err(anyhow!(
+                "Client {client} is in the reserved range {}",
+                Ranges(std::slice::from_ref(range))
+            ));
+        }
+        if !self.allowed.is_empty() && !self.allowed.iter().any(|range| range.contains(&client)) {
+            return Err(anyhow!(
+                "Client {client} is outside the allowed ranges {}",
+                Ranges(&self.allowed)
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_ranges(ranges: &str) -> anyhow::Result<Vec<RangeInclusive<u16>>> {
+    ranges
+        .split(',')
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let parse = |id: &str| {
+                id.trim()
+                    .parse::<u16>()
+                    .with_context(|| format!("Invalid client id range {range}"))
+            };
+            let (start, end) = (parse(start)?, parse(end)?);
+            if start > end {
+                return Err(anyhow!("Empty client id range {range}"));
+            }
+            Ok(start..=end)
+        })
+        .collect()
+}
+
+/// Formats ranges the way they are configured.
+struct Ranges<'a>(&'a [RangeInclusive<u16>]);
+
+impl Display for Ranges<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, range) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            if range.start() == range.end() {
+                write!(f, "{}", range.start())?;
+            } else {
+                write!(f, "{}-{}", range.start(), range.end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_id_ranges() -> anyhow::Result<()> {
+        let mut ranges = ClientIdRanges::default();
+        assert!(ranges.check(65535).is_ok());
+
+        ranges.set_allowed("1-999, 5000")?;
+        ranges.reserve("900-999")?;
+        assert!(ranges.check(1).is_ok());
+        assert!(ranges.check(5000).is_ok());
+        assert_eq!(
+            ranges.check(0).map_err(|err| err.to_string()),
+            Err("Client 0 is outside the allowed ranges 1-999,5000".to_string())
+        );
+        assert_eq!(
+            ranges.check(950).map_err(|err| err.to_string()),
+            Err("Client 950 is in the reserved range 900-999".to_string())
+        );
+
+        ranges.set_allowed("2000-2999")?;
+        assert!(ranges.check(2000).is_ok());
+        assert!(ranges.check(1).is_err());
+        assert!(ranges.check(950).is_err());
+
+        assert!(ClientIdRanges::default().set_allowed("5-1").is_err());
+        assert!(ClientIdRanges::default().set_allowed("1-70000").is_err());
+        assert!(ClientIdRanges::default().reserve("x").is_err());
+
+        Ok(())
+    }
+}
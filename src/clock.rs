@@ -0,0 +1,103 @@
+use std::{
+    cell::Cell,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the current time for time-dependent behavior, so it can be
+/// replaced by a [`ManualClock`] in tests.
+pub trait Clock {
+    /// Seconds since the unix epoch.
+    fn now(&self) -> u64;
+
+    /// Waits until the given second since the unix epoch has begun.
+    fn sleep_until(&self, time: u64);
+}
+
+/// The wall clock of the system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())
+    }
+
+    fn sleep_until(&self, time: u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        if let Some(duration) = Duration::from_secs(time).checked_sub(now) {
+            std::thread::sleep(duration);
+        }
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug, Default, Clone)]
+pub struct ManualClock {
+    now: Cell<u64>,
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: Cell::new(now),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.now.set(self.now.get() + seconds);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+
+    /// Moves the clock forward to the given time instead of waiting.
+    fn sleep_until(&self, time: u64) {
+        self.now.set(self.now.get().max(time));
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for std::rc::Rc<C> {
+    fn now(&self) -> u64 {
+        (**self).now()
+    }
+
+    fn sleep_until(&self, time: u64) {
+        (**self).sleep_until(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new(100);
+        assert_eq!(clock.now(), 100);
+        clock.advance(5);
+        assert_eq!(clock.now(), 105);
+        clock.set(1);
+        assert_eq!(clock.now(), 1);
+        clock.sleep_until(10);
+        assert_eq!(clock.now(), 10);
+        clock.sleep_until(5);
+        assert_eq!(clock.now(), 10);
+    }
+
+    #[test]
+    fn test_system_clock() {
+        // 2020-01-01
+        assert!(SystemClock.now() > 1_577_836_800);
+    }
+}
@@ -0,0 +1,97 @@
+use std::io;
+
+use serde::Serialize;
+
+use crate::{amount::Amount, structs::ClientRecord};
+
+/// The number of accounts and the sums of the amount columns of the accounts
+/// output, for receivers to check that the output arrived complete.
+///
+/// The sums are exact to the four decimal places amounts are kept with, so
+/// they only reconcile with an output that is not rounded by `--precision`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ControlTotals {
+    accounts: u64,
+    locked: u64,
+    available: Amount,
+    held: Amount,
+    total: Amount,
+}
+
+/// The row of the control file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ControlRecord {
+    accounts: u64,
+    locked: u64,
+    available: String,
+    held: String,
+    total: String,
+}
+
+impl ControlTotals {
+    pub fn add(&mut self, record: &ClientRecord) -> anyhow::Result<()> {
+        self.accounts += 1;
+        self.locked += u64::from(record.locked);
+        self.available = self.available.checked_add(record.available)?;
+        self.held = self.held.checked_add(record.held)?;
+        self.total = self.total.checked_add(record.total)?;
+        Ok(())
+    }
+
+    /// Writes the totals as csv with the columns
+    /// `accounts,locked,available,held,total`.
+    pub fn write_csv<W: io::Write>(&self, writer: &mut csv::Writer<W>) -> anyhow::Result<()> {
+        writer.serialize(ControlRecord {
+            accounts: self.accounts,
+            locked: self.locked,
+            available: format!("{:.4}", self.available),
+            held: format!("{:.4}", self.held),
+            total: format!("{:.4}", self.total),
+        })?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(
+        client: u16,
+        available: Amount,
+        held: Amount,
+        locked: bool,
+    ) -> anyhow::Result<ClientRecord> {
+        Ok(ClientRecord {
+            client,
+            available,
+            held,
+            total: available.checked_add(held)?,
+            locked,
+            escrowed: Amount::ZERO,
+        })
+    }
+
+    #[test]
+    fn test_control_totals() -> anyhow::Result<()> {
+        let mut control = ControlTotals::default();
+        control.add(&record(1, Amount::new(1, 1), Amount::new(2, 1), false)?)?;
+        control.add(&record(2, Amount::new(-15, 1), Amount::ZERO, true)?)?;
+        control.add(&record(
+            3,
+            Amount::new(123_425, 2),
+            Amount::new(1, 0),
+            false,
+        )?)?;
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        control.write_csv(&mut writer)?;
+        assert_eq!(
+            String::from_utf8(writer.into_inner()?)?,
+            "accounts,locked,available,held,total\n3,1,1232.8500,1.2000,1234.0500\n"
+        );
+
+        Ok(())
+    }
+}
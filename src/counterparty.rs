@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use crate::{account::Applied, amount::Amount, error::LedgerError, structs};
+
+/// Funds moved with a single counterparty.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Exposure {
+    deposits: u64,
+    deposited: Amount,
+    withdrawals: u64,
+    withdrawn: Amount,
+    /// Funds of disputed deposits that are currently held.
+    held: Amount,
+    chargebacks: u64,
+    charged_back: Amount,
+}
+
+/// Aggregates the exposure to the counterparties of deposits and
+/// withdrawals, and how often their deposits were charged back.
+///
+/// Disputes, resolves and chargebacks are attributed to the counterparty of
+/// the transaction they reference, so the counterparty of every transaction
+/// that names one is remembered for as long as the ledger lives.
+#[derive(Debug, Default)]
+pub struct Counterparties {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+    exposures: Vec<Exposure>,
+    transactions: HashMap<u32, u32>,
+}
+
+impl Counterparties {
+    /// Accounts for a record that was applied to the ledger.
+    pub fn record(
+        &mut self,
+        record: &structs::Record,
+        applied: Applied,
+    ) -> Result<(), LedgerError> {
+        let id = match (&record.counterparty, record.record_type) {
+            (Some(name), structs::RecordType::Deposit | structs::RecordType::Withdrawal) => {
+                let id = self.intern(name);
+                self.transactions.insert(record.tx, id);
+                id
+            }
+            (_, structs::RecordType::Deposit | structs::RecordType::Withdrawal) => return Ok(()),
+            _ => match self.transactions.get(&record.tx) {
+                Some(&id) => id,
+                None => return Ok(()),
+            },
+        };
+
+        let exposure = &mut self.exposures[id as usize];
+        match applied {
+            Applied::Deposited(amount) => {
+                exposure.deposits += 1;
+                exposure.deposited = exposure.deposited.checked_add(amount)?;
+            }
+            Applied::Withdrawn(amount) => {
+                exposure.withdrawals += 1;
+                exposure.withdrawn = exposure.withdrawn.checked_add(amount)?;
+            }
+            Applied::Held(amount) => exposure.held = exposure.held.checked_add(amount)?,
+            Applied::Released(amount) => exposure.held = exposure.held.checked_sub(amount)?,
+            Applied::ChargedBack(amount) => {
+                exposure.held = exposure.held.checked_sub(amount)?;
+                exposure.chargebacks += 1;
+                exposure.charged_back = exposure.charged_back.checked_add(amount)?;
+            }
+            // Admin and escrow operations are not exposure to a counterparty.
+            Applied::Unlocked
+            | Applied::Adjusted(_)
+            | Applied::WrittenOff(_)
+            | Applied::EscrowHeld(_)
+            | Applied::EscrowReleased(_) => {}
+        }
+        Ok(())
+    }
+
+    /// One row per counterparty, ordered by name.
+    pub fn report(&self) -> Vec<structs::CounterpartyRecord> {
+        let mut report: Vec<_> = self
+            .names
+            .iter()
+            .zip(&self.exposures)
+            .map(|(name, exposure)| structs::CounterpartyRecord {
+                counterparty: name.clone(),
+                deposits: exposure.deposits,
+                deposited: exposure.deposited,
+                withdrawals: exposure.withdrawals,
+                withdrawn: exposure.withdrawn,
+                held: exposure.held,
+                chargebacks: exposure.chargebacks,
+                charged_back: exposure.charged_back,
+                chargeback_rate: if exposure.deposits == 0 {
+                    0.
+                } else {
+                    exposure.chargebacks as f32 / exposure.deposits as f32
+                },
+            })
+            .collect();
+        report.sort_unstable_by(|a, b| a.counterparty.cmp(&b.counterparty));
+        report
+    }
+
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        self.exposures.push(Exposure::default());
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{account::Ledger, structs::RecordType};
+
+    #[test]
+    fn test_report() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        let record =
+            |record_type, client, tx, amount: Option<Amount>, counterparty: Option<&str>| {
+                structs::Record {
+                    record_type,
+                    client,
+                    tx,
+                    amount,
+                    counterparty: counterparty.map(str::to_string),
+                    ..Default::default()
+                }
+            };
+        for record in [
+            record(
+                RecordType::Deposit,
+                1,
+                1,
+                Some(Amount::new(10, 0)),
+                Some("shop"),
+            ),
+            record(
+                RecordType::Deposit,
+                2,
+                2,
+                Some(Amount::new(5, 0)),
+                Some("shop"),
+            ),
+            record(RecordType::Deposit, 2, 3, Some(Amount::new(1, 0)), None),
+            record(
+                RecordType::Withdrawal,
+                1,
+                4,
+                Some(Amount::new(2, 0)),
+                Some("atm"),
+            ),
+            record(RecordType::Dispute, 1, 1, None, None),
+            record(RecordType::Chargeback, 1, 1, None, None),
+            record(RecordType::Dispute, 2, 2, None, None),
+        ] {
+            tracker.apply(&record)?;
+        }
+        // Rejected records are not counted.
+        assert!(tracker
+            .apply(&record(
+                RecordType::Withdrawal,
+                2,
+                5,
+                Some(Amount::new(99, 0)),
+                Some("atm")
+            ))
+            .is_err());
+
+        let report = tracker.counterparties().report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].counterparty, "atm");
+        assert_eq!(
+            (report[0].withdrawals, report[0].withdrawn),
+            (1, Amount::new(2, 0))
+        );
+        assert_eq!(
+            report[1],
+            structs::CounterpartyRecord {
+                counterparty: "shop".to_string(),
+                deposits: 2,
+                deposited: Amount::new(15, 0),
+                withdrawals: 0,
+                withdrawn: Amount::ZERO,
+                held: Amount::new(5, 0),
+                chargebacks: 1,
+                charged_back: Amount::new(10, 0),
+                chargeback_rate: 0.5,
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use std::io;
+
+use serde::Serialize;
+
+use crate::{
+    input::{to_csv_line, UnreadableRow},
+    observer::Observer,
+    structs::Record,
+};
+
+/// A row of the dead-letter file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct DeadLetter {
+    row: u64,
+    /// The record as a csv line in the input columns, empty if it is not
+    /// known.
+    payload: String,
+    error: String,
+}
+
+/// Writes every record that could not be read or was rejected as csv, with
+/// its input row, payload and error, instead of only reporting it on stderr.
+///
+/// Records that were read are written in the input columns, trailing empty
+/// columns left out. Unreadable csv rows are written with their fields as
+/// read, rows of other input formats without a payload.
+/// [`DeadLetters::finish`] must be called after the run to report errors,
+/// which cannot be returned from the observer callbacks.
+pub struct DeadLetters<W: io::Write> {
+    writer: csv::Writer<W>,
+    /// The delimiter of the payloads.
+    delimiter: u8,
+    error: Option<anyhow::Error>,
+}
+
+impl<W: io::Write> DeadLetters<W> {
+    pub fn new(writer: csv::Writer<W>, delimiter: u8) -> Self {
+        Self {
+            writer,
+            delimiter,
+            error: None,
+        }
+    }
+
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn payload(&self, record: Option<&Record>, error: &anyhow::Error) -> anyhow::Result<String> {
+        let Some(record) = record else {
+            return Ok(error
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<UnreadableRow>())
+                .map(|row| row.payload.clone())
+                .unwrap_or_default());
+        };
+
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let mut fields = vec![
+            record.record_type.to_string(),
+            record.client.to_string(),
+            record.tx.to_string(),
+            optional(record.amount.map(|amount| amount.to_string())),
+            optional(record.timestamp.map(|timestamp| timestamp.to_string())),
+            optional(record.counterparty.clone()),
+            optional(record.tag.clone()),
+            optional(record.sequence.map(|sequence| sequence.to_string())),
+            optional(record.tenant.clone()),
+            optional(record.signature.clone()),
+            optional(record.reason_code.clone()),
+            optional(record.escrow.clone()),
+        ];
+        while fields.last().is_some_and(String::is_empty) {
+            fields.pop();
+        }
+        to_csv_line(fields.iter().map(String::as_str), self.delimiter)
+    }
+}
+
+impl<W: io::Write> Observer for DeadLetters<W> {
+    fn on_rejected(&mut self, row: u64, record: Option<&Record>, error: &anyhow::Error) {
+        if self.error.is_some() {
+            return;
+        }
+        let dead_letter = self.payload(record, error).map(|payload| DeadLetter {
+            row,
+            payload,
+            error: format!("{error:#}"),
+        });
+        self.error = dead_letter
+            .and_then(|dead_letter| Ok(self.writer.serialize(dead_letter)?))
+            .err();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::{
+        engine::EngineBuilder,
+        input::{InputOptions, RecordReader},
+    };
+
+    #[test]
+    fn test_dead_letters() -> anyhow::Result<()> {
+        let data = "\
+            type, client, tx, amount, timestamp
+            deposit, 1, 1, 2.5, 100
+            deposit, one, 2, 1.0,
+            withdrawal, 1, 3, 5.0,";
+        let records = RecordReader::from_reader(data.as_bytes(), &InputOptions::default())?;
+
+        let dead_letters = Rc::new(RefCell::new(DeadLetters::new(
+            csv::Writer::from_writer(Vec::new()),
+            b',',
+        )));
+        let mut engine = EngineBuilder::new().observer(dead_letters.clone()).build();
+        engine.run(records)?;
+        drop(engine);
+
+        let mut dead_letters = Rc::try_unwrap(dead_letters)
+            .map_err(|_| anyhow!("dead letters are still shared"))?
+            .into_inner();
+        dead_letters.finish()?;
+        let output = String::from_utf8(dead_letters.writer.into_inner()?)?;
+        let lines: Vec<_> = output.lines().collect();
+
+        assert_eq!(lines[0], "row,payload,error");
+        assert!(lines[1].starts_with(r#"2,"deposit,one,2,1.0,","Failed to deserialize record: "#));
+        assert_eq!(
+            lines[2],
+            r#"3,"withdrawal,1,3,5",Failed to perform withdrawal operation with transaction 3 on account 1: Insufficient funds"#
+        );
+        assert_eq!(lines.len(), 3);
+
+        Ok(())
+    }
+}
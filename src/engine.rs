@@ -0,0 +1,901 @@
+use std::{collections::HashMap, time::Instant};
+
+use anyhow::anyhow;
+
+use crate::{
+    account::{Applied, EmptyAccountPolicy, FreezePolicy, Ledger, RetentionPolicy},
+    admin::{AdminKey, Verified},
+    client_ids::ClientIdRanges,
+    clock::{Clock, SystemClock},
+    error::{AmountError, LedgerError},
+    group::AccountGroups,
+    latency::{Latencies, Stage},
+    observer::Observer,
+    structs::{AmountLimits, Record, RecordType},
+};
+
+/// Configures an [`Engine`].
+///
+/// ```
+/// use toy_payments_engine::{account::RetentionPolicy, engine::EngineBuilder};
+///
+/// let engine = EngineBuilder::new()
+///     .retention(RetentionPolicy::DisputeWindow(100))
+///     .journal(true)
+///     .strict(true)
+///     .build();
+/// ```
+#[derive(Default)]
+pub struct EngineBuilder {
+    retention: RetentionPolicy,
+    freeze: FreezePolicy,
+    empty_accounts: EmptyAccountPolicy,
+    omit_zero_accounts: bool,
+    groups: AccountGroups,
+    amount_limits: AmountLimits,
+    client_ids: ClientIdRanges,
+    journal: bool,
+    strict: bool,
+    quarantine: bool,
+    defer_disputes: bool,
+    admin_key: Option<AdminKey>,
+    max_tps: Option<u32>,
+    latency: bool,
+    expected_clients: usize,
+    clock: Option<Box<dyn Clock>>,
+    observers: Vec<Box<dyn Observer>>,
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How much transaction history to keep for disputes.
+    pub fn retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// When to freeze accounts.
+    pub fn freeze(mut self, freeze: FreezePolicy) -> Self {
+        self.freeze = freeze;
+        self
+    }
+
+    /// What happens to accounts that were never funded, e.g. those of failed
+    /// disputes for unknown clients.
+    pub fn empty_accounts(mut self, empty_accounts: EmptyAccountPolicy) -> Self {
+        self.empty_accounts = empty_accounts;
+        self
+    }
+
+    /// Whether to leave accounts without a total or held balance that are
+    /// not locked out of the account listings of the ledger.
+    pub fn omit_zero_accounts(mut self, omit_zero_accounts: bool) -> Self {
+        self.omit_zero_accounts = omit_zero_accounts;
+        self
+    }
+
+    /// Client groups whose available funds are pooled.
+    pub fn groups(mut self, groups: AccountGroups) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    /// Bounds for the amounts of deposits and withdrawals.
+    pub fn amount_limits(mut self, amount_limits: AmountLimits) -> Self {
+        self.amount_limits = amount_limits;
+        self
+    }
+
+    /// The client ids records may use, all by default.
+    pub fn client_ids(mut self, client_ids: ClientIdRanges) -> Self {
+        self.client_ids = client_ids;
+        self
+    }
+
+    /// Whether to record every balance movement as double-entry postings.
+    pub fn journal(mut self, journal: bool) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Whether to abort on the first invalid or rejected record instead of
+    /// reporting it and moving on.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Whether to quarantine records that fail a risk check, i.e. whose
+    /// amount exceeds the maximum, instead of rejecting them. Observers are
+    /// notified about quarantined records, e.g. to write them for review.
+    pub fn quarantine(mut self, quarantine: bool) -> Self {
+        self.quarantine = quarantine;
+        self
+    }
+
+    /// Whether to park disputes, resolves and chargebacks of unknown
+    /// transactions until the transaction arrives, for feeds that are not
+    /// strictly in order. Records still unmatched at the end of the input are
+    /// rejected.
+    pub fn defer_disputes(mut self, defer_disputes: bool) -> Self {
+        self.defer_disputes = defer_disputes;
+        self
+    }
+
+    /// The key that admin records must be signed with. Without it, admin
+    /// records are rejected.
+    pub fn admin_key(mut self, admin_key: AdminKey) -> Self {
+        self.admin_key = Some(admin_key);
+        self
+    }
+
+    /// The most transactions to apply per second of the clock, to not
+    /// overwhelm downstream systems, e.g. when replaying a batch. Unlimited
+    /// by default.
+    pub fn max_tps(mut self, max_tps: u32) -> Self {
+        self.max_tps = Some(max_tps);
+        self
+    }
+
+    /// Whether to time the stages of processing every record, see
+    /// [`Engine::latencies`].
+    pub fn latency(mut self, latency: bool) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// The number of clients to preallocate room for in the ledger, none by
+    /// default.
+    pub fn expected_clients(mut self, expected_clients: usize) -> Self {
+        self.expected_clients = expected_clients;
+        self
+    }
+
+    /// The clock for time-dependent behavior, the system clock by default.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    /// Registers an observer that is notified about every processed record.
+    /// Observers are called in the order they were registered.
+    pub fn observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    pub fn build(self) -> Engine {
+        let mut ledger = Ledger::new()
+            .with_retention(self.retention)
+            .with_freeze_policy(self.freeze)
+            .with_empty_accounts(self.empty_accounts)
+            .with_omit_zero_accounts(self.omit_zero_accounts)
+            .with_groups(self.groups)
+            .with_capacity(self.expected_clients);
+        if self.journal {
+            ledger = ledger.with_journal();
+        }
+        Engine {
+            ledger,
+            amount_limits: self.amount_limits,
+            client_ids: self.client_ids,
+            strict: self.strict,
+            quarantine: self.quarantine,
+            defer_disputes: self.defer_disputes,
+            admin_key: self.admin_key,
+            max_tps: self.max_tps,
+            throttle: (0, 0),
+            latencies: self.latency.then(Latencies::default),
+            deferred: HashMap::new(),
+            last_sequence: None,
+            clock: self.clock.unwrap_or_else(|| Box::new(SystemClock)),
+            observers: self.observers,
+        }
+    }
+}
+
+/// Outcome of [`Engine::run`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    /// Number of records read, including the ones that failed.
+    pub rows: u64,
+    pub applied: u64,
+    pub failed: u64,
+    /// Records held back by a risk check, neither applied nor failed.
+    pub quarantined: u64,
+    /// Gaps and regressions of the `sequence` column, which may indicate
+    /// records lost upstream.
+    pub sequence_gaps: u64,
+    /// When the run started and finished, in seconds since the unix epoch.
+    pub started: u64,
+    pub finished: u64,
+}
+
+/// Feeds records into a [`Ledger`].
+pub struct Engine {
+    ledger: Ledger,
+    amount_limits: AmountLimits,
+    client_ids: ClientIdRanges,
+    strict: bool,
+    quarantine: bool,
+    defer_disputes: bool,
+    admin_key: Option<AdminKey>,
+    max_tps: Option<u32>,
+    /// The current second of the clock and the transactions applied in it,
+    /// for `max_tps`.
+    throttle: (u64, u32),
+    latencies: Option<Latencies>,
+    /// Deferred records with their rows, by the transaction they reference.
+    deferred: HashMap<u32, Vec<(u64, Record)>>,
+    /// The highest sequence number of the current run.
+    last_sequence: Option<u64>,
+    clock: Box<dyn Clock>,
+    observers: Vec<Box<dyn Observer>>,
+}
+
+impl Engine {
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Gives access to the ledger, e.g. to seed opening balances before a run.
+    pub fn ledger_mut(&mut self) -> &mut Ledger {
+        &mut self.ledger
+    }
+
+    /// The processing latencies per stage, if enabled with
+    /// [`EngineBuilder::latency`]. Reading is only timed by [`Engine::run`].
+    pub fn latencies(&self) -> Option<&Latencies> {
+        self.latencies.as_ref()
+    }
+
+    pub fn into_ledger(self) -> Ledger {
+        self.ledger
+    }
+
+    /// Applies all records to the ledger.
+    ///
+    /// Failing records are reported on stderr and skipped, unless the engine
+    /// is strict, in which case the first failure is returned. The same goes
+    /// for gaps and regressions of the records' sequence numbers, except that
+    /// the records are still applied.
+    pub fn run(
+        &mut self,
+        records: impl IntoIterator<Item = anyhow::Result<Record>>,
+    ) -> anyhow::Result<RunSummary> {
+        let mut summary = self.start();
+        let mut records = records.into_iter();
+        let mut row = 0;
+        loop {
+            let started = Instant::now();
+            let Some(result) = records.next() else {
+                break;
+            };
+            self.record_latency(Stage::Read, started);
+            row += 1;
+            self.feed(&mut summary, row, result)?;
+        }
+        self.finish(summary)
+    }
+
+    /// Starts a run whose records are passed one by one to [`Engine::feed`],
+    /// e.g. when they are routed from a shared input, and which is completed
+    /// with [`Engine::finish`]. [`Engine::run`] does all of this at once.
+    pub fn start(&mut self) -> RunSummary {
+        self.last_sequence = None;
+        RunSummary {
+            started: self.clock.now(),
+            ..Default::default()
+        }
+    }
+
+    /// Processes the record read from the given row of the input.
+    pub fn feed(
+        &mut self,
+        summary: &mut RunSummary,
+        row: u64,
+        result: anyhow::Result<Record>,
+    ) -> anyhow::Result<()> {
+        summary.rows += 1;
+        let record = match result {
+            Ok(record) => record,
+            Err(err) => {
+                let err = err.context("Failed to deserialize record");
+                return self.reject(summary, row, None, err);
+            }
+        };
+        if let Some(issue) = record
+            .sequence
+            .and_then(|sequence| self.check_sequence(sequence))
+        {
+            summary.sequence_gaps += 1;
+            if self.strict {
+                return Err(anyhow!("{issue} at row {row}"));
+            }
+            eprintln!("Warning: {issue} at row {row}");
+        }
+        let risk = self
+            .quarantine
+            .then(|| risk_check(&record, &self.amount_limits));
+        if let Some(reason) = risk.flatten() {
+            summary.quarantined += 1;
+            for observer in &mut self.observers {
+                observer.on_quarantined(row, &record, &reason);
+            }
+            return Ok(());
+        }
+        self.process(summary, row, record)
+    }
+
+    /// Completes a run, rejecting the deferred records that never matched.
+    pub fn finish(&mut self, mut summary: RunSummary) -> anyhow::Result<RunSummary> {
+        let mut unmatched: Vec<_> = self.deferred.drain().flat_map(|(_, rows)| rows).collect();
+        unmatched.sort_by_key(|(row, _)| *row);
+        for (row, record) in unmatched {
+            let err = anyhow!(
+                "Failed to perform {} operation with transaction {} on account {}: \
+                 The transaction did not arrive until the end of the input",
+                record.record_type,
+                record.tx,
+                record.client
+            );
+            self.reject(&mut summary, row, Some(&record), err)?;
+        }
+
+        summary.finished = self.clock.now();
+        Ok(summary)
+    }
+
+    /// Applies a record and, if it is a deposit or withdrawal, the deferred
+    /// records referencing it.
+    fn process(
+        &mut self,
+        summary: &mut RunSummary,
+        row: u64,
+        record: Record,
+    ) -> anyhow::Result<()> {
+        self.throttle();
+        match self.apply(row, &record) {
+            Ok(Outcome::Applied) => {
+                summary.applied += 1;
+                self.throttle.1 += 1;
+                if matches!(
+                    record.record_type,
+                    RecordType::Deposit | RecordType::Withdrawal
+                ) {
+                    for (row, deferred) in self.deferred.remove(&record.tx).unwrap_or_default() {
+                        self.process(summary, row, deferred)?;
+                    }
+                }
+                Ok(())
+            }
+            Ok(Outcome::Deferred) => Ok(()),
+            Err(err) => self.reject(summary, row, Some(&record), err),
+        }
+    }
+
+    /// Waits for the next second of the clock once `max_tps` transactions
+    /// were applied in the current one.
+    fn throttle(&mut self) {
+        let Some(max_tps) = self.max_tps else {
+            return;
+        };
+        let (second, applied) = self.throttle;
+        let now = self.clock.now();
+        if now != second {
+            self.throttle = (now, 0);
+        } else if applied >= max_tps {
+            self.clock.sleep_until(second + 1);
+            self.throttle = (self.clock.now(), 0);
+        }
+    }
+
+    /// Returns the gap or regression if the sequence number does not follow
+    /// the previous one.
+    fn check_sequence(&mut self, sequence: u64) -> Option<String> {
+        let last = self.last_sequence.replace(sequence)?;
+        if sequence <= last {
+            self.last_sequence = Some(last);
+            return Some(format!(
+                "Sequence number {sequence} repeats or goes back after {last}"
+            ));
+        }
+        let missing = sequence - last - 1;
+        (missing > 0).then(|| {
+            format!("Sequence number {sequence} follows {last}, {missing} records may be missing")
+        })
+    }
+
+    fn reject(
+        &mut self,
+        summary: &mut RunSummary,
+        row: u64,
+        record: Option<&Record>,
+        err: anyhow::Error,
+    ) -> anyhow::Result<()> {
+        for observer in &mut self.observers {
+            observer.on_rejected(row, record, &err);
+        }
+        if self.strict {
+            return Err(err.context(format!("Failed at row {row}")));
+        }
+        summary.failed += 1;
+        eprintln!("{err:#}");
+        Ok(())
+    }
+
+    fn record_latency(&mut self, stage: Stage, started: Instant) {
+        if let Some(latencies) = &mut self.latencies {
+            latencies.record(stage, started.elapsed());
+        }
+    }
+
+    fn apply(&mut self, row: u64, record: &Record) -> anyhow::Result<Outcome> {
+        let started = Instant::now();
+        let validated = self.validate(record);
+        self.record_latency(Stage::Validate, started);
+        let verified = validated?;
+
+        let started = Instant::now();
+        let outcome = self.apply_validated(row, record, verified);
+        self.record_latency(Stage::Apply, started);
+        outcome
+    }
+
+    /// Validates the record, returning it as verified if it is an admin
+    /// operation.
+    fn validate<'r>(&self, record: &'r Record) -> anyhow::Result<Option<Verified<'r>>> {
+        let verified = if record.is_admin() {
+            let verified = self
+                .admin_key
+                .as_ref()
+                .ok_or(anyhow!("Admin operations require an admin key"))?
+                .verify(record)?;
+            Some(verified)
+        } else {
+            None
+        };
+        self.client_ids
+            .check(record.client)
+            .map_err(|err| anyhow!("Failed to validate the record: {err}"))?;
+        record
+            .validate_with(&self.amount_limits)
+            .map_err(|err| anyhow!("Failed to validate the record: {err}"))?;
+        Ok(verified)
+    }
+
+    fn apply_validated(
+        &mut self,
+        row: u64,
+        record: &Record,
+        verified: Option<Verified>,
+    ) -> anyhow::Result<Outcome> {
+        let was_locked = self.ledger.is_locked(record.client);
+        let applied = match verified {
+            Some(verified) => self.ledger.apply_verified(verified),
+            None => self.ledger.apply(record),
+        };
+        let applied = match applied {
+            Ok(applied) => applied,
+            Err(LedgerError::UnknownTransaction)
+                if self.defer_disputes
+                    && matches!(
+                        record.record_type,
+                        RecordType::Dispute | RecordType::Resolve | RecordType::Chargeback
+                    ) =>
+            {
+                self.deferred
+                    .entry(record.tx)
+                    .or_default()
+                    .push((row, record.clone()));
+                return Ok(Outcome::Deferred);
+            }
+            Err(err) => {
+                return Err(anyhow!(
+                    "Failed to perform {} operation with transaction {} on account {}: {}",
+                    record.record_type,
+                    record.tx,
+                    record.client,
+                    err
+                ))
+            }
+        };
+        if self.observers.is_empty() {
+            return Ok(Outcome::Applied);
+        }
+        let account = self
+            .ledger
+            .client_record(record.client)?
+            .expect("applied records have an account");
+        let locked = !was_locked && account.locked;
+        for observer in &mut self.observers {
+            observer.on_accepted(record, &account);
+            if let Applied::Held(amount) = applied {
+                observer.on_dispute_opened(record.client, record.tx, amount);
+            }
+            if locked {
+                observer.on_lock(record.client);
+            }
+        }
+        Ok(Outcome::Applied)
+    }
+}
+
+/// What [`Engine::apply`] did with a record.
+enum Outcome {
+    Applied,
+    /// Parked until the transaction it references arrives.
+    Deferred,
+}
+
+/// Returns why the record fails a risk check, if it does. Records failing
+/// other validations are rejected as usual.
+fn risk_check(record: &Record, limits: &AmountLimits) -> Option<String> {
+    let err = record.validate_with(limits).err()?;
+    match err.downcast_ref::<AmountError>() {
+        Some(AmountError::TooLarge(_)) => Some(err.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{admin::AdminKey, clock::ManualClock, snapshot::Snapshot, structs::ClientRecord};
+
+    fn records() -> Vec<anyhow::Result<Record>> {
+        vec![
+            Ok(Record {
+                record_type: RecordType::Deposit,
+                client: 1,
+                tx: 1,
+                amount: Some(Amount::new(2, 0)),
+                ..Default::default()
+            }),
+            Err(anyhow!("broken row")),
+            Ok(Record {
+                record_type: RecordType::Withdrawal,
+                client: 1,
+                tx: 2,
+                amount: Some(Amount::new(5, 0)),
+                ..Default::default()
+            }),
+            Ok(Record {
+                record_type: RecordType::Withdrawal,
+                client: 1,
+                tx: 3,
+                amount: Some(Amount::new(1, 0)),
+                ..Default::default()
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_run() -> anyhow::Result<()> {
+        let mut engine = EngineBuilder::new()
+            .journal(true)
+            .clock(ManualClock::new(1_700_000_000))
+            .build();
+        let summary = engine.run(records())?;
+
+        assert_eq!(
+            summary,
+            RunSummary {
+                rows: 4,
+                applied: 2,
+                failed: 2,
+                quarantined: 0,
+                sequence_gaps: 0,
+                started: 1_700_000_000,
+                finished: 1_700_000_000,
+            }
+        );
+        assert_eq!(
+            engine.ledger().client_records()?[0].total,
+            Amount::new(1, 0)
+        );
+        engine.ledger().verify_journal()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_tps() -> anyhow::Result<()> {
+        let clock = Rc::new(ManualClock::new(100));
+        let mut engine = EngineBuilder::new().max_tps(1).clock(clock.clone()).build();
+        let summary = engine.run(records())?;
+        assert_eq!(summary.applied, 2);
+        // The records after the first applied one wait for the next second.
+        assert_eq!(summary.finished, 101);
+        assert_eq!(clock.now(), 101);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_ids() -> anyhow::Result<()> {
+        let mut client_ids = ClientIdRanges::default();
+        client_ids.set_allowed("1-99")?;
+        let mut engine = EngineBuilder::new().client_ids(client_ids.clone()).build();
+        assert_eq!(engine.run(records())?.applied, 2);
+
+        client_ids.reserve("1")?;
+        let mut engine = EngineBuilder::new().client_ids(client_ids).build();
+        let summary = engine.run(records())?;
+        assert_eq!((summary.applied, summary.failed), (0, 4));
+        assert_eq!(engine.ledger().client_records()?, []);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_latency() -> anyhow::Result<()> {
+        assert!(EngineBuilder::new().build().latencies().is_none());
+
+        let mut engine = EngineBuilder::new().latency(true).build();
+        engine.run(records())?;
+        let report = engine
+            .latencies()
+            .ok_or(anyhow!("latencies are not recorded"))?
+            .report();
+        assert_eq!(
+            report
+                .iter()
+                .map(|record| (record.stage.as_str(), record.count))
+                .collect::<Vec<_>>(),
+            [("read", 4), ("validate", 3), ("apply", 3)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_strict() -> anyhow::Result<()> {
+        let mut engine = EngineBuilder::new().strict(true).build();
+
+        assert!(engine.run(records()).is_err());
+        assert_eq!(
+            engine.ledger().client_records()?[0].total,
+            Amount::new(2, 0)
+        );
+
+        Ok(())
+    }
+
+    fn record(record_type: RecordType, tx: u32, amount: Option<Amount>) -> anyhow::Result<Record> {
+        Ok(Record {
+            record_type,
+            client: 1,
+            tx,
+            amount,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_defer_disputes() -> anyhow::Result<()> {
+        let records = || {
+            vec![
+                record(RecordType::Dispute, 1, None),
+                record(RecordType::Chargeback, 1, None),
+                record(RecordType::Dispute, 2, None),
+                record(RecordType::Deposit, 3, Some(Amount::new(5, 0))),
+                record(RecordType::Deposit, 1, Some(Amount::new(2, 0))),
+            ]
+        };
+
+        let mut engine = EngineBuilder::new().defer_disputes(true).build();
+        let summary = engine.run(records())?;
+        assert_eq!((summary.applied, summary.failed), (4, 1));
+        let account = &engine.ledger().client_records()?[0];
+        assert_eq!(
+            (account.total, account.held, account.locked),
+            (Amount::new(5, 0), Amount::ZERO, true)
+        );
+
+        let mut engine = EngineBuilder::new().build();
+        let summary = engine.run(records())?;
+        assert_eq!((summary.applied, summary.failed), (2, 3));
+
+        let mut engine = EngineBuilder::new()
+            .defer_disputes(true)
+            .strict(true)
+            .build();
+        let err = engine.run(records()).expect_err("tx 2 never arrives");
+        assert_eq!(err.to_string(), "Failed at row 3");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_admin_operations() -> anyhow::Result<()> {
+        let key = AdminKey::new(b"secret")?;
+        let adjustment = |tx, amount, reason_code: Option<&str>| -> anyhow::Result<Record> {
+            let mut record = record(RecordType::Adjustment, tx, Some(amount))?;
+            record.reason_code = reason_code.map(str::to_string);
+            Ok(record)
+        };
+        let signed = |record: anyhow::Result<Record>| -> anyhow::Result<Record> {
+            let mut record = record?;
+            record.signature = Some(key.sign(&record));
+            Ok(record)
+        };
+        let records = vec![
+            record(RecordType::Deposit, 1, Some(Amount::new(2, 0))),
+            signed(adjustment(2, Amount::new(-3, 0), Some("FEE"))),
+            adjustment(3, Amount::new(10, 0), None),
+            signed(record(RecordType::WriteOff, 4, None)),
+            adjustment(5, Amount::new(-1, 0), Some("FEE")),
+            adjustment(6, Amount::new(5, 1), Some("GOODWILL")),
+        ]
+        .into_iter()
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut engine = EngineBuilder::new().admin_key(key.clone()).build();
+        let summary = engine.run(records.iter().cloned().map(Ok))?;
+        // The unsigned credit of tx 6 is rejected like the other failures.
+        assert_eq!((summary.applied, summary.failed), (3, 3));
+        let account = &engine.ledger().client_records()?[0];
+        assert_eq!(
+            (account.available, account.total),
+            (Amount::ZERO, Amount::ZERO)
+        );
+        let adjustments = engine.ledger().adjustments();
+        assert_eq!(
+            adjustments
+                .iter()
+                .map(|adjustment| (adjustment.tx, adjustment.amount, adjustment.admin))
+                .collect::<Vec<_>>(),
+            [(2, Amount::new(-3, 0), true), (4, Amount::new(1, 0), true)]
+        );
+
+        let mut engine = EngineBuilder::new().strict(true).build();
+        let err = engine
+            .run(records.into_iter().map(Ok))
+            .expect_err("admin operations need a key");
+        assert_eq!(err.to_string(), "Failed at row 2");
+        assert_eq!(
+            err.root_cause().to_string(),
+            "Admin operations require an admin key"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_admin_replay() -> anyhow::Result<()> {
+        let key = AdminKey::new(b"secret")?;
+        let mut bonus = record(RecordType::Adjustment, 2, Some(Amount::new(1000, 0)))?;
+        bonus.reason_code = Some("BONUS".to_string());
+        bonus.signature = Some(key.sign(&bonus));
+        let records = vec![
+            record(RecordType::Deposit, 1, Some(Amount::new(1, 0)))?,
+            bonus.clone(),
+        ];
+
+        let mut engine = EngineBuilder::new().admin_key(key.clone()).build();
+        let summary = engine.run(records.into_iter().map(Ok))?;
+        assert_eq!((summary.applied, summary.failed), (2, 0));
+        // The signature stays valid, but the tx id was already applied.
+        let summary = engine.run([Ok(bonus.clone())])?;
+        assert_eq!((summary.applied, summary.failed), (0, 1));
+        assert_eq!(
+            engine.ledger().client_records()?[0].available,
+            Amount::new(1001, 0)
+        );
+
+        // Also after continuing from a snapshot.
+        let snapshot = Snapshot::from_bytes(&engine.ledger().snapshot().to_bytes())?;
+        let mut engine = EngineBuilder::new().admin_key(key).build();
+        engine.ledger_mut().restore(&snapshot)?;
+        let summary = engine.run([Ok(bonus)])?;
+        assert_eq!((summary.applied, summary.failed), (0, 1));
+        assert_eq!(
+            engine.ledger().client_records()?[0].available,
+            Amount::new(1001, 0)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequence_gaps() -> anyhow::Result<()> {
+        let records = || {
+            [Some(1), Some(2), Some(5), None, Some(4), Some(6)]
+                .into_iter()
+                .enumerate()
+                .map(|(tx, sequence)| {
+                    Ok(Record {
+                        sequence,
+                        ..record(RecordType::Deposit, tx as u32, Some(Amount::new(1, 0)))?
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut engine = EngineBuilder::new().build();
+        let summary = engine.run(records())?;
+        assert_eq!((summary.applied, summary.sequence_gaps), (6, 2));
+
+        let mut engine = EngineBuilder::new().strict(true).build();
+        let err = engine.run(records()).expect_err("the sequence has a gap");
+        assert_eq!(
+            err.to_string(),
+            "Sequence number 5 follows 2, 2 records may be missing at row 3"
+        );
+        assert_eq!(
+            engine.ledger().client_records()?[0].total,
+            Amount::new(2, 0)
+        );
+
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Observer for Recorder {
+        fn on_accepted(&mut self, record: &Record, account: &ClientRecord) {
+            self.events
+                .borrow_mut()
+                .push(format!("accepted {} {}", record.tx, account.total));
+        }
+
+        fn on_rejected(&mut self, row: u64, _record: Option<&Record>, _error: &anyhow::Error) {
+            self.events.borrow_mut().push(format!("rejected row {row}"));
+        }
+
+        fn on_dispute_opened(&mut self, client: u16, tx: u32, amount: Amount) {
+            self.events
+                .borrow_mut()
+                .push(format!("dispute {client} {tx} {amount}"));
+        }
+
+        fn on_lock(&mut self, client: u16) {
+            self.events.borrow_mut().push(format!("lock {client}"));
+        }
+    }
+
+    #[test]
+    fn test_observer() -> anyhow::Result<()> {
+        let recorder = Recorder::default();
+        let events = recorder.events.clone();
+        let mut engine = EngineBuilder::new().observer(recorder).build();
+
+        let mut records = records();
+        for record_type in [RecordType::Dispute, RecordType::Chargeback] {
+            records.push(Ok(Record {
+                record_type,
+                client: 1,
+                tx: 1,
+                amount: None,
+                ..Default::default()
+            }));
+        }
+        engine.run(records)?;
+
+        assert_eq!(
+            *events.borrow(),
+            [
+                "accepted 1 2",
+                "rejected row 2",
+                "rejected row 3",
+                "accepted 3 1",
+                "accepted 1 1",
+                "dispute 1 1 2",
+                "accepted 1 -1",
+                "lock 1",
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt::Display;
+
+use crate::amount::Amount;
+
+/// Reasons for the ledger to reject an operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LedgerError {
+    NegativeAmount,
+    MissingAmount,
+    DuplicateTransaction,
+    AccountLocked,
+    InsufficientFunds,
+    UnknownTransaction,
+    AlreadyDisputed,
+    AlreadyChargedBack,
+    NotDisputed,
+    OpeningBalanceExists(u16),
+    InconsistentOpeningBalance(u16),
+    UnbalancedJournal,
+    JournalMismatch(u16),
+    /// A balance or total would exceed the range of an
+    /// [`Amount`](crate::amount::Amount).
+    Overflow,
+    NothingToWriteOff,
+    /// A write-off while funds are held by a dispute or in escrow.
+    FundsOnHold,
+    /// An escrow release exceeds the funds in the escrow bucket.
+    InsufficientEscrow,
+    /// An unsigned adjustment would credit funds, which only admin
+    /// operations may.
+    UnsignedCredit,
+    /// An admin operation that was not verified with an
+    /// [`AdminKey`](crate::admin::AdminKey).
+    Unauthorized,
+}
+
+impl Display for LedgerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerError::NegativeAmount => write!(f, "amount has to be positive"),
+            LedgerError::MissingAmount => write!(f, "Missing amount in record"),
+            LedgerError::DuplicateTransaction => {
+                write!(f, "Customer already has a transaction with this tx id")
+            }
+            LedgerError::AccountLocked => write!(f, "This account is locked"),
+            LedgerError::InsufficientFunds => write!(f, "Insufficient funds"),
+            LedgerError::UnknownTransaction => {
+                write!(f, "Customer does not has a transaction with this tx id")
+            }
+            LedgerError::AlreadyDisputed => write!(f, "Transaction is already disputed"),
+            LedgerError::AlreadyChargedBack => {
+                write!(f, "Transaction has already been charged back")
+            }
+            LedgerError::NotDisputed => write!(f, "Transaction is not disputed"),
+            LedgerError::OpeningBalanceExists(client) => {
+                write!(f, "Opening balance for client {client} is already set")
+            }
+            LedgerError::InconsistentOpeningBalance(client) => {
+                write!(f, "Opening balance for client {client} is inconsistent")
+            }
+            LedgerError::UnbalancedJournal => write!(f, "Journal postings do not sum up to zero"),
+            LedgerError::JournalMismatch(client) => {
+                write!(f, "Journal does not match the balances of client {client}")
+            }
+            LedgerError::Overflow => write!(f, "Amount exceeds the supported range"),
+            LedgerError::InsufficientEscrow => write!(f, "Insufficient funds in escrow"),
+            LedgerError::NothingToWriteOff => {
+                write!(f, "Account has no negative balance to write off")
+            }
+            LedgerError::FundsOnHold => {
+                write!(
+                    f,
+                    "Account has held or escrowed funds, which cannot be written off"
+                )
+            }
+            LedgerError::UnsignedCredit => {
+                write!(f, "Crediting adjustments require an admin signature")
+            }
+            LedgerError::Unauthorized => {
+                write!(f, "Admin operations require a verified signature")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+/// Reasons for an amount of a record to be rejected, when parsing it or by
+/// the [`AmountLimits`](crate::structs::AmountLimits).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    /// Not a decimal number, e.g. `NaN`, `inf` or `1e3`.
+    Malformed,
+    Negative,
+    TooManyDecimals(u32),
+    TooLarge(Amount),
+}
+
+impl Display for AmountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AmountError::Malformed => write!(f, "Amount is not a decimal number"),
+            AmountError::Negative => write!(f, "Amount is negative"),
+            AmountError::TooManyDecimals(max) => {
+                write!(f, "Amount has more than {max} decimal places")
+            }
+            AmountError::TooLarge(max) => write!(f, "Amount exceeds the maximum of {max}"),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
@@ -0,0 +1,482 @@
+use std::{collections::BTreeMap, io, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::{
+    amount::Amount,
+    error::LedgerError,
+    journal::{Journal, PostingAccount},
+};
+
+/// The input has no dates, so every entry is booked on this day.
+pub(crate) const DATE: &str = "1970-01-01";
+/// [`DATE`] in the US format QIF uses.
+const QIF_DATE: &str = "01/01/1970";
+
+/// Commodity of exported amounts, unless overridden.
+pub const DEFAULT_COMMODITY: &str = "USD";
+
+/// Plain-text accounting formats the journal can be exported to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Beancount,
+    Ledger,
+    /// Per-client statements as simplified ISO 20022 camt.053 XML.
+    Camt053,
+    /// Per-client bank accounts in the Quicken Interchange Format.
+    Qif,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beancount" => Ok(ExportFormat::Beancount),
+            "ledger-cli" | "ledger" => Ok(ExportFormat::Ledger),
+            "camt053" => Ok(ExportFormat::Camt053),
+            "qif" => Ok(ExportFormat::Qif),
+            other => Err(anyhow!(
+                "Unknown export format {other}, expected one of: beancount, ledger-cli, camt053, qif"
+            )),
+        }
+    }
+}
+
+/// Writes the journal in the given format.
+pub fn write_journal(
+    journal: &Journal,
+    format: ExportFormat,
+    commodity: &str,
+    writer: &mut impl io::Write,
+) -> anyhow::Result<()> {
+    match format {
+        ExportFormat::Beancount | ExportFormat::Ledger => {
+            write_transactions(journal, format, commodity, writer)
+        }
+        ExportFormat::Camt053 => write_camt053(journal, commodity, writer),
+        ExportFormat::Qif => write_qif(journal, writer),
+    }
+}
+
+/// Writes every transfer of the journal as a plain-text accounting transaction.
+///
+/// Client funds are booked as assets and the clearing and opening accounts
+/// as equity, so the exported balances of client accounts are positive.
+fn write_transactions(
+    journal: &Journal,
+    format: ExportFormat,
+    commodity: &str,
+    writer: &mut impl io::Write,
+) -> anyhow::Result<()> {
+    let mut accounts: Vec<_> = journal
+        .postings()
+        .iter()
+        .map(|posting| posting.account)
+        .collect();
+    accounts.sort_by_key(|account| account_name(*account));
+    accounts.dedup();
+
+    if format == ExportFormat::Beancount {
+        for account in &accounts {
+            writeln!(writer, "{DATE} open {} {commodity}", account_name(*account))?;
+        }
+        writeln!(writer)?;
+    }
+
+    for pair in journal.postings().chunks(2) {
+        let [from, to] = pair else {
+            return Err(anyhow!("Journal postings are not in pairs"));
+        };
+        let description = describe(from.account, to.account);
+        match format {
+            ExportFormat::Beancount => {
+                writeln!(writer, "{DATE} * \"{description}\"")?;
+                writeln!(writer, "  tx: {}", from.tx)?;
+            }
+            _ => writeln!(
+                writer,
+                "{} ({}) {description}",
+                DATE.replace('-', "/"),
+                from.tx
+            )?,
+        }
+        for posting in pair {
+            writeln!(
+                writer,
+                "  {}  {:.4} {commodity}",
+                account_name(posting.account),
+                posting.amount
+            )?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// A movement of funds into or out of a client's account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    tx: u32,
+    /// Positive for credits, negative for debits.
+    amount: Amount,
+    description: &'static str,
+}
+
+/// The opening balance and entries of a client.
+#[derive(Debug, Default)]
+struct Statement {
+    opening: Amount,
+    entries: Vec<Entry>,
+}
+
+impl Statement {
+    fn closing(&self) -> Result<Amount, LedgerError> {
+        self.entries
+            .iter()
+            .try_fold(self.opening, |closing, entry| {
+                closing.checked_add(entry.amount)
+            })
+    }
+}
+
+/// Collects the statements of all clients from the journal. Disputes,
+/// resolves and escrow movements only move funds within an account and are
+/// not entries.
+fn statements(journal: &Journal) -> Result<BTreeMap<u16, Statement>, LedgerError> {
+    let mut statements: BTreeMap<u16, Statement> = BTreeMap::new();
+    for pair in journal.postings().chunks_exact(2) {
+        let (from, to) = (pair[0].account, pair[1].account);
+        let amount = pair[1].amount;
+        let (client, amount) = match (from, to) {
+            (PostingAccount::Opening, PostingAccount::Available(client))
+            | (PostingAccount::Opening, PostingAccount::Held(client))
+            | (PostingAccount::Opening, PostingAccount::Escrow(client)) => {
+                let statement = statements.entry(client).or_default();
+                statement.opening = statement.opening.checked_add(amount)?;
+                continue;
+            }
+            (PostingAccount::Clearing, PostingAccount::Available(client))
+            | (PostingAccount::Adjustments, PostingAccount::Available(client))
+            | (PostingAccount::Losses, PostingAccount::Available(client)) => (client, amount),
+            (PostingAccount::Available(client), PostingAccount::Clearing)
+            | (PostingAccount::Available(client), PostingAccount::Adjustments)
+            | (PostingAccount::Held(client), PostingAccount::Clearing) => {
+                (client, amount.checked_neg()?)
+            }
+            _ => continue,
+        };
+        statements.entry(client).or_default().entries.push(Entry {
+            tx: pair[0].tx,
+            amount,
+            description: describe(from, to),
+        });
+    }
+    Ok(statements)
+}
+
+/// Writes a camt.053 bank-to-customer statement with one `Stmt` per client.
+fn write_camt053(
+    journal: &Journal,
+    commodity: &str,
+    writer: &mut impl io::Write,
+) -> anyhow::Result<()> {
+    let indicator = |amount: Amount| {
+        if amount < Amount::ZERO {
+            "DBIT"
+        } else {
+            "CRDT"
+        }
+    };
+    let commodity = escape_xml(commodity);
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">"#
+    )?;
+    writeln!(writer, "  <BkToCstmrStmt>")?;
+    writeln!(writer, "    <GrpHdr>")?;
+    writeln!(writer, "      <MsgId>toy-payments-engine</MsgId>")?;
+    writeln!(writer, "      <CreDtTm>{DATE}T00:00:00</CreDtTm>")?;
+    writeln!(writer, "    </GrpHdr>")?;
+
+    for (client, statement) in statements(journal)? {
+        writeln!(writer, "    <Stmt>")?;
+        writeln!(writer, "      <Id>{client}</Id>")?;
+        writeln!(writer, "      <CreDtTm>{DATE}T00:00:00</CreDtTm>")?;
+        writeln!(writer, "      <Acct>")?;
+        writeln!(writer, "        <Id><Othr><Id>{client}</Id></Othr></Id>")?;
+        writeln!(writer, "        <Ccy>{commodity}</Ccy>")?;
+        writeln!(writer, "      </Acct>")?;
+        for (code, balance) in [("OPBD", statement.opening), ("CLBD", statement.closing()?)] {
+            writeln!(writer, "      <Bal>")?;
+            writeln!(
+                writer,
+                "        <Tp><CdOrPrtry><Cd>{code}</Cd></CdOrPrtry></Tp>"
+            )?;
+            writeln!(
+                writer,
+                r#"        <Amt Ccy="{commodity}">{:.4}</Amt>"#,
+                balance.checked_abs()?
+            )?;
+            writeln!(
+                writer,
+                "        <CdtDbtInd>{}</CdtDbtInd>",
+                indicator(balance)
+            )?;
+            writeln!(writer, "        <Dt><Dt>{DATE}</Dt></Dt>")?;
+            writeln!(writer, "      </Bal>")?;
+        }
+        for entry in &statement.entries {
+            writeln!(writer, "      <Ntry>")?;
+            writeln!(writer, "        <NtryRef>{}</NtryRef>", entry.tx)?;
+            writeln!(
+                writer,
+                r#"        <Amt Ccy="{commodity}">{:.4}</Amt>"#,
+                entry.amount.checked_abs()?
+            )?;
+            writeln!(
+                writer,
+                "        <CdtDbtInd>{}</CdtDbtInd>",
+                indicator(entry.amount)
+            )?;
+            writeln!(writer, "        <Sts>BOOK</Sts>")?;
+            writeln!(writer, "        <BookgDt><Dt>{DATE}</Dt></BookgDt>")?;
+            writeln!(
+                writer,
+                "        <AddtlNtryInf>{}</AddtlNtryInf>",
+                entry.description
+            )?;
+            writeln!(writer, "      </Ntry>")?;
+        }
+        writeln!(writer, "    </Stmt>")?;
+    }
+
+    writeln!(writer, "  </BkToCstmrStmt>")?;
+    writeln!(writer, "</Document>")?;
+    Ok(())
+}
+
+/// Writes a QIF file with a bank account per client. Opening balances are
+/// written as an entry of their own, as QIF has no notion of them.
+fn write_qif(journal: &Journal, writer: &mut impl io::Write) -> anyhow::Result<()> {
+    for (client, statement) in statements(journal)? {
+        writeln!(writer, "!Account\nNClient {client}\nTBank\n^")?;
+        writeln!(writer, "!Type:Bank")?;
+        if statement.opening != Amount::ZERO {
+            writeln!(
+                writer,
+                "D{QIF_DATE}\nT{:.4}\nPOpening Balance\n^",
+                statement.opening
+            )?;
+        }
+        for entry in &statement.entries {
+            writeln!(
+                writer,
+                "D{QIF_DATE}\nT{:.4}\nN{}\nP{}\n^",
+                entry.amount, entry.tx, entry.description
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn account_name(account: PostingAccount) -> String {
+    match account {
+        PostingAccount::Available(client) => format!("Assets:Clients:{client}:Available"),
+        PostingAccount::Held(client) => format!("Assets:Clients:{client}:Held"),
+        PostingAccount::Escrow(client) => format!("Assets:Clients:{client}:Escrow"),
+        PostingAccount::Clearing => "Equity:Clearing".to_string(),
+        PostingAccount::Opening => "Equity:Opening-Balances".to_string(),
+        PostingAccount::Adjustments => "Equity:Adjustments".to_string(),
+        PostingAccount::Losses => "Equity:Losses".to_string(),
+    }
+}
+
+/// Names the operation that moved funds between the two accounts.
+fn describe(from: PostingAccount, to: PostingAccount) -> &'static str {
+    match (from, to) {
+        (PostingAccount::Opening, _) => "opening balance",
+        (PostingAccount::Clearing, _) => "deposit",
+        (PostingAccount::Adjustments, _) | (_, PostingAccount::Adjustments) => "adjustment",
+        (PostingAccount::Losses, _) => "write-off",
+        (PostingAccount::Available(_), PostingAccount::Clearing) => "withdrawal",
+        (PostingAccount::Available(_), PostingAccount::Escrow(_)) => "escrow hold",
+        (PostingAccount::Escrow(_), _) => "escrow release",
+        (PostingAccount::Available(_), _) => "dispute",
+        (PostingAccount::Held(_), PostingAccount::Available(_)) => "resolve",
+        (PostingAccount::Held(_), _) => "chargeback",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal() -> anyhow::Result<Journal> {
+        let mut journal = Journal::default();
+        journal.transfer(
+            1,
+            PostingAccount::Clearing,
+            PostingAccount::Available(2),
+            Amount::new(15, 1),
+        )?;
+        journal.transfer(
+            1,
+            PostingAccount::Available(2),
+            PostingAccount::Held(2),
+            Amount::new(15, 1),
+        )?;
+        Ok(journal)
+    }
+
+    #[test]
+    fn test_parse_export_format() -> anyhow::Result<()> {
+        assert_eq!(
+            "beancount".parse::<ExportFormat>()?,
+            ExportFormat::Beancount
+        );
+        assert_eq!("ledger-cli".parse::<ExportFormat>()?, ExportFormat::Ledger);
+        assert_eq!("camt053".parse::<ExportFormat>()?, ExportFormat::Camt053);
+        assert_eq!("qif".parse::<ExportFormat>()?, ExportFormat::Qif);
+        assert!("gnucash".parse::<ExportFormat>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_beancount() -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        write_journal(&journal()?, ExportFormat::Beancount, "USD", &mut output)?;
+
+        assert_eq!(
+            String::from_utf8(output)?,
+            "1970-01-01 open Assets:Clients:2:Available USD\n\
+             1970-01-01 open Assets:Clients:2:Held USD\n\
+             1970-01-01 open Equity:Clearing USD\n\
+             \n\
+             1970-01-01 * \"deposit\"\n  \
+               tx: 1\n  \
+               Equity:Clearing  -1.5000 USD\n  \
+               Assets:Clients:2:Available  1.5000 USD\n\
+             \n\
+             1970-01-01 * \"dispute\"\n  \
+               tx: 1\n  \
+               Assets:Clients:2:Available  -1.5000 USD\n  \
+               Assets:Clients:2:Held  1.5000 USD\n\
+             \n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_statements() -> anyhow::Result<()> {
+        let mut journal = journal()?;
+        journal.transfer(
+            0,
+            PostingAccount::Opening,
+            PostingAccount::Available(3),
+            Amount::new(4, 0),
+        )?;
+        journal.transfer(
+            2,
+            PostingAccount::Held(2),
+            PostingAccount::Clearing,
+            Amount::new(15, 1),
+        )?;
+        journal.transfer(
+            3,
+            PostingAccount::Available(3),
+            PostingAccount::Clearing,
+            Amount::new(1, 0),
+        )?;
+
+        let statements = statements(&journal)?;
+
+        assert_eq!(
+            statements[&2].entries,
+            vec![
+                Entry {
+                    tx: 1,
+                    amount: Amount::new(15, 1),
+                    description: "deposit"
+                },
+                Entry {
+                    tx: 2,
+                    amount: Amount::new(-15, 1),
+                    description: "chargeback"
+                },
+            ]
+        );
+        assert_eq!(statements[&2].closing()?, Amount::ZERO);
+        assert_eq!(statements[&3].opening, Amount::new(4, 0));
+        assert_eq!(statements[&3].closing()?, Amount::new(3, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_camt053() -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        write_journal(&journal()?, ExportFormat::Camt053, "EUR", &mut output)?;
+        let output = String::from_utf8(output)?;
+
+        assert!(output.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+        assert_eq!(output.matches("<Stmt>").count(), 1);
+        assert_eq!(output.matches("<Ntry>").count(), 1);
+        assert!(output.contains(
+            "        <NtryRef>1</NtryRef>\n        <Amt Ccy=\"EUR\">1.5000</Amt>\n        <CdtDbtInd>CRDT</CdtDbtInd>\n"
+        ));
+        assert!(output.ends_with("</Document>\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_qif() -> anyhow::Result<()> {
+        let mut journal = journal()?;
+        journal.transfer(
+            0,
+            PostingAccount::Opening,
+            PostingAccount::Available(3),
+            Amount::new(4, 0),
+        )?;
+
+        let mut output = Vec::new();
+        write_journal(&journal, ExportFormat::Qif, "USD", &mut output)?;
+
+        assert_eq!(
+            String::from_utf8(output)?,
+            "!Account\nNClient 2\nTBank\n^\n!Type:Bank\n\
+             D01/01/1970\nT1.5000\nN1\nPdeposit\n^\n\
+             !Account\nNClient 3\nTBank\n^\n!Type:Bank\n\
+             D01/01/1970\nT4.0000\nPOpening Balance\n^\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_ledger() -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        write_journal(&journal()?, ExportFormat::Ledger, "USD", &mut output)?;
+
+        assert!(String::from_utf8(output)?.starts_with(
+            "1970/01/01 (1) deposit\n  \
+               Equity:Clearing  -1.5000 USD\n  \
+               Assets:Clients:2:Available  1.5000 USD\n\n"
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Context};
+
+/// Links client ids into account groups, e.g. households, whose available
+/// funds are pooled for withdrawals.
+///
+/// Group files are plain text files of `group=client,...` lines:
+///
+/// ```text
+/// smith=1,2
+/// doe=7,8,9
+/// ```
+///
+/// A client can be a member of at most one group.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AccountGroups {
+    group_of: HashMap<u16, String>,
+    members: HashMap<String, Vec<u16>>,
+}
+
+impl AccountGroups {
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read account groups {}", path.display()))?;
+        contents.parse()
+    }
+
+    pub fn group_of(&self, client: u16) -> Option<&str> {
+        self.group_of.get(&client).map(String::as_str)
+    }
+
+    /// The members of the group the client belongs to, if any.
+    pub fn members_of(&self, client: u16) -> Option<&[u16]> {
+        self.group_of
+            .get(&client)
+            .map(|group| self.members[group].as_slice())
+    }
+
+    /// All groups with their members.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u16])> {
+        self.members
+            .iter()
+            .map(|(group, members)| (group.as_str(), members.as_slice()))
+    }
+}
+
+impl std::str::FromStr for AccountGroups {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut groups = Self::default();
+
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (group, clients) = line
+                .split_once('=')
+                .ok_or(anyhow!("Unexpected account group line: {line}"))?;
+            let group = group.trim();
+            if group.is_empty() || groups.members.contains_key(group) {
+                return Err(anyhow!("Missing or duplicate group name in line: {line}"));
+            }
+            let mut members = Vec::new();
+            for client in clients.split(',').map(str::trim) {
+                let client: u16 = client
+                    .parse()
+                    .with_context(|| format!("Invalid client id {client} in group {group}"))?;
+                if let Some(other) = groups.group_of.insert(client, group.to_string()) {
+                    return Err(anyhow!(
+                        "Client {client} is a member of both {other} and {group}"
+                    ));
+                }
+                members.push(client);
+            }
+            groups.members.insert(group.to_string(), members);
+        }
+
+        Ok(groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let groups: AccountGroups = "smith=1, 2\n\ndoe=7\n".parse()?;
+        assert_eq!(groups.group_of(2), Some("smith"));
+        assert_eq!(groups.group_of(3), None);
+        assert_eq!(groups.members_of(1), Some(&[1, 2][..]));
+        assert_eq!(groups.members_of(7), Some(&[7][..]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!("smith".parse::<AccountGroups>().is_err());
+        assert!("=1".parse::<AccountGroups>().is_err());
+        assert!("smith=1,x".parse::<AccountGroups>().is_err());
+        assert!("smith=1\nsmith=2".parse::<AccountGroups>().is_err());
+        assert!("smith=1\ndoe=1".parse::<AccountGroups>().is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use anyhow::anyhow;
+
+use crate::{
+    engine::{Engine, RunSummary},
+    hash::ClientSet,
+    structs::{ClientRecord, Record},
+};
+
+/// Feeds input that is grouped by client into the engine, emitting the final
+/// balances of every client as soon as the records of the next one start and
+/// dropping its account, so only one client is kept in memory at a time.
+///
+/// The clients are emitted in the order of the input. Records of a client
+/// that was already emitted fail the run, as the input is not grouped.
+pub fn run(
+    engine: &mut Engine,
+    records: impl IntoIterator<Item = anyhow::Result<Record>>,
+    mut emit: impl FnMut(ClientRecord) -> anyhow::Result<()>,
+) -> anyhow::Result<RunSummary> {
+    let mut summary = engine.start();
+    let mut current = None;
+    let mut finished = ClientSet::default();
+    for (index, result) in records.into_iter().enumerate() {
+        let row = index as u64 + 1;
+        if let Ok(record) = &result {
+            if current != Some(record.client) {
+                if finished.contains(&record.client) {
+                    return Err(anyhow!(
+                        "The input is not grouped by client, client {} reappears in row {row}",
+                        record.client
+                    ));
+                }
+                if let Some(client) = current.replace(record.client) {
+                    finished.insert(client);
+                    if let Some(account) = engine.ledger_mut().take_client_record(client)? {
+                        emit(account)?;
+                    }
+                }
+            }
+        }
+        engine.feed(&mut summary, row, result)?;
+    }
+    let summary = engine.finish(summary)?;
+    if let Some(client) = current {
+        if let Some(account) = engine.ledger_mut().take_client_record(client)? {
+            emit(account)?;
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{amount::Amount, engine::EngineBuilder, structs::RecordType};
+
+    fn record(
+        record_type: RecordType,
+        client: u16,
+        tx: u32,
+        amount: Option<Amount>,
+    ) -> anyhow::Result<Record> {
+        Ok(Record {
+            record_type,
+            client,
+            tx,
+            amount,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_grouped() -> anyhow::Result<()> {
+        let mut engine = EngineBuilder::new().build();
+        let mut emitted = Vec::new();
+        let summary = run(
+            &mut engine,
+            [
+                record(RecordType::Deposit, 2, 1, Some(Amount::new(3, 0))),
+                record(RecordType::Withdrawal, 2, 2, Some(Amount::new(1, 0))),
+                record(RecordType::Deposit, 1, 3, Some(Amount::new(2, 0))),
+                Err(anyhow!("unreadable row")),
+                record(RecordType::Dispute, 1, 3, None),
+            ],
+            |account| {
+                emitted.push((account.client, account.total, account.held));
+                Ok(())
+            },
+        )?;
+
+        assert_eq!(
+            emitted,
+            [
+                (2, Amount::new(2, 0), Amount::ZERO),
+                (1, Amount::new(2, 0), Amount::new(2, 0))
+            ]
+        );
+        assert_eq!(summary.applied, 4);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(engine.ledger().client_records()?, []);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_grouped_rejects_ungrouped_input() {
+        let mut engine = EngineBuilder::new().build();
+        let result = run(
+            &mut engine,
+            [
+                record(RecordType::Deposit, 1, 1, Some(Amount::new(1, 0))),
+                record(RecordType::Deposit, 2, 2, Some(Amount::new(1, 0))),
+                record(RecordType::Deposit, 1, 3, Some(Amount::new(1, 0))),
+            ],
+            |_| Ok(()),
+        );
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hasher,
+};
+
+/// The multiplier of the Fx hash, as used by rustc.
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+/// A fast, non-cryptographic hasher for small keys, after the Fx hash of
+/// rustc.
+///
+/// Unlike the SipHash of the standard library, it is not resistant to hash
+/// flooding, which is fine for maps keyed by client id, as there are at most
+/// 65536 of them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FxHasher {
+    hash: u64,
+}
+
+impl FxHasher {
+    fn add(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            self.add(u64::from_le_bytes(word));
+        }
+        let mut rest = [0; 8];
+        let remainder = chunks.remainder();
+        if !remainder.is_empty() {
+            rest[..remainder.len()].copy_from_slice(remainder);
+            self.add(u64::from_le_bytes(rest));
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// The hasher of the maps keyed by client id, the standard library's one
+/// with the `std-hasher` feature.
+#[cfg(not(feature = "std-hasher"))]
+pub type ClientHasher = std::hash::BuildHasherDefault<FxHasher>;
+#[cfg(feature = "std-hasher")]
+pub type ClientHasher = std::collections::hash_map::RandomState;
+
+pub type ClientMap<V> = HashMap<u16, V, ClientHasher>;
+pub type ClientSet = HashSet<u16, ClientHasher>;
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{BuildHasher, BuildHasherDefault, Hash};
+
+    use super::*;
+
+    fn hash(value: impl Hash) -> u64 {
+        BuildHasherDefault::<FxHasher>::default().hash_one(value)
+    }
+
+    #[test]
+    fn test_fx_hasher() {
+        assert_eq!(hash(1u16), hash(1u16));
+        assert_ne!(hash(1u16), hash(2u16));
+        assert_ne!(hash("client"), hash("clients"));
+        assert_ne!(hash([1u8; 9].as_slice()), hash([1u8; 8].as_slice()));
+
+        let mut clients = ClientMap::default();
+        for client in 0..=u16::MAX {
+            clients.insert(client, client);
+        }
+        assert_eq!(clients.len(), 65536);
+        assert_eq!(clients.get(&42), Some(&42));
+    }
+}
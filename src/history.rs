@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, io, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::{
+    observer::Observer,
+    structs::{ClientRecord, HistoryRecord, Record},
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Which balances a [`BalanceHistory`] writes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HistorySampling {
+    /// The balances after every applied transaction.
+    #[default]
+    Every,
+    /// The last balances of every client and day (in UTC). Transactions
+    /// without a timestamp are left out.
+    Daily,
+}
+
+impl FromStr for HistorySampling {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "every" => Ok(HistorySampling::Every),
+            "daily" => Ok(HistorySampling::Daily),
+            other => Err(anyhow!(
+                "Unknown history sampling {other}, expected one of: every, daily"
+            )),
+        }
+    }
+}
+
+/// Writes the balances of the clients over time as csv, e.g. to chart the
+/// exposure over a batch.
+///
+/// Rows are written while the engine runs. [`BalanceHistory::finish`] must
+/// be called afterwards to write the last daily samples and to report
+/// errors, which cannot be returned from the observer callbacks.
+pub struct BalanceHistory<W: io::Write> {
+    writer: csv::Writer<W>,
+    sampling: HistorySampling,
+    /// The latest balances of every client, with their day, that are not
+    /// written yet.
+    pending: BTreeMap<u16, (u64, HistoryRecord)>,
+    error: Option<csv::Error>,
+}
+
+impl<W: io::Write> BalanceHistory<W> {
+    pub fn new(writer: csv::Writer<W>, sampling: HistorySampling) -> Self {
+        Self {
+            writer,
+            sampling,
+            pending: BTreeMap::new(),
+            error: None,
+        }
+    }
+
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        for (_, (_, row)) in std::mem::take(&mut self.pending) {
+            self.write(&row);
+        }
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write(&mut self, row: &HistoryRecord) {
+        if self.error.is_none() {
+            self.error = self.writer.serialize(row).err();
+        }
+    }
+}
+
+impl<W: io::Write> Observer for BalanceHistory<W> {
+    fn on_accepted(&mut self, record: &Record, account: &ClientRecord) {
+        let row = HistoryRecord {
+            client: account.client,
+            timestamp: record.timestamp,
+            tx: record.tx,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+        };
+        match (self.sampling, record.timestamp) {
+            (HistorySampling::Every, _) => self.write(&row),
+            (HistorySampling::Daily, Some(timestamp)) => {
+                let day = timestamp / SECONDS_PER_DAY;
+                match self.pending.insert(account.client, (day, row)) {
+                    Some((previous_day, previous)) if previous_day < day => self.write(&previous),
+                    // Keep the later sample if the transactions are out of order.
+                    Some(previous) if previous.0 > day => {
+                        self.pending.insert(account.client, previous);
+                    }
+                    _ => {}
+                }
+            }
+            (HistorySampling::Daily, None) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::amount::Amount;
+    use crate::{engine::EngineBuilder, structs::RecordType};
+
+    fn deposit(client: u16, tx: u32, timestamp: Option<u64>) -> anyhow::Result<Record> {
+        Ok(Record {
+            record_type: RecordType::Deposit,
+            client,
+            tx,
+            amount: Some(Amount::new(1, 0)),
+            timestamp,
+            ..Default::default()
+        })
+    }
+
+    fn history(sampling: HistorySampling) -> anyhow::Result<String> {
+        let history = Rc::new(RefCell::new(BalanceHistory::new(
+            csv::Writer::from_writer(Vec::new()),
+            sampling,
+        )));
+        let mut engine = EngineBuilder::new().observer(history.clone()).build();
+        engine.run([
+            deposit(1, 1, Some(100)),
+            deposit(2, 2, Some(200)),
+            deposit(1, 3, Some(SECONDS_PER_DAY - 1)),
+            deposit(1, 4, None),
+            deposit(1, 5, Some(SECONDS_PER_DAY)),
+        ])?;
+        drop(engine);
+
+        let mut history = Rc::try_unwrap(history)
+            .map_err(|_| anyhow!("history is still shared"))?
+            .into_inner();
+        history.finish()?;
+        Ok(String::from_utf8(history.writer.into_inner()?)?)
+    }
+
+    #[test]
+    fn test_history_every() -> anyhow::Result<()> {
+        assert_eq!(
+            history(HistorySampling::Every)?,
+            "client,timestamp,tx,available,held,total,locked\n\
+             1,100,1,1.0,0.0,1.0,false\n\
+             2,200,2,1.0,0.0,1.0,false\n\
+             1,86399,3,2.0,0.0,2.0,false\n\
+             1,,4,3.0,0.0,3.0,false\n\
+             1,86400,5,4.0,0.0,4.0,false\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_daily() -> anyhow::Result<()> {
+        assert_eq!(
+            history(HistorySampling::Daily)?,
+            "client,timestamp,tx,available,held,total,locked\n\
+             1,86399,3,2.0,0.0,2.0,false\n\
+             1,86400,5,4.0,0.0,4.0,false\n\
+             2,200,2,1.0,0.0,1.0,false\n"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,483 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    rc::Rc,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    plaintext::PlainTextReader,
+    proto::ProtobufReader,
+    structs::{NumberFormat, Record, Source},
+};
+
+/// The column names the engine expects in its input, the ones after `amount`
+/// are optional.
+pub const COLUMNS: [&str; 12] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "timestamp",
+    "counterparty",
+    "tag",
+    "sequence",
+    "tenant",
+    "signature",
+    "reason_code",
+    "escrow",
+];
+
+/// The encoding of transaction input files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    /// Length-delimited protobuf messages, see `proto/record.proto`.
+    Protobuf,
+    /// Beancount or ledger journals, see [`PlainTextReader`].
+    PlainText,
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "protobuf" => Ok(InputFormat::Protobuf),
+            "beancount" | "ledger-cli" | "ledger" => Ok(InputFormat::PlainText),
+            other => Err(anyhow!(
+                "Unknown input format {other}, expected one of: csv, protobuf, beancount, ledger-cli"
+            )),
+        }
+    }
+}
+
+/// How transaction input files are read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputOptions {
+    pub format: InputFormat,
+    /// Field delimiter of the input, which is also used for all csv output.
+    pub delimiter: u8,
+    pub number_format: NumberFormat,
+    /// Pairs of (engine column, input column) used to rename the
+    /// headers of third-party exports before deserialization.
+    pub header_map: Vec<(String, String)>,
+    /// The input has no header row and its columns are in the
+    /// fixed order `type,client,tx,amount`.
+    pub no_headers: bool,
+    /// Capture the values of unknown columns into [`Record::extra`] instead
+    /// of ignoring them, which also tolerates rows that leave them out.
+    pub extra_columns: bool,
+    /// Reject record type aliases like `DEPOSIT` or `withdraw` instead of
+    /// normalizing them.
+    pub strict_types: bool,
+}
+
+impl Default for InputOptions {
+    fn default() -> Self {
+        Self {
+            format: InputFormat::default(),
+            delimiter: b',',
+            number_format: NumberFormat::default(),
+            header_map: Vec::new(),
+            no_headers: false,
+            extra_columns: false,
+            strict_types: false,
+        }
+    }
+}
+
+impl InputOptions {
+    /// Sets the delimiter from a single character, `\t` or `tab`.
+    pub fn set_delimiter(&mut self, delimiter: &str) -> anyhow::Result<()> {
+        self.delimiter = match delimiter {
+            "\\t" | "tab" => b'\t',
+            delimiter if delimiter.len() == 1 => delimiter.as_bytes()[0],
+            _ => {
+                return Err(anyhow!(
+                    "Delimiter has to be a single ascii character, got {delimiter}"
+                ))
+            }
+        };
+        Ok(())
+    }
+
+    /// Adds header mappings from a list like `type=txn_type,client=customer_id`.
+    pub fn add_header_mappings(&mut self, mappings: &str) -> anyhow::Result<()> {
+        for mapping in mappings
+            .split([',', '\n'])
+            .map(str::trim)
+            .filter(|mapping| !mapping.is_empty())
+        {
+            let (column, source) = mapping.split_once('=').ok_or(anyhow!(
+                "Malformed column mapping {mapping}, expected column=header"
+            ))?;
+            let column = column.trim();
+            if !COLUMNS.contains(&column) {
+                return Err(anyhow!(
+                    "Unknown column {column}, expected one of: {}",
+                    COLUMNS.join(", ")
+                ));
+            }
+            self.header_map
+                .push((column.to_string(), source.trim().to_string()));
+        }
+        Ok(())
+    }
+
+    /// Adds header mappings from a file with one `column=header` pair per line.
+    pub fn add_header_mapping_file(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let mappings = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read mapping file {}", path.display()))?;
+        self.add_header_mappings(&mappings)
+    }
+}
+
+/// Opens a transaction input file in the configured format.
+pub fn read_records(
+    path: impl AsRef<Path>,
+    options: &InputOptions,
+) -> anyhow::Result<Box<dyn Iterator<Item = anyhow::Result<Record>>>> {
+    Ok(match options.format {
+        InputFormat::Csv => Box::new(RecordReader::from_path(path, options)?),
+        InputFormat::Protobuf => Box::new(ProtobufReader::new(BufReader::new(File::open(path)?))),
+        InputFormat::PlainText => Box::new(PlainTextReader::new(BufReader::new(File::open(path)?))),
+    })
+}
+
+/// A csv row that is not a valid record, with its fields, so they can be
+/// kept, e.g. in a dead-letter file.
+#[derive(Debug)]
+pub struct UnreadableRow {
+    /// The trimmed fields of the row as a csv line.
+    pub payload: String,
+    pub error: String,
+}
+
+impl Display for UnreadableRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for UnreadableRow {}
+
+/// Encodes the fields as a single csv line without a line break.
+pub fn to_csv_line<'a>(
+    fields: impl IntoIterator<Item = &'a str>,
+    delimiter: u8,
+) -> anyhow::Result<String> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(Vec::new());
+    writer.write_record(fields)?;
+    let line = String::from_utf8(writer.into_inner()?)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Reads transaction records from csv input.
+pub struct RecordReader<R> {
+    reader: csv::Reader<R>,
+    headers: csv::StringRecord,
+    number_format: NumberFormat,
+    delimiter: u8,
+    extra_columns: bool,
+    strict_types: bool,
+    /// The name of the input file, for the sources of the records.
+    file: Option<Rc<str>>,
+}
+
+impl RecordReader<File> {
+    pub fn from_path(path: impl AsRef<Path>, options: &InputOptions) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut reader = Self::from_reader(File::open(path)?, options)?;
+        reader.file = Some(path.display().to_string().into());
+        Ok(reader)
+    }
+}
+
+impl<R: io::Read> RecordReader<R> {
+    pub fn from_reader(reader: R, options: &InputOptions) -> anyhow::Result<Self> {
+        let mut reader = csv_reader(reader, options);
+        let headers = headers(&mut reader, options)?;
+
+        Ok(Self {
+            reader,
+            headers,
+            number_format: options.number_format,
+            delimiter: options.delimiter,
+            extra_columns: options.extra_columns,
+            strict_types: options.strict_types,
+            file: None,
+        })
+    }
+}
+
+/// A csv reader of transaction records, with the rows as they are.
+pub fn csv_reader<R: io::Read>(reader: R, options: &InputOptions) -> csv::Reader<R> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .delimiter(options.delimiter)
+        .flexible(true)
+        .has_headers(!options.no_headers)
+        .from_reader(reader)
+}
+
+/// The headers of the input, renamed by the header mappings, or the fixed
+/// column order without a header row.
+pub fn headers<R: io::Read>(
+    reader: &mut csv::Reader<R>,
+    options: &InputOptions,
+) -> csv::Result<csv::StringRecord> {
+    if options.no_headers {
+        return Ok(csv::StringRecord::from(COLUMNS.to_vec()));
+    }
+    Ok(reader
+        .headers()?
+        .iter()
+        .map(|header| {
+            options
+                .header_map
+                .iter()
+                .find(|(_, source)| source == header)
+                .map_or(header, |(column, _)| column.as_str())
+        })
+        .collect())
+}
+
+impl<R> RecordReader<R> {
+    /// Deserializes the known columns of the row, capturing the non-empty
+    /// values of the others by header, or by position if they have none.
+    fn read_with_extra(&self, row: &csv::StringRecord) -> anyhow::Result<Record> {
+        let mut headers = csv::StringRecord::new();
+        let mut fields = csv::StringRecord::new();
+        let mut extra = BTreeMap::new();
+        for (index, field) in row.iter().enumerate() {
+            match self.headers.get(index) {
+                Some(header) if COLUMNS.contains(&header) => {
+                    headers.push_field(header);
+                    fields.push_field(field);
+                }
+                _ if field.is_empty() => {}
+                Some(header) => {
+                    extra.insert(header.to_string(), field.to_string());
+                }
+                None => {
+                    extra.insert((index + 1).to_string(), field.to_string());
+                }
+            }
+        }
+        let mut record =
+            Record::from_row(&fields, &headers, self.number_format, self.strict_types)?;
+        record.extra = Some(extra);
+        Ok(record)
+    }
+}
+
+impl<R: io::Read> Iterator for RecordReader<R> {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.reader.records().next()? {
+            Ok(row) => row,
+            Err(err) => return Some(Err(err.into())),
+        };
+        let record = if self.extra_columns {
+            self.read_with_extra(&row)
+        } else {
+            Record::from_row(&row, &self.headers, self.number_format, self.strict_types)
+        };
+        let source = self
+            .file
+            .clone()
+            .zip(row.position())
+            .map(|(file, position)| Source {
+                file,
+                line: position.line(),
+            });
+        Some(
+            record
+                .map(|record| Record { source, ..record })
+                .map_err(|err| match to_csv_line(&row, self.delimiter) {
+                    Ok(payload) => UnreadableRow {
+                        payload,
+                        error: format!("{err:#}"),
+                    }
+                    .into(),
+                    Err(_) => err,
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::structs::RecordType;
+
+    #[test]
+    fn test_header_mapping() -> anyhow::Result<()> {
+        let data = "\
+            customer_id, txn_type, amount, id
+            1, deposit, 2.5, 7";
+
+        let mut options = InputOptions::default();
+        options.add_header_mappings("type=txn_type, client=customer_id,tx=id")?;
+
+        let records = RecordReader::from_reader(data.as_bytes(), &options)?
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        assert_eq!(
+            records,
+            vec![Record {
+                record_type: RecordType::Deposit,
+                client: 1,
+                tx: 7,
+                amount: Some(Amount::new(25, 1)),
+                ..Default::default()
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_headers() -> anyhow::Result<()> {
+        let data = "\
+            deposit, 1, 1, 1.5
+            dispute, 1, 1";
+
+        let options = InputOptions {
+            no_headers: true,
+            ..Default::default()
+        };
+
+        let records = RecordReader::from_reader(data.as_bytes(), &options)?
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        assert_eq!(
+            records,
+            vec![
+                Record {
+                    record_type: RecordType::Deposit,
+                    client: 1,
+                    tx: 1,
+                    amount: Some(Amount::new(15, 1)),
+                    ..Default::default()
+                },
+                Record {
+                    record_type: RecordType::Dispute,
+                    client: 1,
+                    tx: 1,
+                    amount: None,
+                    ..Default::default()
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sources() -> anyhow::Result<()> {
+        let records =
+            RecordReader::from_path("samples/transactions.csv", &InputOptions::default())?
+                .collect::<anyhow::Result<Vec<_>>>()?;
+        let source = records[1]
+            .source
+            .as_ref()
+            .ok_or(anyhow!("missing source"))?;
+        assert_eq!(source.to_string(), "samples/transactions.csv:3");
+
+        let records = RecordReader::from_reader(
+            "type,client,tx,amount\ndeposit,1,1,1\n".as_bytes(),
+            &InputOptions::default(),
+        )?;
+        assert!(records
+            .map(|record| record.map(|record| record.source))
+            .all(|source| matches!(source, Ok(None))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extra_columns() -> anyhow::Result<()> {
+        let data = "\
+            type, client, tx, amount, memo, source
+            deposit, 1, 1, 1.5, rent, erp
+            deposit, 1, 2, 2.0
+            dispute, 1, 1, , , , late";
+
+        let options = InputOptions {
+            extra_columns: true,
+            ..Default::default()
+        };
+        let records = RecordReader::from_reader(data.as_bytes(), &options)?
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let extra = |pairs: &[(&str, &str)]| {
+            Some(
+                pairs
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            records[0].extra,
+            extra(&[("memo", "rent"), ("source", "erp")])
+        );
+        assert_eq!(records[1].amount, Some(Amount::new(2, 0)));
+        assert_eq!(records[1].extra, extra(&[]));
+        assert_eq!(records[2].record_type, RecordType::Dispute);
+        assert_eq!(records[2].extra, extra(&[("7", "late")]));
+
+        // Without capturing, rows leaving out the unknown columns fail.
+        let mut records = RecordReader::from_reader(data.as_bytes(), &InputOptions::default())?;
+        assert_eq!(
+            records.next().transpose()?.and_then(|record| record.extra),
+            None
+        );
+        assert!(records.next().transpose().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delimiter() -> anyhow::Result<()> {
+        let data = "type\tclient\ttx\tamount\ndeposit\t1\t1\t1.5";
+
+        let mut options = InputOptions::default();
+        options.set_delimiter("\\t")?;
+
+        let records = RecordReader::from_reader(data.as_bytes(), &options)?
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].amount, Some(Amount::new(15, 1)));
+
+        options.set_delimiter(";")?;
+        assert_eq!(options.delimiter, b';');
+        options.set_delimiter("tab")?;
+        assert_eq!(options.delimiter, b'\t');
+        assert!(options.set_delimiter(";;").is_err());
+        assert!(options.set_delimiter("€").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_mapping_invalid() {
+        let mut options = InputOptions::default();
+        assert!(options.add_header_mappings("kind=txn_type").is_err());
+        assert!(options.add_header_mappings("type").is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::HashMap, fmt::Display};
+
+use serde::Serialize;
+
+use crate::{amount::Amount, error::LedgerError};
+
+/// An account that postings are booked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PostingAccount {
+    /// Funds of a client that are available for withdrawal.
+    Available(u16),
+    /// Funds of a client that are held because of a dispute.
+    Held(u16),
+    /// Funds of a client in escrow buckets.
+    Escrow(u16),
+    /// Internal account for funds moving into or out of the engine,
+    /// i.e. the counterpart of deposits, withdrawals and chargebacks.
+    Clearing,
+    /// Internal account for balances carried over from a previous run.
+    Opening,
+    /// Internal account for adjustments, which sets them apart from
+    /// customer-originated movements.
+    Adjustments,
+    /// Internal account for negative balances written off as unrecoverable.
+    Losses,
+}
+
+impl Display for PostingAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostingAccount::Available(client) => write!(f, "client:{client}:available"),
+            PostingAccount::Held(client) => write!(f, "client:{client}:held"),
+            PostingAccount::Escrow(client) => write!(f, "client:{client}:escrow"),
+            PostingAccount::Clearing => write!(f, "clearing"),
+            PostingAccount::Opening => write!(f, "opening"),
+            PostingAccount::Adjustments => write!(f, "adjustments"),
+            PostingAccount::Losses => write!(f, "losses"),
+        }
+    }
+}
+
+/// A single line of the journal.
+/// A positive amount increases the balance of the account, a negative one decreases it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Posting {
+    pub tx: u32,
+    pub account: PostingAccount,
+    pub amount: Amount,
+}
+
+#[derive(Debug, Serialize)]
+struct PostingRow {
+    tx: u32,
+    account: String,
+    amount: Amount,
+}
+
+/// Double-entry journal of every balance movement in the ledger.
+///
+/// Postings are always recorded in pairs of equal and opposite amounts,
+/// so the sum of all postings is zero by construction.
+#[derive(Debug, Default)]
+pub struct Journal {
+    postings: Vec<Posting>,
+}
+
+impl Journal {
+    /// Moves `amount` from one account to another.
+    pub fn transfer(
+        &mut self,
+        tx: u32,
+        from: PostingAccount,
+        to: PostingAccount,
+        amount: Amount,
+    ) -> Result<(), LedgerError> {
+        self.postings.push(Posting {
+            tx,
+            account: from,
+            amount: amount.checked_neg()?,
+        });
+        self.postings.push(Posting {
+            tx,
+            account: to,
+            amount,
+        });
+        Ok(())
+    }
+
+    pub fn postings(&self) -> &[Posting] {
+        &self.postings
+    }
+
+    /// Checks that every pair of postings cancels out.
+    pub fn is_balanced(&self) -> bool {
+        self.postings.chunks(2).all(|pair| {
+            matches!(pair, [debit, credit]
+                if debit.amount.checked_add(credit.amount) == Ok(Amount::ZERO))
+        })
+    }
+
+    /// Sums up the postings of every account.
+    pub fn balances(&self) -> Result<HashMap<PostingAccount, Amount>, LedgerError> {
+        let mut balances = HashMap::new();
+        for posting in &self.postings {
+            let balance: &mut Amount = balances.entry(posting.account).or_default();
+            *balance = balance.checked_add(posting.amount)?;
+        }
+        Ok(balances)
+    }
+
+    /// Writes all postings as csv rows of `tx,account,amount`.
+    pub fn write_csv<W: std::io::Write>(&self, writer: &mut csv::Writer<W>) -> anyhow::Result<()> {
+        for posting in &self.postings {
+            writer.serialize(PostingRow {
+                tx: posting.tx,
+                account: posting.account.to_string(),
+                amount: posting.amount,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer() -> anyhow::Result<()> {
+        let mut journal = Journal::default();
+        journal.transfer(
+            1,
+            PostingAccount::Clearing,
+            PostingAccount::Available(1),
+            Amount::new(25, 1),
+        )?;
+        journal.transfer(
+            1,
+            PostingAccount::Available(1),
+            PostingAccount::Held(1),
+            Amount::new(25, 1),
+        )?;
+
+        assert!(journal.is_balanced());
+        assert_eq!(journal.postings().len(), 4);
+
+        let balances = journal.balances()?;
+        assert_eq!(balances[&PostingAccount::Clearing], Amount::new(-25, 1));
+        assert_eq!(balances[&PostingAccount::Available(1)], Amount::ZERO);
+        assert_eq!(balances[&PostingAccount::Held(1)], Amount::new(25, 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_csv() -> anyhow::Result<()> {
+        let mut journal = Journal::default();
+        journal.transfer(
+            7,
+            PostingAccount::Clearing,
+            PostingAccount::Available(3),
+            Amount::new(15, 1),
+        )?;
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        journal.write_csv(&mut writer)?;
+        let output = String::from_utf8(writer.into_inner()?)?;
+
+        assert_eq!(
+            output,
+            "tx,account,amount\n7,clearing,-1.5\n7,client:3:available,1.5\n"
+        );
+
+        Ok(())
+    }
+}
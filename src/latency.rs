@@ -0,0 +1,178 @@
+use std::{fmt::Display, time::Duration};
+
+use serde::Serialize;
+
+/// Sub-buckets per power of two, which bounds the error of the percentiles
+/// to an eighth of the value.
+const SUB_BUCKETS: u64 = 8;
+const BUCKETS: usize = 496;
+
+/// The stages of processing a record that are timed separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading and parsing the record from the input.
+    Read,
+    /// Checking the signature of admin records and validating the record.
+    Validate,
+    /// Applying the record to the ledger and notifying the observers.
+    Apply,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::Read => write!(f, "read"),
+            Stage::Validate => write!(f, "validate"),
+            Stage::Apply => write!(f, "apply"),
+        }
+    }
+}
+
+/// A histogram of durations with logarithmic buckets, so it takes constant
+/// memory however many records are timed.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[bucket(nanos)] += 1;
+        self.count += 1;
+        self.max = self.max.max(nanos);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The duration that the given share of the recorded ones, between 0 and
+    /// 1, does not exceed, as the upper bound of its bucket.
+    pub fn percentile(&self, share: f64) -> Duration {
+        let rank = (share * self.count as f64).ceil().max(1.) as u64;
+        let mut seen = 0;
+        for (index, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(upper_bound(index).min(self.max));
+            }
+        }
+        Duration::from_nanos(self.max)
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+}
+
+fn bucket(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS {
+        return nanos as usize;
+    }
+    let exponent = u64::from(63 - nanos.leading_zeros());
+    let sub_bucket = (nanos >> (exponent - 3)) & (SUB_BUCKETS - 1);
+    ((exponent - 2) * SUB_BUCKETS + sub_bucket) as usize
+}
+
+fn upper_bound(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < SUB_BUCKETS {
+        return bucket;
+    }
+    let exponent = bucket / SUB_BUCKETS + 2;
+    let sub_bucket = bucket % SUB_BUCKETS;
+    let bound = u128::from(SUB_BUCKETS + sub_bucket + 1) << (exponent - 3);
+    u64::try_from(bound - 1).unwrap_or(u64::MAX)
+}
+
+/// A row of the latency report, with durations in microseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LatencyRecord {
+    pub stage: String,
+    pub count: u64,
+    pub p50_us: f64,
+    pub p90_us: f64,
+    pub p99_us: f64,
+    pub max_us: f64,
+}
+
+/// The processing latencies of an engine per stage.
+#[derive(Debug, Clone, Default)]
+pub struct Latencies {
+    read: Histogram,
+    validate: Histogram,
+    apply: Histogram,
+}
+
+impl Latencies {
+    pub fn record(&mut self, stage: Stage, duration: Duration) {
+        match stage {
+            Stage::Read => self.read.record(duration),
+            Stage::Validate => self.validate.record(duration),
+            Stage::Apply => self.apply.record(duration),
+        }
+    }
+
+    pub fn report(&self) -> Vec<LatencyRecord> {
+        let micros = |duration: Duration| duration.as_nanos() as f64 / 1e3;
+        [
+            (Stage::Read, &self.read),
+            (Stage::Validate, &self.validate),
+            (Stage::Apply, &self.apply),
+        ]
+        .into_iter()
+        .map(|(stage, histogram)| LatencyRecord {
+            stage: stage.to_string(),
+            count: histogram.count(),
+            p50_us: micros(histogram.percentile(0.5)),
+            p90_us: micros(histogram.percentile(0.9)),
+            p99_us: micros(histogram.percentile(0.99)),
+            max_us: micros(histogram.max()),
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        for nanos in [0, 1, 7, 8, 9, 15, 16, 1_000, 123_456_789, u64::MAX] {
+            let bucket = bucket(nanos);
+            assert!(bucket < BUCKETS);
+            assert!(upper_bound(bucket) >= nanos, "{nanos}");
+            assert!(upper_bound(bucket) - nanos <= nanos / 8, "{nanos}");
+        }
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = Histogram::default();
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+
+        assert_eq!(histogram.count(), 100);
+        let p50 = histogram.percentile(0.5).as_micros();
+        assert!((50..=57).contains(&p50), "{p50}");
+        let p99 = histogram.percentile(0.99).as_micros();
+        assert!((99..=100).contains(&p99), "{p99}");
+        assert_eq!(histogram.max(), Duration::from_micros(100));
+        assert_eq!(Histogram::default().percentile(0.5), Duration::ZERO);
+    }
+}
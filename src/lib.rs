@@ -0,0 +1,45 @@
+#![forbid(unsafe_code)]
+
+pub mod account;
+pub mod admin;
+pub mod amount;
+pub mod audit;
+pub mod check;
+pub mod cli;
+pub mod client_ids;
+pub mod clock;
+pub mod control;
+pub mod counterparty;
+pub mod deadletter;
+pub mod engine;
+pub mod error;
+pub mod export;
+pub mod group;
+pub mod grouped;
+pub mod hash;
+pub mod history;
+pub mod input;
+pub mod journal;
+pub mod latency;
+pub mod manifest;
+pub mod observer;
+pub mod output;
+pub mod payout;
+pub mod plaintext;
+pub mod proto;
+pub mod pseudonym;
+pub mod quarantine;
+pub mod registry;
+pub mod reorder;
+pub mod repl;
+pub mod replay;
+pub mod schema;
+pub mod sha256;
+pub mod shadow;
+pub mod snapshot;
+pub mod store;
+pub mod structs;
+pub mod system;
+pub mod tag;
+pub mod template;
+pub mod tenant;
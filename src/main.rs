@@ -1,79 +1,737 @@
 #![forbid(unsafe_code)]
 
-use std::{env, io};
+use std::{
+    cell::RefCell,
+    env,
+    io::{self, Write},
+    rc::Rc,
+};
 
-use anyhow::anyhow;
-
-mod account;
-mod structs;
+use toy_payments_engine::{
+    admin, amount, audit, check, cli, control, deadletter, engine, export, group, grouped, history,
+    input, manifest, output, payout, pseudonym, quarantine, registry, reorder, repl, replay,
+    schema, sha256, shadow, snapshot, structs, template, tenant,
+};
 
 fn main() -> anyhow::Result<()> {
-    let mut args = env::args();
-    if args.len() != 2 {
-        return Err(anyhow!(
-            "Expected exactly one argument: the path to the transaction csv file."
-        ));
+    let options = cli::Options::parse(env::args().skip(1))?;
+
+    if options.command == cli::Command::InspectSnapshot {
+        let bytes = std::fs::read(&options.file_path)?;
+        let (version, sections) = snapshot::inspect(&bytes)?;
+        println!("version {version}");
+        let snapshot = snapshot::Snapshot::from_bytes(&bytes);
+        for section in &sections {
+            println!("{section}");
+        }
+        let snapshot = snapshot?;
+        println!(
+            "{} snapshot: {} accounts, {} transactions",
+            if snapshot.delta { "delta" } else { "full" },
+            snapshot.accounts.len(),
+            snapshot
+                .accounts
+                .iter()
+                .map(|account| account.transactions.len())
+                .sum::<usize>()
+        );
+        return Ok(());
     }
 
-    let file_path = args.next_back().expect("No arguments provided");
+    if options.command == cli::Command::CompactSnapshot {
+        let mut compacted = snapshot::Snapshot::from_path(&options.file_path)?;
+        for delta in &options.deltas {
+            compacted.merge(&snapshot::Snapshot::from_path(delta)?);
+        }
+        if let Some(snapshot_path) = &options.snapshot {
+            compacted.write_to_path(snapshot_path)?;
+        }
+        return Ok(());
+    }
 
-    let mut reader = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .flexible(true)
-        .has_headers(true)
-        .from_path(file_path)?;
+    if options.command == cli::Command::FoldSnapshot {
+        let mut folded = snapshot::Snapshot::from_path(&options.file_path)?;
+        if folded.delta {
+            return Err(anyhow::anyhow!(
+                "Cannot fold the delta snapshot {}, compact it first",
+                options.file_path
+            ));
+        }
+        let count = folded.fold(options.retention)?;
+        eprintln!("{count} transactions folded into opening balances");
+        if let Some(snapshot_path) = &options.snapshot {
+            folded.write_to_path(snapshot_path)?;
+        }
+        return Ok(());
+    }
 
-    let mut account_ledger = account::Ledger::new();
+    if options.command == cli::Command::VerifyAudit {
+        let entries = audit::verify(&options.file_path, options.input.delimiter)?;
+        println!("{entries} audit log entries verified");
+        return Ok(());
+    }
 
-    for result in reader.deserialize::<structs::Record>() {
-        let record = match result {
-            Ok(r) => r,
-            Err(err) => {
-                eprintln!("Failed to deserialize record: {err}");
-                continue;
+    if options.command == cli::Command::CheckSnapshot {
+        let violations = check::check(&snapshot::Snapshot::from_path(&options.file_path)?)?;
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(options.input.delimiter)
+            .from_writer(io::stdout());
+        for violation in &violations {
+            writer.serialize(violation)?;
+        }
+        writer.flush()?;
+        if !violations.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Found {} invariant violations",
+                violations.len()
+            ));
+        }
+        return Ok(());
+    }
+
+    if options.validate_schema {
+        let violations = schema::validate(&options.file_path, &options.input)?;
+        if !violations.is_empty() {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(options.input.delimiter)
+                .from_writer(io::stdout());
+            for violation in &violations {
+                writer.serialize(violation)?;
             }
-        };
-        if let Err(err) = record.validate() {
-            eprintln!("Failed to validate the record: {err}");
-            continue;
+            writer.flush()?;
+            return Err(anyhow::anyhow!(
+                "Found {} schema violations, no records were applied",
+                violations.len()
+            ));
         }
+    }
+
+    let admin_key = options
+        .admin_key
+        .as_ref()
+        .map(admin::AdminKey::from_key_file)
+        .transpose()?;
 
-        let account = account_ledger.get_or_insert_customer(record.client);
+    if let (cli::Command::Shadow, Some(against)) = (options.command, &options.against) {
+        let shadow_options = cli::Options::parse(
+            against
+                .split_whitespace()
+                .map(str::to_string)
+                .chain([options.file_path.clone()]),
+        )?;
+        let mut primary = shadow_engine(&options, admin_key.as_ref());
+        let mut shadow = shadow_engine(&shadow_options, admin_key.as_ref());
+        shadow::run(
+            &mut primary,
+            &mut shadow,
+            input::read_records(&options.file_path, &options.input)?,
+        )?;
+
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(options.input.delimiter)
+            .from_writer(io::stdout());
+        let diffs = shadow::diff(primary.ledger(), shadow.ledger())?;
+        for diff in &diffs {
+            writer.serialize(diff)?;
+        }
+        writer.flush()?;
+        eprintln!("{} accounts differ in the shadow run", diffs.len());
+        return Ok(());
+    }
 
-        let outcome = match record.record_type {
-            structs::RecordType::Deposit => {
-                let amount = record.amount.ok_or(anyhow!("Missing amount for deposit"))?;
-                account.deposit(record.tx, amount)
+    if let (cli::Command::SignAdmin, Some(admin_key)) = (options.command, &admin_key) {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(options.input.delimiter)
+            .from_writer(io::stdout());
+        writer.write_record(["type", "client", "tx", "amount", "reason_code", "signature"])?;
+        for (index, record) in input::read_records(&options.file_path, &options.input)?.enumerate()
+        {
+            let record = record?;
+            if !record.record_type.is_admin() {
+                return Err(anyhow::anyhow!(
+                    "Row {} is not an admin operation",
+                    index + 1
+                ));
             }
-            structs::RecordType::Withdrawal => {
-                let amount = record
+            writer.write_record([
+                record.record_type.to_string(),
+                record.client.to_string(),
+                record.tx.to_string(),
+                record
                     .amount
-                    .ok_or(anyhow!("Missing amount for withdrawal"))?;
-                account.withdraw(record.tx, amount)
+                    .map(|amount| amount.to_string())
+                    .unwrap_or_default(),
+                record.reason_code.clone().unwrap_or_default(),
+                admin_key.sign(&record),
+            ])?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    if options.command == cli::Command::Repl {
+        let mut engine_builder = engine::EngineBuilder::new()
+            .retention(options.retention)
+            .freeze(options.freeze)
+            .empty_accounts(options.empty_accounts)
+            .amount_limits(options.amount_limits)
+            .client_ids(options.client_ids.clone());
+        if let Some(admin_key) = admin_key {
+            engine_builder = engine_builder.admin_key(admin_key);
+        }
+        let mut engine = engine_builder.build();
+        if !options.file_path.is_empty() {
+            let bytes = std::fs::read(&options.file_path)?;
+            if snapshot::is_snapshot(&bytes) {
+                engine
+                    .ledger_mut()
+                    .restore(&snapshot::Snapshot::from_bytes(&bytes)?)?;
+            } else {
+                engine.run(input::read_records(&options.file_path, &options.input)?)?;
+            }
+        }
+        repl::Repl::new(engine.into_ledger()).run(io::stdin().lock(), io::stdout())?;
+        return Ok(());
+    }
+
+    let input_hash = if options.manifest.is_some()
+        || options.registry.is_some()
+        || options.run_manifest.is_some()
+    {
+        let input_hash = manifest::hash_file(&options.file_path)?;
+        eprintln!("Processing {} with sha256 {input_hash}", options.file_path);
+        Some(input_hash)
+    } else {
+        None
+    };
+
+    let manifest = match (&options.manifest, &input_hash) {
+        (Some(path), Some(input_hash)) => {
+            let manifest = manifest::Manifest::from_path(path)?;
+            manifest.verify_hash(input_hash)?;
+            Some(manifest)
+        }
+        _ => None,
+    };
+
+    let mut input_registry = match (&options.registry, &input_hash) {
+        (Some(path), Some(input_hash)) => {
+            let input_registry = registry::InputRegistry::load(path)?;
+            if input_registry.contains(input_hash) {
+                eprintln!(
+                    "Warning: the content of {} has already been processed before",
+                    options.file_path
+                );
             }
-            structs::RecordType::Dispute => account.dispute(record.tx),
-            structs::RecordType::Resolve => account.resolve(record.tx),
-            structs::RecordType::Chargeback => account.chargeback(record.tx),
+            Some(input_registry)
+        }
+        _ => None,
+    };
+
+    let mut amount_limits = options.amount_limits;
+    let records = if options.command == cli::Command::ApplyQuarantine {
+        // Approved records were already reviewed, so they skip the risk checks.
+        amount_limits.max_amount = amount::Amount::MAX;
+        let approved = quarantine::read_approved(&options.file_path, options.input.delimiter)?;
+        Box::new(approved.into_iter().map(Ok))
+    } else {
+        input::read_records(&options.file_path, &options.input)?
+    };
+    let records: Box<dyn Iterator<Item = anyhow::Result<structs::Record>>> =
+        match options.reorder_window {
+            Some(window) => Box::new(reorder::Reorder::new(records, window)),
+            None => records,
         };
+    let records: Box<dyn Iterator<Item = anyhow::Result<structs::Record>>> = match options.at {
+        Some(at) => Box::new(replay::until(records, at)),
+        None => records,
+    };
+
+    if let Some(tenants_dir) = &options.tenants {
+        let settings = options
+            .tenant_settings
+            .as_ref()
+            .map(tenant::TenantSettings::from_path)
+            .transpose()?
+            .unwrap_or_default();
+        let (
+            retention,
+            freeze,
+            empty_accounts,
+            omit_zero_accounts,
+            strict,
+            defer_disputes,
+            max_tps,
+        ) = (
+            options.retention,
+            options.freeze,
+            options.empty_accounts,
+            options.omit_zero_accounts,
+            options.strict,
+            options.defer_disputes,
+            options.max_tps,
+        );
+        let client_ids = options.client_ids.clone();
+        let tenants = tenant::Tenants::new(move |tenant| {
+            let mut engine_builder = engine::EngineBuilder::new()
+                .retention(retention)
+                .freeze(settings.freeze(tenant, freeze)?)
+                .empty_accounts(empty_accounts)
+                .omit_zero_accounts(omit_zero_accounts)
+                .amount_limits(settings.amount_limits(tenant, amount_limits))
+                .client_ids(settings.client_ids(tenant, client_ids.clone())?)
+                .strict(strict)
+                .defer_disputes(defer_disputes);
+            if let Some(admin_key) = &admin_key {
+                engine_builder = engine_builder.admin_key(admin_key.clone());
+            }
+            if let Some(max_tps) = max_tps {
+                engine_builder = engine_builder.max_tps(max_tps);
+            }
+            Ok(engine_builder.build())
+        });
+        let ledgers = tenants.run(records)?;
+
+        if let Some(manifest) = &manifest {
+            manifest.verify_rows(ledgers.values().map(|(_, summary)| summary.rows).sum())?;
+        }
+        std::fs::create_dir_all(tenants_dir)?;
+        for (tenant, (ledger, _)) in &ledgers {
+            let written_off = ledger.trial_balance()?.written_off;
+            if written_off > amount::Amount::ZERO {
+                eprintln!("Wrote off {written_off} of negative balances in tenant {tenant}");
+            }
+            let path = std::path::Path::new(tenants_dir).join(format!("{tenant}.csv"));
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(options.input.delimiter)
+                .from_path(path)?;
+            for account in ledger.client_records()? {
+                writer.serialize(
+                    options
+                        .precision
+                        .apply(&account, options.output_number_format),
+                )?;
+            }
+            writer.flush()?;
+        }
+        if let (Some(input_registry), Some(input_hash)) = (&mut input_registry, &input_hash) {
+            input_registry.record(input_hash, &options.file_path)?;
+        }
+        return Ok(());
+    }
+    let mut pseudonymizer = options
+        .pseudonymize
+        .as_ref()
+        .map(pseudonym::Pseudonymizer::from_key_file)
+        .transpose()?;
+    let template = options
+        .template
+        .as_ref()
+        .map(template::Template::from_path)
+        .transpose()?;
+
+    let groups = options
+        .groups
+        .as_ref()
+        .map(group::AccountGroups::from_path)
+        .transpose()?;
+
+    let balance_history = match &options.balance_history {
+        Some(path) => Some(Rc::new(RefCell::new(history::BalanceHistory::new(
+            csv::WriterBuilder::new()
+                .delimiter(options.input.delimiter)
+                .from_path(path)?,
+            options.history_sampling,
+        )))),
+        None => None,
+    };
+
+    let audit_log = match &options.audit_log {
+        Some(path) => Some(Rc::new(RefCell::new(audit::AuditLog::new(
+            csv::WriterBuilder::new()
+                .delimiter(options.input.delimiter)
+                .from_path(path)?,
+        )))),
+        None => None,
+    };
+
+    let dead_letters = match &options.dead_letter {
+        Some(path) => Some(Rc::new(RefCell::new(deadletter::DeadLetters::new(
+            csv::WriterBuilder::new()
+                .delimiter(options.input.delimiter)
+                .from_path(path)?,
+            options.input.delimiter,
+        )))),
+        None => None,
+    };
+
+    let quarantine = match &options.quarantine {
+        Some(path) => Some(Rc::new(RefCell::new(quarantine::Quarantine::new(
+            csv::WriterBuilder::new()
+                .delimiter(options.input.delimiter)
+                .from_path(path)?,
+        )))),
+        None => None,
+    };
+
+    let mut engine_builder = engine::EngineBuilder::new()
+        .retention(options.retention)
+        .freeze(options.freeze)
+        .empty_accounts(options.empty_accounts)
+        .omit_zero_accounts(options.omit_zero_accounts)
+        .groups(groups.unwrap_or_default())
+        .amount_limits(amount_limits)
+        .client_ids(options.client_ids.clone())
+        .quarantine(quarantine.is_some())
+        .journal(
+            options.journal.is_some()
+                || options.system_accounts.is_some()
+                || options.command == cli::Command::Export,
+        )
+        .strict(options.strict)
+        .defer_disputes(options.defer_disputes)
+        .latency(options.latency.is_some());
+    if let Some(expected_clients) = options.expected_clients {
+        engine_builder = engine_builder.expected_clients(expected_clients);
+    }
+    if let Some(admin_key) = admin_key {
+        engine_builder = engine_builder.admin_key(admin_key);
+    }
+    if let Some(max_tps) = options.max_tps {
+        engine_builder = engine_builder.max_tps(max_tps);
+    }
+    if let Some(balance_history) = &balance_history {
+        engine_builder = engine_builder.observer(balance_history.clone());
+    }
+    if let Some(audit_log) = &audit_log {
+        engine_builder = engine_builder.observer(audit_log.clone());
+    }
+    if let Some(dead_letters) = &dead_letters {
+        engine_builder = engine_builder.observer(dead_letters.clone());
+    }
+    if let Some(quarantine) = &quarantine {
+        engine_builder = engine_builder.observer(quarantine.clone());
+    }
+    let mut engine = engine_builder.build();
 
-        if let Err(err) = outcome {
+    if let Some(restore) = &options.restore {
+        let snapshot = snapshot::Snapshot::from_path(restore)?;
+        if snapshot.delta {
+            return Err(anyhow::anyhow!(
+                "Cannot restore from the delta snapshot {restore}, compact it first"
+            ));
+        }
+        engine.ledger_mut().restore(&snapshot)?;
+    }
+
+    if let Some(opening_balances) = &options.opening_balances {
+        let mut opening_reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .delimiter(options.input.delimiter)
+            .has_headers(true)
+            .from_path(opening_balances)?;
+        for record in opening_reader.deserialize::<structs::ClientRecord>() {
+            engine.ledger_mut().open_account(&record?)?;
+        }
+    }
+
+    if let Some(opening_transactions) = &options.opening_transactions {
+        for record in input::read_records(opening_transactions, &options.input)? {
+            let record = record?;
+            record.validate_with(&options.amount_limits)?;
+            engine.ledger_mut().open_transaction(&record)?;
+        }
+    }
+
+    // Everything written to stdout is hashed for the run manifest.
+    let output_hasher = Rc::new(RefCell::new(sha256::Sha256::default()));
+    let output = || manifest::HashingWriter::new(io::stdout(), output_hasher.clone());
+
+    let mut control_totals = control::ControlTotals::default();
+    let summary = if options.grouped_by_client {
+        // The accounts are taken out of the ledger as they are written, so
+        // the accounts output below is empty.
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(options.input.delimiter)
+            .from_writer(output());
+        let summary = grouped::run(&mut engine, records, |account| {
+            control_totals.add(&account)?;
+            Ok(writer.serialize(
+                options
+                    .precision
+                    .apply(&account, options.output_number_format),
+            )?)
+        })?;
+        writer.flush()?;
+        summary
+    } else {
+        engine.run(records)?
+    };
+    if let (Some(latencies), Some(path)) = (engine.latencies(), &options.latency) {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(options.input.delimiter)
+            .from_path(path)?;
+        for record in latencies.report() {
+            writer.serialize(record)?;
+        }
+        writer.flush()?;
+    }
+    let mut account_ledger = engine.into_ledger();
+
+    let mut settlements = Vec::new();
+    if let Some(instructions) = &options.instructions {
+        let instructions = payout::read_instructions(instructions, options.input.delimiter)?;
+        let results = payout::pay_out(&mut account_ledger, &instructions, &amount_limits);
+        for (row, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(settlement) => settlements.push(settlement),
+                Err(err) if options.strict => {
+                    return Err(err.context(format!("Failed at payout instruction {}", row + 1)))
+                }
+                Err(err) => eprintln!("{err}"),
+            }
+        }
+    }
+    if let Some(balance_history) = &balance_history {
+        balance_history.borrow_mut().finish()?;
+    }
+    if let Some(audit_log) = &audit_log {
+        audit_log.borrow_mut().finish()?;
+    }
+    if let Some(dead_letters) = &dead_letters {
+        dead_letters.borrow_mut().finish()?;
+    }
+    if let (Some(quarantine), Some(path)) = (&quarantine, &options.quarantine) {
+        quarantine.borrow_mut().finish()?;
+        if summary.quarantined > 0 {
             eprintln!(
-                "Failed to perform {} operation with transaction {} on account {}: {}",
-                record.record_type, record.tx, record.client, err
+                "Quarantined {} records for review in {path}",
+                summary.quarantined
             );
-        };
+        }
+    }
+
+    let written_off = account_ledger.trial_balance()?.written_off;
+    if written_off > amount::Amount::ZERO {
+        eprintln!("Wrote off {written_off} of negative balances");
+    }
+
+    if let Some(manifest) = &manifest {
+        manifest.verify_rows(summary.rows)?;
     }
 
+    account_ledger.verify_journal()?;
+    if let (Some(journal_path), Some(journal)) = (&options.journal, account_ledger.journal()) {
+        journal.write_csv(
+            &mut csv::WriterBuilder::new()
+                .delimiter(options.input.delimiter)
+                .from_path(journal_path)?,
+        )?;
+    }
+
+    // The internal accounts follow the accounts of the clients.
+    let system_records = match &options.system_accounts {
+        Some(system_accounts) => system_accounts.records(
+            account_ledger
+                .journal()
+                .expect("system accounts record the journal"),
+        )?,
+        None => Vec::new(),
+    };
+
     let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.input.delimiter)
         .has_headers(true)
         .flexible(true)
-        .from_writer(io::stdout());
+        .from_writer(output());
 
-    for account in account_ledger.client_records() {
-        writer.serialize(account)?;
+    match options.command {
+        cli::Command::Accounts | cli::Command::ApplyQuarantine => {
+            match (&template, options.output_format) {
+                (Some(template), _) => {
+                    let mut stdout = output();
+                    for account in account_ledger.client_records()? {
+                        stdout.write_all(template.render(&account).as_bytes())?;
+                    }
+                }
+                (None, output::OutputFormat::Fixed) => {
+                    let mut stdout = output();
+                    for account in account_ledger.client_records()? {
+                        stdout.write_all(
+                            options
+                                .fixed_width
+                                .render(&account, &options.precision, options.output_number_format)?
+                                .as_bytes(),
+                        )?;
+                    }
+                }
+                (None, output::OutputFormat::Csv) if options.groups.is_some() => {
+                    for account in account_ledger.grouped_records()? {
+                        writer.serialize(account)?;
+                    }
+                }
+                (None, output::OutputFormat::Csv) if options.extended => {
+                    for account in account_ledger.extended_records()? {
+                        writer.serialize(account)?;
+                    }
+                }
+                (None, output::OutputFormat::Csv) => {
+                    for account in account_ledger
+                        .client_records()?
+                        .into_iter()
+                        .chain(system_records.iter().cloned())
+                    {
+                        let account = options
+                            .precision
+                            .apply(&account, options.output_number_format);
+                        match &mut pseudonymizer {
+                            Some(pseudonymizer) => {
+                                writer.serialize(pseudonymizer.pseudonymize(account))?
+                            }
+                            None => writer.serialize(account)?,
+                        }
+                    }
+                }
+            }
+        }
+        cli::Command::Report => writer.serialize(account_ledger.trial_balance()?)?,
+        cli::Command::Concentration => {
+            for account in account_ledger.concentration(options.top.unwrap_or(cli::DEFAULT_TOP))? {
+                writer.serialize(account)?;
+            }
+        }
+        cli::Command::Counterparties => {
+            for counterparty in account_ledger.counterparties().report() {
+                writer.serialize(counterparty)?;
+            }
+        }
+        cli::Command::Tags => {
+            for tag in account_ledger.tags().report()? {
+                writer.serialize(tag)?;
+            }
+        }
+        cli::Command::Adjustments => {
+            for adjustment in account_ledger.adjustments() {
+                writer.serialize(adjustment)?;
+            }
+        }
+        cli::Command::Balance => {
+            let client = options.client.expect("balance requires --client");
+            let account = account_ledger
+                .client_record(client)?
+                .ok_or(anyhow::anyhow!(
+                    "Client {client} has no transactions by then"
+                ))?;
+            writer.serialize(
+                options
+                    .precision
+                    .apply(&account, options.output_number_format),
+            )?;
+        }
+        cli::Command::Payout => payout::write_settlements(
+            &settlements,
+            options.settlement_format,
+            options
+                .commodity
+                .as_deref()
+                .unwrap_or(export::DEFAULT_COMMODITY),
+            options.input.delimiter,
+            &mut output(),
+        )?,
+        cli::Command::Export => {
+            if let Some(journal) = account_ledger.journal() {
+                export::write_journal(
+                    journal,
+                    options.export_format,
+                    options
+                        .commodity
+                        .as_deref()
+                        .unwrap_or(export::DEFAULT_COMMODITY),
+                    &mut output(),
+                )?;
+            }
+        }
+        cli::Command::InspectSnapshot
+        | cli::Command::CheckSnapshot
+        | cli::Command::Shadow
+        | cli::Command::VerifyAudit
+        | cli::Command::CompactSnapshot
+        | cli::Command::FoldSnapshot
+        | cli::Command::SignAdmin
+        | cli::Command::Repl => {
+            unreachable!("handled before processing")
+        }
     }
 
     writer.flush()?;
 
+    if let Some(control_file) = &options.control_file {
+        for account in account_ledger
+            .client_records()?
+            .iter()
+            .chain(&system_records)
+        {
+            control_totals.add(account)?;
+        }
+        control_totals.write_csv(
+            &mut csv::WriterBuilder::new()
+                .delimiter(options.input.delimiter)
+                .from_path(control_file)?,
+        )?;
+    }
+
+    if let (Some(pseudonymizer), Some(pseudonym_map)) = (&pseudonymizer, &options.pseudonym_map) {
+        pseudonymizer.write_mapping(
+            &mut csv::WriterBuilder::new()
+                .delimiter(options.input.delimiter)
+                .from_path(pseudonym_map)?,
+        )?;
+    }
+
+    if let Some(snapshot_path) = &options.snapshot {
+        let snapshot = if options.delta {
+            account_ledger.delta_snapshot()
+        } else {
+            account_ledger.snapshot()
+        };
+        snapshot.write_to_path(snapshot_path)?;
+    }
+
+    if let (Some(input_registry), Some(input_hash)) = (&mut input_registry, &input_hash) {
+        input_registry.record(input_hash, &options.file_path)?;
+    }
+
+    if let (Some(run_manifest_path), Some(input_hash)) = (&options.run_manifest, &input_hash) {
+        let run_manifest = manifest::RunManifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            args: env::args().skip(1).collect::<Vec<_>>().join(" "),
+            input: options.file_path.clone(),
+            input_sha256: input_hash.clone(),
+            rows: summary.rows,
+            applied: summary.applied,
+            failed: summary.failed,
+            quarantined: summary.quarantined,
+            output_sha256: output_hasher.borrow().clone().finalize_hex(),
+        };
+        std::fs::write(run_manifest_path, run_manifest.to_string())?;
+    }
+
     Ok(())
 }
+
+/// An engine with the processing options of the command line, without any
+/// inputs or outputs besides the records, for the `shadow` command.
+fn shadow_engine(options: &cli::Options, admin_key: Option<&admin::AdminKey>) -> engine::Engine {
+    let mut engine_builder = engine::EngineBuilder::new()
+        .retention(options.retention)
+        .freeze(options.freeze)
+        .empty_accounts(options.empty_accounts)
+        .amount_limits(options.amount_limits)
+        .client_ids(options.client_ids.clone())
+        .strict(options.strict)
+        .defer_disputes(options.defer_disputes);
+    if let Some(admin_key) = admin_key {
+        engine_builder = engine_builder.admin_key(admin_key.clone());
+    }
+    engine_builder.build()
+}
@@ -0,0 +1,206 @@
+use std::{cell::RefCell, fmt::Display, fs::File, io, path::Path, rc::Rc};
+
+use anyhow::{anyhow, Context};
+
+use crate::sha256::Sha256;
+
+/// Describes an input file so that truncation or corruption can be detected.
+///
+/// Manifests are plain text files of `key=value` lines:
+///
+/// ```text
+/// rows=8
+/// sha256=4c0f...
+/// ```
+///
+/// `rows` counts the data rows of the input, excluding the header.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Manifest {
+    pub rows: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest {}", path.display()))?;
+        contents.parse()
+    }
+
+    /// Checks the SHA-256 of the input file against the manifest.
+    pub fn verify_hash(&self, sha256: &str) -> anyhow::Result<()> {
+        if sha256 != self.sha256 {
+            return Err(anyhow!(
+                "Input hash {sha256} does not match the manifest hash {}",
+                self.sha256
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the number of processed rows against the manifest.
+    pub fn verify_rows(&self, rows: u64) -> anyhow::Result<()> {
+        if rows != self.rows {
+            return Err(anyhow!(
+                "Input has {rows} rows, but the manifest expects {}",
+                self.rows
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Manifest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rows = None;
+        let mut sha256 = None;
+
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match line.split_once('=') {
+                Some(("rows", value)) => {
+                    rows = Some(value.trim().parse().context("Invalid manifest row count")?)
+                }
+                Some(("sha256", value)) => sha256 = Some(value.trim().to_ascii_lowercase()),
+                _ => return Err(anyhow!("Unexpected manifest line: {line}")),
+            }
+        }
+
+        Ok(Self {
+            rows: rows.ok_or(anyhow!("Manifest is missing the row count"))?,
+            sha256: sha256.ok_or(anyhow!("Manifest is missing the sha256 hash"))?,
+        })
+    }
+}
+
+/// Describes a run, so that downstream systems can verify which input and
+/// configuration produced which output.
+///
+/// Run manifests are written as `key=value` lines like input manifests. They
+/// contain nothing that varies between runs of the same input and options, so
+/// repeated runs produce the same manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunManifest {
+    pub version: String,
+    /// The command line arguments, separated by spaces.
+    pub args: String,
+    pub input: String,
+    pub input_sha256: String,
+    pub rows: u64,
+    pub applied: u64,
+    pub failed: u64,
+    pub quarantined: u64,
+    /// The SHA-256 of everything written to stdout.
+    pub output_sha256: String,
+}
+
+impl Display for RunManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "version={}", self.version)?;
+        writeln!(f, "args={}", self.args)?;
+        writeln!(f, "input={}", self.input)?;
+        writeln!(f, "input_sha256={}", self.input_sha256)?;
+        writeln!(f, "rows={}", self.rows)?;
+        writeln!(f, "applied={}", self.applied)?;
+        writeln!(f, "failed={}", self.failed)?;
+        writeln!(f, "quarantined={}", self.quarantined)?;
+        writeln!(f, "output_sha256={}", self.output_sha256)
+    }
+}
+
+/// Passes writes on while hashing them into a hasher shared by all clones,
+/// so output written through several writers is hashed in order.
+#[derive(Debug, Clone)]
+pub struct HashingWriter<W: io::Write> {
+    inner: W,
+    hasher: Rc<RefCell<Sha256>>,
+}
+
+impl<W: io::Write> HashingWriter<W> {
+    pub fn new(inner: W, hasher: Rc<RefCell<Sha256>>) -> Self {
+        Self { inner, hasher }
+    }
+}
+
+impl<W: io::Write> io::Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.borrow_mut().update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Computes the SHA-256 of a file as lowercase hex.
+pub fn hash_file(path: impl AsRef<Path>) -> anyhow::Result<String> {
+    let mut hasher = Sha256::default();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize_hex())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() -> anyhow::Result<()> {
+        let manifest: Manifest = "rows=8\nsha256=ABCDEF\n".parse()?;
+        assert_eq!(
+            manifest,
+            Manifest {
+                rows: 8,
+                sha256: "abcdef".to_string(),
+            }
+        );
+
+        assert!("rows=8".parse::<Manifest>().is_err());
+        assert!("rows=x\nsha256=ab".parse::<Manifest>().is_err());
+        assert!("rows=1\nsha256=ab\nsize=3".parse::<Manifest>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hashing_writer() -> anyhow::Result<()> {
+        use std::io::Write;
+
+        let hasher = Rc::new(RefCell::new(Sha256::default()));
+        let mut first = HashingWriter::new(Vec::new(), hasher.clone());
+        let mut second = HashingWriter::new(Vec::new(), hasher.clone());
+        first.write_all(b"ab")?;
+        second.write_all(b"c")?;
+        assert_eq!(first.inner, b"ab");
+
+        let mut expected = Sha256::default();
+        expected.update(b"abc");
+        assert_eq!(
+            hasher.borrow().clone().finalize_hex(),
+            expected.finalize_hex()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_hash() -> anyhow::Result<()> {
+        let manifest: Manifest = "rows=2\nsha256=ab".parse()?;
+        manifest.verify_hash("ab")?;
+        assert!(manifest.verify_hash("cd").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_rows() -> anyhow::Result<()> {
+        let manifest: Manifest = "rows=2\nsha256=ab".parse()?;
+        manifest.verify_rows(2)?;
+        assert!(manifest.verify_rows(1).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    amount::Amount,
+    structs::{ClientRecord, Record},
+};
+
+/// Callbacks invoked by an [`Engine`](crate::engine::Engine) while it
+/// processes records, e.g. for notifications or metrics.
+///
+/// All callbacks do nothing by default, so implementations only override
+/// the ones they need.
+pub trait Observer {
+    /// A record was applied to the ledger, leaving the account of its client
+    /// with the given balances.
+    fn on_accepted(&mut self, _record: &Record, _account: &ClientRecord) {}
+
+    /// A row could not be read, was invalid or was rejected by the ledger.
+    /// The record is missing if the row could not be read.
+    fn on_rejected(&mut self, _row: u64, _record: Option<&Record>, _error: &anyhow::Error) {}
+
+    /// A valid record failed a risk check and was quarantined instead of
+    /// being applied.
+    fn on_quarantined(&mut self, _row: u64, _record: &Record, _reason: &str) {}
+
+    /// A dispute held `amount` of a transaction of the client.
+    fn on_dispute_opened(&mut self, _client: u16, _tx: u32, _amount: Amount) {}
+
+    /// The account of the client was frozen.
+    fn on_lock(&mut self, _client: u16) {}
+}
+
+/// Lets the caller keep a handle to an observer registered on an engine,
+/// e.g. to read collected data after a run.
+impl<O: Observer> Observer for Rc<RefCell<O>> {
+    fn on_accepted(&mut self, record: &Record, account: &ClientRecord) {
+        self.borrow_mut().on_accepted(record, account);
+    }
+
+    fn on_rejected(&mut self, row: u64, record: Option<&Record>, error: &anyhow::Error) {
+        self.borrow_mut().on_rejected(row, record, error);
+    }
+
+    fn on_quarantined(&mut self, row: u64, record: &Record, reason: &str) {
+        self.borrow_mut().on_quarantined(row, record, reason);
+    }
+
+    fn on_dispute_opened(&mut self, client: u16, tx: u32, amount: Amount) {
+        self.borrow_mut().on_dispute_opened(client, tx, amount);
+    }
+
+    fn on_lock(&mut self, client: u16) {
+        self.borrow_mut().on_lock(client);
+    }
+}
@@ -0,0 +1,349 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde::Serialize;
+
+use crate::{
+    amount::Amount,
+    structs::{ClientRecord, NumberFormat},
+};
+
+/// Decimal places of the fixed-width amounts without a precision.
+const FIXED_DECIMALS: usize = 4;
+
+/// The encoding of the account output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// Fixed-width records, see [`FixedWidthLayout`].
+    Fixed,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "fixed" => Ok(OutputFormat::Fixed),
+            other => Err(anyhow!(
+                "Unknown output format {other}, expected one of: csv, fixed"
+            )),
+        }
+    }
+}
+
+/// Decimal places of the amount columns of the account output, applied when
+/// writing, independent of the precision amounts are kept in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Precision {
+    pub available: Option<usize>,
+    pub held: Option<usize>,
+    pub total: Option<usize>,
+}
+
+impl Precision {
+    /// Sets the decimal places of all amount columns from a number like `2`,
+    /// or of single columns from a list like `held=2,total=4`.
+    pub fn set(&mut self, precision: &str) -> anyhow::Result<()> {
+        let decimals = |value: &str, column: &str| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid precision for column {column}: {value}"))
+        };
+        if !precision.contains('=') {
+            let decimals = Some(decimals(precision, "amounts")?);
+            (self.available, self.held, self.total) = (decimals, decimals, decimals);
+            return Ok(());
+        }
+        for setting in precision
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+        {
+            let (column, value) = setting.split_once('=').ok_or(anyhow!(
+                "Malformed column precision {setting}, expected column=decimals"
+            ))?;
+            let decimals = Some(decimals(value, column)?);
+            match column.trim() {
+                "available" => self.available = decimals,
+                "held" => self.held = decimals,
+                "total" => self.total = decimals,
+                other => {
+                    return Err(anyhow!(
+                        "Unknown amount column {other}, expected one of: available, held, total"
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_set(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// The account with its amounts rounded to their decimal places and in
+    /// the number format. Amounts without a precision in the standard format
+    /// are written as they are.
+    pub fn apply(&self, record: &ClientRecord, number_format: NumberFormat) -> PreciseClientRecord {
+        let amount = |decimals: Option<usize>, amount: Amount| match decimals {
+            Some(decimals) => {
+                PreciseAmount::Rounded(number_format.localize(&format!("{amount:.decimals$}")))
+            }
+            None if number_format != NumberFormat::Standard => {
+                PreciseAmount::Rounded(number_format.localize(&amount.to_string()))
+            }
+            None => PreciseAmount::Exact(amount),
+        };
+        PreciseClientRecord {
+            client: record.client,
+            available: amount(self.available, record.available),
+            held: amount(self.held, record.held),
+            total: amount(self.total, record.total),
+            locked: record.locked,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum PreciseAmount {
+    Exact(Amount),
+    Rounded(String),
+}
+
+/// An account as written with a [`Precision`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PreciseClientRecord {
+    pub client: u16,
+    pub available: PreciseAmount,
+    pub held: PreciseAmount,
+    pub total: PreciseAmount,
+    pub locked: bool,
+}
+
+/// Column widths and padding of fixed-width account records.
+///
+/// Every record is a line of the columns `client`, `available`, `held`,
+/// `total` and `locked` without separators. Numbers are right-aligned and
+/// padded with the padding character, amounts have four decimal places unless
+/// a [`Precision`] is given.
+/// The locked flag is `Y` or `N`, left-aligned and padded with spaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedWidthLayout {
+    pub client: usize,
+    pub available: usize,
+    pub held: usize,
+    pub total: usize,
+    pub locked: usize,
+    pub padding: char,
+}
+
+impl Default for FixedWidthLayout {
+    fn default() -> Self {
+        Self {
+            client: 5,
+            available: 16,
+            held: 16,
+            total: 16,
+            locked: 1,
+            padding: ' ',
+        }
+    }
+}
+
+impl FixedWidthLayout {
+    /// Sets column widths from a list like `client=6,total=20`.
+    pub fn set_widths(&mut self, widths: &str) -> anyhow::Result<()> {
+        for setting in widths.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (column, width) = setting.split_once('=').ok_or(anyhow!(
+                "Malformed column width {setting}, expected column=width"
+            ))?;
+            let width = width
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid width for column {column}: {width}"))?;
+            match column.trim() {
+                "client" => self.client = width,
+                "available" => self.available = width,
+                "held" => self.held = width,
+                "total" => self.total = width,
+                "locked" => self.locked = width,
+                other => {
+                    return Err(anyhow!(
+                        "Unknown column {other}, expected one of: client, available, held, total, locked"
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders an account as a single line, failing if a value does not fit
+    /// into its column.
+    pub fn render(
+        &self,
+        record: &ClientRecord,
+        precision: &Precision,
+        number_format: NumberFormat,
+    ) -> anyhow::Result<String> {
+        let mut line = String::new();
+        line.push_str(&self.pad_number("client", record.client.to_string(), self.client)?);
+        for (column, amount, width, decimals) in [
+            (
+                "available",
+                record.available,
+                self.available,
+                precision.available,
+            ),
+            ("held", record.held, self.held, precision.held),
+            ("total", record.total, self.total, precision.total),
+        ] {
+            let decimals = decimals.unwrap_or(FIXED_DECIMALS);
+            let amount = number_format.localize(&format!("{amount:.decimals$}"));
+            line.push_str(&self.pad_number(column, amount, width)?);
+        }
+        let locked = if record.locked { "Y" } else { "N" };
+        if self.locked < locked.len() {
+            return Err(anyhow!("Column locked is too narrow for {locked}"));
+        }
+        line.push_str(&format!("{locked:<width$}", width = self.locked));
+        line.push('\n');
+        Ok(line)
+    }
+
+    fn pad_number(&self, column: &str, value: String, width: usize) -> anyhow::Result<String> {
+        let length = value.chars().count();
+        if length > width {
+            return Err(anyhow!(
+                "Value {value} does not fit into the {width} characters of column {column}"
+            ));
+        }
+        let padding = width - length;
+        // Zero padding goes between the sign and the digits.
+        Ok(match value.strip_prefix('-') {
+            Some(digits) if self.padding == '0' => {
+                format!("-{}{digits}", "0".repeat(padding))
+            }
+            _ => format!("{}{value}", self.padding.to_string().repeat(padding)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> ClientRecord {
+        ClientRecord {
+            client: 42,
+            available: Amount::new(-15, 1),
+            held: Amount::new(2, 0),
+            total: Amount::new(5, 1),
+            locked: true,
+            escrowed: Amount::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_render_fixed_width() -> anyhow::Result<()> {
+        let layout = FixedWidthLayout::default();
+        assert_eq!(
+            layout.render(&record(), &Precision::default(), NumberFormat::Standard)?,
+            "   42         -1.5000          2.0000          0.5000Y\n"
+        );
+
+        let layout = FixedWidthLayout {
+            client: 4,
+            available: 8,
+            held: 8,
+            total: 8,
+            locked: 2,
+            padding: '0',
+        };
+        assert_eq!(
+            layout.render(&record(), &Precision::default(), NumberFormat::Standard)?,
+            "0042-01.5000002.0000000.5000Y \n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_overflow() {
+        let layout = FixedWidthLayout {
+            available: 6,
+            ..Default::default()
+        };
+        assert!(layout
+            .render(&record(), &Precision::default(), NumberFormat::Standard)
+            .is_err());
+    }
+
+    #[test]
+    fn test_render_precision() -> anyhow::Result<()> {
+        let mut precision = Precision::default();
+        precision.set("available=1,total=0")?;
+        assert_eq!(
+            FixedWidthLayout::default().render(&record(), &precision, NumberFormat::Standard)?,
+            "   42            -1.5          2.0000               0Y\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_precision() -> anyhow::Result<()> {
+        let mut precision = Precision::default();
+        assert!(!precision.is_set());
+        precision.set("2")?;
+        assert_eq!(precision.held, Some(2));
+        precision.set("held=4")?;
+        assert_eq!(
+            (precision.available, precision.held, precision.total),
+            (Some(2), Some(4), Some(2))
+        );
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(precision.apply(&record(), NumberFormat::Standard))?;
+        writer.serialize(Precision::default().apply(&record(), NumberFormat::Standard))?;
+        writer.serialize(precision.apply(
+            &ClientRecord {
+                total: Amount::new(12_345, 1),
+                ..record()
+            },
+            NumberFormat::German,
+        ))?;
+        assert_eq!(
+            String::from_utf8(writer.into_inner()?)?,
+            "client,available,held,total,locked\n\
+             42,-1.50,2.0000,0.50,true\n\
+             42,-1.5,2.0,0.5,true\n\
+             42,\"-1,50\",\"2,0000\",\"1.234,50\",true\n"
+        );
+
+        assert!(precision.set("balance=2").is_err());
+        assert!(precision.set("held=x").is_err());
+        assert!(precision.set("-1").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_widths() -> anyhow::Result<()> {
+        let mut layout = FixedWidthLayout::default();
+        layout.set_widths("client=8, total=20")?;
+        assert_eq!(layout.client, 8);
+        assert_eq!(layout.total, 20);
+        assert_eq!(layout.held, 16);
+
+        assert!(layout.set_widths("balance=3").is_err());
+        assert!(layout.set_widths("client=x").is_err());
+        assert!(layout.set_widths("client").is_err());
+
+        Ok(())
+    }
+}
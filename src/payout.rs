@@ -0,0 +1,296 @@
+use std::{io, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::Ledger,
+    amount::Amount,
+    export::{escape_xml, DATE},
+    structs::AmountLimits,
+};
+
+/// A row of the payout instructions file: pay out funds of a client to a
+/// bank account.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PayoutInstruction {
+    pub client: u16,
+    /// Transaction id of the payout in the ledger.
+    pub tx: u32,
+    /// The amount to pay out, all available funds if empty.
+    pub amount: Option<Amount>,
+    pub creditor: String,
+    pub iban: String,
+}
+
+/// A payout debited from an account, a row of the csv settlement file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Settlement {
+    pub client: u16,
+    pub tx: u32,
+    pub amount: Amount,
+    pub creditor: String,
+    pub iban: String,
+}
+
+/// Formats of the settlement file handed to the bank.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SettlementFormat {
+    #[default]
+    Csv,
+    /// Credit transfers as simplified ISO 20022 pain.001 XML.
+    Pain001,
+}
+
+impl FromStr for SettlementFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(SettlementFormat::Csv),
+            "pain001" => Ok(SettlementFormat::Pain001),
+            other => Err(anyhow!(
+                "Unknown settlement format {other}, expected one of: csv, pain001"
+            )),
+        }
+    }
+}
+
+pub fn read_instructions(
+    path: impl AsRef<Path>,
+    delimiter: u8,
+) -> anyhow::Result<Vec<PayoutInstruction>> {
+    let path = path.as_ref();
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .delimiter(delimiter)
+        .from_path(path)
+        .with_context(|| format!("Failed to read payout instructions {}", path.display()))?;
+    Ok(reader.deserialize().collect::<Result<_, _>>()?)
+}
+
+/// Debits the payouts from the available funds of the clients, like
+/// withdrawals, so they are part of the journal and snapshots. Returns the
+/// settlement or the reason it failed per instruction.
+pub fn pay_out(
+    ledger: &mut Ledger,
+    instructions: &[PayoutInstruction],
+    limits: &AmountLimits,
+) -> Vec<anyhow::Result<Settlement>> {
+    instructions
+        .iter()
+        .map(|instruction| {
+            let amount = match instruction.amount {
+                Some(amount) => amount,
+                None => {
+                    ledger
+                        .client_record(instruction.client)?
+                        .ok_or(anyhow!("Unknown client {}", instruction.client))?
+                        .available
+                }
+            };
+            if amount <= Amount::ZERO {
+                return Err(anyhow!(
+                    "Nothing to pay out for client {}",
+                    instruction.client
+                ));
+            }
+            limits.check(amount)?;
+            ledger
+                .withdraw(instruction.client, instruction.tx, amount)
+                .map_err(|err| {
+                    anyhow!(
+                        "Failed to pay out {amount} with transaction {} from account {}: {err}",
+                        instruction.tx,
+                        instruction.client
+                    )
+                })?;
+            Ok(Settlement {
+                client: instruction.client,
+                tx: instruction.tx,
+                amount,
+                creditor: instruction.creditor.clone(),
+                iban: instruction.iban.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Writes the settlement file for the bank in the given format.
+pub fn write_settlements(
+    settlements: &[Settlement],
+    format: SettlementFormat,
+    commodity: &str,
+    delimiter: u8,
+    writer: &mut impl io::Write,
+) -> anyhow::Result<()> {
+    match format {
+        SettlementFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .from_writer(writer);
+            for settlement in settlements {
+                writer.serialize(settlement)?;
+            }
+            writer.flush()?;
+            Ok(())
+        }
+        SettlementFormat::Pain001 => write_pain001(settlements, commodity, writer),
+    }
+}
+
+/// Writes a pain.001 customer credit transfer initiation with a single
+/// payment of one credit transfer per settlement.
+fn write_pain001(
+    settlements: &[Settlement],
+    commodity: &str,
+    writer: &mut impl io::Write,
+) -> anyhow::Result<()> {
+    let commodity = escape_xml(commodity);
+    let count = settlements.len();
+    let sum = settlements
+        .iter()
+        .try_fold(Amount::ZERO, |sum, settlement| {
+            sum.checked_add(settlement.amount)
+        })?;
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.001.001.03">"#
+    )?;
+    writeln!(writer, "  <CstmrCdtTrfInitn>")?;
+    writeln!(writer, "    <GrpHdr>")?;
+    writeln!(writer, "      <MsgId>toy-payments-engine</MsgId>")?;
+    writeln!(writer, "      <CreDtTm>{DATE}T00:00:00</CreDtTm>")?;
+    writeln!(writer, "      <NbOfTxs>{count}</NbOfTxs>")?;
+    writeln!(writer, "      <CtrlSum>{sum:.4}</CtrlSum>")?;
+    writeln!(
+        writer,
+        "      <InitgPty><Nm>toy-payments-engine</Nm></InitgPty>"
+    )?;
+    writeln!(writer, "    </GrpHdr>")?;
+    writeln!(writer, "    <PmtInf>")?;
+    writeln!(writer, "      <PmtInfId>payouts</PmtInfId>")?;
+    writeln!(writer, "      <PmtMtd>TRF</PmtMtd>")?;
+    writeln!(writer, "      <NbOfTxs>{count}</NbOfTxs>")?;
+    writeln!(writer, "      <CtrlSum>{sum:.4}</CtrlSum>")?;
+    writeln!(writer, "      <ReqdExctnDt>{DATE}</ReqdExctnDt>")?;
+    writeln!(writer, "      <Dbtr><Nm>toy-payments-engine</Nm></Dbtr>")?;
+    for settlement in settlements {
+        writeln!(writer, "      <CdtTrfTxInf>")?;
+        writeln!(
+            writer,
+            "        <PmtId><EndToEndId>{}</EndToEndId></PmtId>",
+            settlement.tx
+        )?;
+        writeln!(
+            writer,
+            r#"        <Amt><InstdAmt Ccy="{commodity}">{:.4}</InstdAmt></Amt>"#,
+            settlement.amount
+        )?;
+        writeln!(
+            writer,
+            "        <Cdtr><Nm>{}</Nm></Cdtr>",
+            escape_xml(&settlement.creditor)
+        )?;
+        writeln!(
+            writer,
+            "        <CdtrAcct><Id><IBAN>{}</IBAN></Id></CdtrAcct>",
+            escape_xml(&settlement.iban)
+        )?;
+        writeln!(
+            writer,
+            "        <RmtInf><Ustrd>Payout to client {}</Ustrd></RmtInf>",
+            settlement.client
+        )?;
+        writeln!(writer, "      </CdtTrfTxInf>")?;
+    }
+    writeln!(writer, "    </PmtInf>")?;
+    writeln!(writer, "  </CstmrCdtTrfInitn>")?;
+    writeln!(writer, "</Document>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(client: u16, tx: u32, amount: Option<Amount>) -> PayoutInstruction {
+        PayoutInstruction {
+            client,
+            tx,
+            amount,
+            creditor: "Jane & Co".to_string(),
+            iban: "DE89370400440532013000".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_pay_out() -> anyhow::Result<()> {
+        let mut ledger = Ledger::new().with_journal();
+        ledger.deposit(1, 1, Amount::new(10, 0))?;
+        ledger.deposit(2, 2, Amount::new(5, 0))?;
+
+        let results = pay_out(
+            &mut ledger,
+            &[
+                instruction(1, 10, Some(Amount::new(4, 0))),
+                instruction(2, 11, None),
+                instruction(1, 12, Some(Amount::new(7, 0))),
+                instruction(3, 13, None),
+            ],
+            &AmountLimits::default(),
+        );
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.as_ref().ok().map(|settlement| settlement.amount))
+                .collect::<Vec<_>>(),
+            [Some(Amount::new(4, 0)), Some(Amount::new(5, 0)), None, None]
+        );
+        let accounts = ledger.client_records()?;
+        assert_eq!(
+            accounts[0].total.checked_add(accounts[1].total)?,
+            Amount::new(6, 0)
+        );
+        ledger.verify_journal()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_settlements() -> anyhow::Result<()> {
+        let settlements = [Settlement {
+            client: 1,
+            tx: 10,
+            amount: Amount::new(45, 1),
+            creditor: "Jane & Co".to_string(),
+            iban: "DE89370400440532013000".to_string(),
+        }];
+
+        let mut csv = Vec::new();
+        write_settlements(&settlements, SettlementFormat::Csv, "EUR", b',', &mut csv)?;
+        assert_eq!(
+            String::from_utf8(csv)?,
+            "client,tx,amount,creditor,iban\n1,10,4.5,Jane & Co,DE89370400440532013000\n"
+        );
+
+        let mut xml = Vec::new();
+        write_settlements(
+            &settlements,
+            SettlementFormat::Pain001,
+            "EUR",
+            b',',
+            &mut xml,
+        )?;
+        let xml = String::from_utf8(xml)?;
+        assert!(xml.contains("<CtrlSum>4.5000</CtrlSum>"));
+        assert!(xml.contains(r#"<InstdAmt Ccy="EUR">4.5000</InstdAmt>"#));
+        assert!(xml.contains("<Nm>Jane &amp; Co</Nm>"));
+
+        assert!("xml".parse::<SettlementFormat>().is_err());
+
+        Ok(())
+    }
+}
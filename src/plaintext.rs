@@ -0,0 +1,265 @@
+use std::{collections::HashMap, io};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    amount::Amount,
+    structs::{Record, RecordType},
+};
+
+/// Reads the postings of simple beancount or ledger journals as records.
+///
+/// Every posting with an amount to an account below `Assets:` becomes a
+/// deposit, or a withdrawal if the amount is negative, of the client the
+/// account is mapped to. Clients are numbered in the order their accounts
+/// first appear, starting at 1. Postings to other accounts, postings with
+/// elided amounts and all directives other than transactions are skipped.
+///
+/// The tx id of a transaction is taken from a ledger `(code)` or a beancount
+/// `tx:` metadata entry if it is numeric, and is otherwise one more than the
+/// previous transaction's.
+pub struct PlainTextReader<R> {
+    lines: io::Lines<R>,
+    line_number: usize,
+    clients: HashMap<String, u16>,
+    tx: Option<u32>,
+    next_tx: u32,
+}
+
+impl<R: io::BufRead> PlainTextReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            line_number: 0,
+            clients: HashMap::new(),
+            tx: None,
+            next_tx: 1,
+        }
+    }
+
+    /// The client ids that accounts have been mapped to so far.
+    pub fn clients(&self) -> &HashMap<String, u16> {
+        &self.clients
+    }
+
+    /// Parses a line, returning a record if it is a posting to an asset account.
+    fn parse_line(&mut self, line: &str) -> anyhow::Result<Option<Record>> {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+
+        if !line.starts_with([' ', '\t']) {
+            // Transactions start with a date, anything else is a directive
+            // or a comment that ends the current transaction.
+            self.tx = None;
+            if line.starts_with(|c: char| c.is_ascii_digit()) && is_transaction_header(line) {
+                let tx = transaction_code(line).unwrap_or(self.next_tx);
+                self.set_tx(tx);
+            }
+            return Ok(None);
+        }
+
+        let Some(tx) = self.tx else {
+            return Ok(None);
+        };
+        let line = line.trim();
+        if line.starts_with([';', '#']) {
+            return Ok(None);
+        }
+        if let Some(value) = line.strip_prefix("tx:") {
+            if let Ok(tx) = value.trim().trim_matches('"').parse() {
+                self.set_tx(tx);
+            }
+            return Ok(None);
+        }
+
+        let line = line.split(';').next().unwrap_or_default();
+        let (account, amount) = match line.find("  ").or_else(|| line.find('\t')) {
+            Some(index) => line.split_at(index),
+            None => line.split_once(' ').unwrap_or((line, "")),
+        };
+        let account = account.trim_start_matches(['!', '*', ' ']);
+        if !account.starts_with("Assets:") {
+            return Ok(None);
+        }
+
+        // Drop the commodity, prices and thousands separators of the amount.
+        let amount = amount.split(['@', '=']).next().unwrap_or_default();
+        let amount: String = amount
+            .chars()
+            .filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+            .collect();
+        if amount.is_empty() {
+            return Ok(None);
+        }
+        let amount: Amount = amount
+            .parse()
+            .with_context(|| format!("Invalid amount for account {account}"))?;
+
+        let next_client = self.clients.len() + 1;
+        let client = match self.clients.get(account) {
+            Some(&client) => client,
+            None => {
+                let client = u16::try_from(next_client)
+                    .map_err(|_| anyhow!("Too many asset accounts in the journal"))?;
+                self.clients.insert(account.to_string(), client);
+                client
+            }
+        };
+
+        Ok(Some(Record {
+            record_type: if amount < Amount::ZERO {
+                RecordType::Withdrawal
+            } else {
+                RecordType::Deposit
+            },
+            client,
+            tx,
+            amount: Some(amount.checked_abs()?),
+            ..Default::default()
+        }))
+    }
+
+    fn set_tx(&mut self, tx: u32) {
+        self.tx = Some(tx);
+        self.next_tx = tx.wrapping_add(1);
+    }
+}
+
+impl<R: io::BufRead> Iterator for PlainTextReader<R> {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+            self.line_number += 1;
+            match self.parse_line(&line) {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => {}
+                Err(err) => return Some(Err(err.context(format!("Line {}", self.line_number)))),
+            }
+        }
+    }
+}
+
+/// Dated beancount directives that are not transactions.
+const DIRECTIVES: [&str; 11] = [
+    "open",
+    "close",
+    "commodity",
+    "balance",
+    "pad",
+    "note",
+    "document",
+    "event",
+    "query",
+    "custom",
+    "price",
+];
+
+fn is_transaction_header(line: &str) -> bool {
+    line.split_whitespace()
+        .nth(1)
+        .is_none_or(|word| !DIRECTIVES.contains(&word))
+}
+
+/// Returns the numeric code of a ledger transaction, e.g. `2024/01/01 (42) Payee`.
+fn transaction_code(line: &str) -> Option<u32> {
+    let (_, rest) = line.split_once('(')?;
+    let (code, _) = rest.split_once(')')?;
+    code.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_beancount() -> anyhow::Result<()> {
+        let data = "\
+option \"title\" \"Test\"
+2024-01-01 open Assets:Checking USD
+2024-01-01 open Income:Salary
+
+2024-01-02 * \"Employer\" \"Salary\"
+  tx: 10
+  Assets:Checking  1,500.00 USD
+  Income:Salary   -1,500.00 USD
+
+2024-01-03 * \"Rent\"
+  ; paid by transfer
+  Assets:Checking -700 USD
+  Expenses:Rent
+
+2024-01-04 balance Assets:Checking 800 USD
+";
+
+        let records = PlainTextReader::new(data.as_bytes()).collect::<anyhow::Result<Vec<_>>>()?;
+
+        assert_eq!(
+            records,
+            vec![
+                Record {
+                    record_type: RecordType::Deposit,
+                    client: 1,
+                    tx: 10,
+                    amount: Some(Amount::new(1500, 0)),
+                    ..Default::default()
+                },
+                Record {
+                    record_type: RecordType::Withdrawal,
+                    client: 1,
+                    tx: 11,
+                    amount: Some(Amount::new(700, 0)),
+                    ..Default::default()
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_ledger() -> anyhow::Result<()> {
+        let data = "\
+; opening
+2024/01/01 (5) Opening Balance
+    Assets:Bank:Savings    $250.50
+    Equity:Opening Balances
+
+2024/01/02 Transfer
+    Assets:Bank:Checking    $-20
+    Assets:Bank:Savings     $20
+";
+
+        let mut reader = PlainTextReader::new(data.as_bytes());
+        let records = reader.by_ref().collect::<anyhow::Result<Vec<_>>>()?;
+
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| (record.record_type, record.client, record.tx, record.amount))
+                .collect::<Vec<_>>(),
+            vec![
+                (RecordType::Deposit, 1, 5, Some(Amount::new(2505, 1))),
+                (RecordType::Withdrawal, 2, 6, Some(Amount::new(20, 0))),
+                (RecordType::Deposit, 1, 6, Some(Amount::new(20, 0))),
+            ]
+        );
+        assert_eq!(reader.clients()["Assets:Bank:Checking"], 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_invalid_amount() {
+        let data = "2024-01-01 *\n  Assets:Cash  1.2.3 USD\n";
+
+        assert!(PlainTextReader::new(data.as_bytes())
+            .next()
+            .is_some_and(|record| record.is_err()));
+    }
+}
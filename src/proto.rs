@@ -0,0 +1,266 @@
+use std::io;
+
+use anyhow::{anyhow, Context};
+
+use crate::structs::{Record, RecordType};
+
+/// Upper bound for a single message, protecting against allocating
+/// huge buffers because of a corrupted length prefix.
+const MAX_MESSAGE_LENGTH: u64 = 64 * 1024;
+
+/// Reads length-delimited protobuf `Record` messages as defined in
+/// `proto/record.proto`.
+pub struct ProtobufReader<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: io::Read> ProtobufReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            done: false,
+        }
+    }
+
+    fn read_message(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut length = 0u64;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0u8];
+            if self.reader.read(&mut byte)? == 0 {
+                if shift == 0 {
+                    return Ok(None);
+                }
+                return Err(anyhow!("Unexpected end of input in message length"));
+            }
+            length |= u64::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                if length > MAX_MESSAGE_LENGTH {
+                    return Err(anyhow!("Message length {length} exceeds the maximum"));
+                }
+                let mut message = vec![0; usize::try_from(length)?];
+                self.reader
+                    .read_exact(&mut message)
+                    .context("Unexpected end of input in message")?;
+                return Ok(Some(message));
+            }
+        }
+        Err(anyhow!("Message length is not a valid varint"))
+    }
+}
+
+impl<R: io::Read> Iterator for ProtobufReader<R> {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_message() {
+            Ok(Some(message)) => Some(decode_record(&message)),
+            Ok(None) => None,
+            // The stream cannot be resynchronized after a framing error.
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+fn decode_record(mut bytes: &[u8]) -> anyhow::Result<Record> {
+    let mut record_type = None;
+    let mut client = 0;
+    let mut tx = 0;
+    let mut amount = None;
+    let mut timestamp = None;
+    let mut counterparty = None;
+    let mut tag = None;
+    let mut sequence = None;
+    let mut tenant = None;
+    let mut signature = None;
+    let mut reason_code = None;
+    let mut escrow = None;
+
+    while !bytes.is_empty() {
+        let key = decode_varint(&mut bytes)?;
+        match (key >> 3, key & 0x7) {
+            (1, 0) => {
+                record_type = Some(match decode_varint(&mut bytes)? {
+                    1 => RecordType::Deposit,
+                    2 => RecordType::Withdrawal,
+                    3 => RecordType::Dispute,
+                    4 => RecordType::Resolve,
+                    5 => RecordType::Chargeback,
+                    6 => RecordType::Unlock,
+                    7 => RecordType::Adjustment,
+                    8 => RecordType::WriteOff,
+                    9 => RecordType::EscrowHold,
+                    10 => RecordType::EscrowRelease,
+                    other => return Err(anyhow!("Unknown record type {other}")),
+                })
+            }
+            (2, 0) => client = u16::try_from(decode_varint(&mut bytes)?)?,
+            (3, 0) => tx = u32::try_from(decode_varint(&mut bytes)?)?,
+            (4, 2) => {
+                let value = std::str::from_utf8(decode_bytes(&mut bytes)?)?.trim();
+                amount = match value {
+                    "" => None,
+                    value => Some(value.parse().context("Invalid amount")?),
+                };
+            }
+            (5, 0) => timestamp = Some(decode_varint(&mut bytes)?),
+            (6, 2) => {
+                let value = std::str::from_utf8(decode_bytes(&mut bytes)?)?.trim();
+                counterparty = (!value.is_empty()).then(|| value.to_string());
+            }
+            (7, 2) => {
+                let value = std::str::from_utf8(decode_bytes(&mut bytes)?)?.trim();
+                tag = (!value.is_empty()).then(|| value.to_string());
+            }
+            (8, 0) => sequence = Some(decode_varint(&mut bytes)?),
+            (9, 2) => {
+                let value = std::str::from_utf8(decode_bytes(&mut bytes)?)?.trim();
+                tenant = (!value.is_empty()).then(|| value.to_string());
+            }
+            (10, 2) => {
+                let value = std::str::from_utf8(decode_bytes(&mut bytes)?)?.trim();
+                signature = (!value.is_empty()).then(|| value.to_string());
+            }
+            (11, 2) => {
+                let value = std::str::from_utf8(decode_bytes(&mut bytes)?)?.trim();
+                reason_code = (!value.is_empty()).then(|| value.to_string());
+            }
+            (12, 2) => {
+                let value = std::str::from_utf8(decode_bytes(&mut bytes)?)?.trim();
+                escrow = (!value.is_empty()).then(|| value.to_string());
+            }
+            // Skip unknown fields for forward compatibility.
+            (_, 0) => {
+                decode_varint(&mut bytes)?;
+            }
+            (_, 1) => skip(&mut bytes, 8)?,
+            (_, 2) => {
+                decode_bytes(&mut bytes)?;
+            }
+            (_, 5) => skip(&mut bytes, 4)?,
+            (field, wire_type) => {
+                return Err(anyhow!(
+                    "Unsupported wire type {wire_type} for field {field}"
+                ))
+            }
+        }
+    }
+
+    Ok(Record {
+        record_type: record_type.ok_or(anyhow!("Missing record type"))?,
+        client,
+        tx,
+        amount,
+        timestamp,
+        counterparty,
+        tag,
+        sequence,
+        tenant,
+        signature,
+        reason_code,
+        escrow,
+        ..Default::default()
+    })
+}
+
+fn decode_varint(bytes: &mut &[u8]) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or(anyhow!("Unexpected end of message in varint"))?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(anyhow!("Varint is too long"))
+}
+
+fn decode_bytes<'a>(bytes: &mut &'a [u8]) -> anyhow::Result<&'a [u8]> {
+    let length = usize::try_from(decode_varint(bytes)?)?;
+    if bytes.len() < length {
+        return Err(anyhow!("Unexpected end of message in field"));
+    }
+    let (value, rest) = bytes.split_at(length);
+    *bytes = rest;
+    Ok(value)
+}
+
+fn skip(bytes: &mut &[u8], length: usize) -> anyhow::Result<()> {
+    if bytes.len() < length {
+        return Err(anyhow!("Unexpected end of message in field"));
+    }
+    *bytes = &bytes[length..];
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+
+    #[test]
+    fn test_decode_stream() -> anyhow::Result<()> {
+        let data: &[u8] = &[
+            // deposit, client 1, tx 300, amount "1.5", timestamp 100, counterparty "m1"
+            18, 0x08, 1, 0x10, 1, 0x18, 0xac, 0x02, 0x22, 3, b'1', b'.', b'5', 0x28, 100, 0x32, 2,
+            b'm', b'1', // dispute, client 1, tx 300, plus an unknown fixed32 field 15
+            12, 0x08, 3, 0x10, 1, 0x18, 0xac, 0x02, 0x7d, 1, 2, 3, 4,
+        ];
+
+        let records = ProtobufReader::new(data).collect::<anyhow::Result<Vec<_>>>()?;
+
+        assert_eq!(
+            records,
+            vec![
+                Record {
+                    record_type: RecordType::Deposit,
+                    client: 1,
+                    tx: 300,
+                    amount: Some(Amount::new(15, 1)),
+                    timestamp: Some(100),
+                    counterparty: Some("m1".to_string()),
+                    ..Default::default()
+                },
+                Record {
+                    record_type: RecordType::Dispute,
+                    client: 1,
+                    tx: 300,
+                    amount: None,
+                    ..Default::default()
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        // Unknown record type
+        let data: &[u8] = &[2, 0x08, 99];
+        assert!(ProtobufReader::new(data).next().is_some_and(|r| r.is_err()));
+
+        // Client id out of range
+        let data: &[u8] = &[6, 0x08, 1, 0x10, 0x80, 0x80, 0x04];
+        assert!(ProtobufReader::new(data).next().is_some_and(|r| r.is_err()));
+
+        // Corrupted length prefix
+        let data: &[u8] = &[0xff, 0xff, 0xff, 0x7f];
+        assert!(ProtobufReader::new(data).next().is_some_and(|r| r.is_err()));
+
+        // Truncated message stops the stream
+        let data: &[u8] = &[5, 0x08, 1];
+        let mut reader = ProtobufReader::new(data);
+        assert!(reader.next().is_some_and(|r| r.is_err()));
+        assert!(reader.next().is_none());
+    }
+}
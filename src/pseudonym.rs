@@ -0,0 +1,129 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+
+use crate::{
+    output::{PreciseAmount, PreciseClientRecord},
+    sha256::{hmac_sha256, to_hex},
+};
+
+/// Replaces client ids with pseudonyms derived from a secret key, so that
+/// outputs can be shared without revealing the real client ids.
+///
+/// A pseudonym is the first 8 bytes of the HMAC-SHA256 of the big-endian
+/// client id, as hex. The same key always produces the same pseudonyms,
+/// while they cannot be linked to the client ids without it.
+#[derive(Debug)]
+pub struct Pseudonymizer {
+    key: Vec<u8>,
+    /// Pseudonyms handed out so far, by client id.
+    mapping: BTreeMap<u16, String>,
+}
+
+/// An account with its client id replaced by a pseudonym.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PseudonymousRecord {
+    pub client: String,
+    pub available: PreciseAmount,
+    pub held: PreciseAmount,
+    pub total: PreciseAmount,
+    pub locked: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct MappingRow<'a> {
+    client: u16,
+    pseudonym: &'a str,
+}
+
+impl Pseudonymizer {
+    pub fn new(key: &[u8]) -> anyhow::Result<Self> {
+        if key.is_empty() {
+            return Err(anyhow!("Pseudonymization key is empty"));
+        }
+        Ok(Self {
+            key: key.to_vec(),
+            mapping: BTreeMap::new(),
+        })
+    }
+
+    /// Reads the key from a file, ignoring a trailing newline.
+    pub fn from_key_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let key = std::fs::read(path)
+            .with_context(|| format!("Failed to read key file {}", path.display()))?;
+        Self::new(key.strip_suffix(b"\n").unwrap_or(&key))
+    }
+
+    pub fn pseudonym(&mut self, client: u16) -> &str {
+        let key = &self.key;
+        self.mapping
+            .entry(client)
+            .or_insert_with(|| to_hex(&hmac_sha256(key, &client.to_be_bytes())[..8]))
+    }
+
+    /// Replaces the client id of an account, as written with a
+    /// [`Precision`](crate::output::Precision).
+    pub fn pseudonymize(&mut self, record: PreciseClientRecord) -> PseudonymousRecord {
+        PseudonymousRecord {
+            client: self.pseudonym(record.client).to_string(),
+            available: record.available,
+            held: record.held,
+            total: record.total,
+            locked: record.locked,
+        }
+    }
+
+    /// Writes the pseudonyms handed out so far as csv rows of `client,pseudonym`.
+    pub fn write_mapping<W: std::io::Write>(
+        &self,
+        writer: &mut csv::Writer<W>,
+    ) -> anyhow::Result<()> {
+        for (&client, pseudonym) in &self.mapping {
+            writer.serialize(MappingRow { client, pseudonym })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+
+    #[test]
+    fn test_pseudonym() -> anyhow::Result<()> {
+        let mut pseudonymizer = Pseudonymizer::new(b"secret")?;
+        let pseudonym = pseudonymizer.pseudonym(1).to_string();
+
+        assert_eq!(pseudonym.len(), 16);
+        assert_eq!(pseudonymizer.pseudonym(1), pseudonym);
+        assert_ne!(pseudonymizer.pseudonym(2), pseudonym);
+        assert_ne!(Pseudonymizer::new(b"other")?.pseudonym(1), pseudonym);
+        assert!(Pseudonymizer::new(b"").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_mapping() -> anyhow::Result<()> {
+        let mut pseudonymizer = Pseudonymizer::new(b"secret")?;
+        let record = pseudonymizer.pseudonymize(PreciseClientRecord {
+            client: 3,
+            available: PreciseAmount::Exact(Amount::new(1, 0)),
+            held: PreciseAmount::Exact(Amount::ZERO),
+            total: PreciseAmount::Exact(Amount::new(1, 0)),
+            locked: false,
+        });
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        pseudonymizer.write_mapping(&mut writer)?;
+        let output = String::from_utf8(writer.into_inner()?)?;
+
+        assert_eq!(output, format!("client,pseudonym\n3,{}\n", record.client));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::{io, path::Path};
+
+use crate::{
+    observer::Observer,
+    structs::{QuarantineRecord, Record},
+};
+
+/// Writes records that failed a risk check as csv, with their input row and
+/// the reason, so they can be reviewed manually.
+///
+/// Reviewers approve a record by setting its `approved` column to `true`,
+/// and [`read_approved`] reads the approved records back to apply them.
+/// [`Quarantine::finish`] must be called after the run to report errors,
+/// which cannot be returned from the observer callbacks.
+pub struct Quarantine<W: io::Write> {
+    writer: csv::Writer<W>,
+    error: Option<csv::Error>,
+}
+
+impl<W: io::Write> Quarantine<W> {
+    pub fn new(writer: csv::Writer<W>) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: io::Write> Observer for Quarantine<W> {
+    fn on_quarantined(&mut self, row: u64, record: &Record, reason: &str) {
+        if self.error.is_none() {
+            self.error = self
+                .writer
+                .serialize(QuarantineRecord::new(row, record, reason))
+                .err();
+        }
+    }
+}
+
+/// Reads the approved records of a quarantine file, in order.
+pub fn read_approved(path: impl AsRef<Path>, delimiter: u8) -> anyhow::Result<Vec<Record>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .delimiter(delimiter)
+        .from_path(path)?;
+    let mut records = Vec::new();
+    for row in reader.deserialize::<QuarantineRecord>() {
+        let row = row?;
+        if row.approved {
+            records.push(row.into_record());
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::{
+        amount::Amount,
+        clock::ManualClock,
+        engine::{EngineBuilder, RunSummary},
+        structs::{AmountLimits, RecordType},
+    };
+
+    fn deposit(tx: u32, amount: Amount) -> anyhow::Result<Record> {
+        Ok(Record {
+            record_type: RecordType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(amount),
+            tag: Some("salary".to_string()),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_quarantine() -> anyhow::Result<()> {
+        let quarantine = Rc::new(RefCell::new(Quarantine::new(csv::Writer::from_writer(
+            Vec::new(),
+        ))));
+        let mut engine = EngineBuilder::new()
+            .amount_limits(AmountLimits {
+                max_amount: Amount::new(100, 0),
+                ..Default::default()
+            })
+            .quarantine(true)
+            .clock(ManualClock::new(0))
+            .observer(quarantine.clone())
+            .build();
+        let summary = engine.run([
+            deposit(1, Amount::new(10, 0)),
+            deposit(2, Amount::new(500, 0)),
+            deposit(3, Amount::new(-1, 0)),
+        ])?;
+        assert_eq!(
+            summary,
+            RunSummary {
+                rows: 3,
+                applied: 1,
+                failed: 1,
+                quarantined: 1,
+                ..Default::default()
+            }
+        );
+        drop(engine);
+
+        let mut quarantine = Rc::try_unwrap(quarantine)
+            .map_err(|_| anyhow!("quarantine is still shared"))?
+            .into_inner();
+        quarantine.finish()?;
+        assert_eq!(
+            String::from_utf8(quarantine.writer.into_inner()?)?,
+            "row,type,client,tx,amount,timestamp,counterparty,tag,sequence,tenant,signature,reason_code,escrow,reason,approved\n\
+             2,deposit,1,2,500.0,,,salary,,,,,,Amount exceeds the maximum of 100,false\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_approved() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("tpe-quarantine-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "row,type,client,tx,amount,timestamp,counterparty,tag,sequence,tenant,signature,reason_code,escrow,reason,approved\n\
+             2,deposit,1,2,500.0,,,salary,,,,,,Amount exceeds the maximum of 100,true\n\
+             5,withdrawal,1,5,200.0,7,shop,,,,,,,Amount exceeds the maximum of 100,false\n",
+        )?;
+        let records = read_approved(&path, b',');
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(records?, [deposit(2, Amount::new(500, 0))?]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    collections::HashSet,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// A plain text file remembering the content hashes of processed inputs,
+/// so that processing the same content twice can be detected.
+///
+/// Every line holds a SHA-256 hash, optionally followed by a space and
+/// the name of the input it was computed from.
+#[derive(Debug)]
+pub struct InputRegistry {
+    path: PathBuf,
+    hashes: HashSet<String>,
+}
+
+impl InputRegistry {
+    /// Loads the registry, treating a missing file as empty.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let hashes = match std::fs::read_to_string(path) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(|line| line.split_whitespace().next())
+                .map(str::to_string)
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read registry {}", path.display()))
+            }
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            hashes,
+        })
+    }
+
+    pub fn contains(&self, sha256: &str) -> bool {
+        self.hashes.contains(sha256)
+    }
+
+    /// Appends a processed input to the registry file, unless its hash is already known.
+    pub fn record(&mut self, sha256: &str, input: &str) -> anyhow::Result<()> {
+        if self.contains(sha256) {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{sha256} {input}")?;
+        self.hashes.insert(sha256.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_roundtrip() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("tpe-registry-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut registry = InputRegistry::load(&path)?;
+        assert!(!registry.contains("abc"));
+        registry.record("abc", "first.csv")?;
+        registry.record("abc", "second.csv")?;
+        assert!(registry.contains("abc"));
+        assert_eq!(std::fs::read_to_string(&path)?, "abc first.csv\n");
+
+        let registry = InputRegistry::load(&path)?;
+        assert!(registry.contains("abc"));
+        assert!(!registry.contains("first.csv"));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::structs::Record;
+
+/// Reorders a stream of records by timestamp within a tolerance window, so
+/// that slightly out-of-order events, e.g. from distributed producers, are
+/// applied in the order they happened.
+///
+/// A record is held back until a record at least `window` seconds newer
+/// has been read, or the input ends. Records without a timestamp keep their
+/// position relative to the records read before them, and records arriving
+/// later than the window are passed on right away. Records with the same
+/// timestamp keep their input order, and rows that could not be read are
+/// passed on right away.
+pub struct Reorder<I> {
+    records: I,
+    window: u64,
+    /// The newest timestamp read so far.
+    latest: u64,
+    /// Records held back, by timestamp and input position.
+    pending: BinaryHeap<Reverse<Pending>>,
+    read: u64,
+}
+
+struct Pending {
+    timestamp: u64,
+    position: u64,
+    record: Record,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        (self.timestamp, self.position) == (other.timestamp, other.position)
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.timestamp, self.position).cmp(&(other.timestamp, other.position))
+    }
+}
+
+impl<I> Reorder<I> {
+    pub fn new(records: I, window: u64) -> Self {
+        Self {
+            records,
+            window,
+            latest: 0,
+            pending: BinaryHeap::new(),
+            read: 0,
+        }
+    }
+
+    /// Returns the oldest record held back if its window has passed.
+    fn pop_due(&mut self) -> Option<Record> {
+        let Reverse(oldest) = self.pending.peek()?;
+        if oldest.timestamp.saturating_add(self.window) > self.latest {
+            return None;
+        }
+        self.pending.pop().map(|Reverse(pending)| pending.record)
+    }
+}
+
+impl<I: Iterator<Item = anyhow::Result<Record>>> Iterator for Reorder<I> {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pop_due() {
+                return Some(Ok(record));
+            }
+            match self.records.next() {
+                Some(Ok(record)) => {
+                    let timestamp = record.timestamp.unwrap_or(self.latest);
+                    self.latest = self.latest.max(timestamp);
+                    self.read += 1;
+                    self.pending.push(Reverse(Pending {
+                        timestamp,
+                        position: self.read,
+                        record,
+                    }));
+                }
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    return self
+                        .pending
+                        .pop()
+                        .map(|Reverse(pending)| Ok(pending.record))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::{amount::Amount, structs::RecordType};
+
+    fn deposit(tx: u32, timestamp: Option<u64>) -> anyhow::Result<Record> {
+        Ok(Record {
+            record_type: RecordType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(Amount::new(1, 0)),
+            timestamp,
+            ..Default::default()
+        })
+    }
+
+    fn reorder(records: Vec<anyhow::Result<Record>>, window: u64) -> Vec<Option<u32>> {
+        Reorder::new(records.into_iter(), window)
+            .map(|record| record.ok().map(|record| record.tx))
+            .collect()
+    }
+
+    #[test]
+    fn test_reorder() {
+        let records = || {
+            vec![
+                deposit(1, Some(100)),
+                deposit(2, Some(105)),
+                deposit(3, Some(103)),
+                deposit(4, None),
+                deposit(5, Some(110)),
+                deposit(6, Some(90)),
+                deposit(7, Some(105)),
+            ]
+        };
+
+        assert_eq!(
+            reorder(records(), 10),
+            [
+                Some(1),
+                Some(6),
+                Some(3),
+                Some(2),
+                Some(4),
+                Some(7),
+                Some(5)
+            ]
+        );
+        assert_eq!(
+            reorder(records(), 0),
+            [
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(4),
+                Some(5),
+                Some(6),
+                Some(7)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reorder_errors() {
+        let records = vec![
+            deposit(1, Some(100)),
+            Err(anyhow!("broken row")),
+            deposit(2, Some(99)),
+        ];
+
+        assert_eq!(reorder(records, 5), [None, Some(2), Some(1)]);
+    }
+}
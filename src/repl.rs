@@ -0,0 +1,237 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    account::{AccountFilter, Applied, Ledger},
+    amount::Amount,
+    structs::{ClientRecord, Record, RecordType},
+};
+
+const HELP: &str = "\
+Commands:
+  deposit <client> <tx> <amount>
+  withdraw <client> <tx> <amount>
+  dispute <client> <tx>
+  resolve <client> <tx>
+  chargeback <client> <tx>
+  show <client>         print the account of a client
+  accounts              print all accounts
+  tx <tx>               print a retained transaction
+  save <path>           write a snapshot of the ledger
+  help
+  quit";
+
+/// Interactive command loop over a ledger, for investigating and testing
+/// scenarios by hand.
+pub struct Repl {
+    ledger: Ledger,
+}
+
+impl Repl {
+    pub fn new(ledger: Ledger) -> Self {
+        Self { ledger }
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Reads commands line by line until `quit` or the end of the input.
+    /// Failing commands are reported and do not end the loop.
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            if matches!(line.trim(), "quit" | "exit") {
+                break;
+            }
+            match self.execute(&line) {
+                Ok(response) if response.is_empty() => {}
+                Ok(response) => writeln!(output, "{response}")?,
+                Err(err) => writeln!(output, "error: {err:#}")?,
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Executes a single command and returns its response.
+    pub fn execute(&mut self, line: &str) -> anyhow::Result<String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(String::new());
+        };
+
+        match command {
+            "deposit" | "withdraw" | "withdrawal" => {
+                let [client, tx, amount] = args else {
+                    return Err(anyhow!("Usage: {command} <client> <tx> <amount>"));
+                };
+                let record_type = if command == "deposit" {
+                    RecordType::Deposit
+                } else {
+                    RecordType::Withdrawal
+                };
+                let amount = amount.parse().context("Invalid amount")?;
+                self.apply(record_type, client, tx, Some(amount))
+            }
+            "dispute" | "resolve" | "chargeback" => {
+                let [client, tx] = args else {
+                    return Err(anyhow!("Usage: {command} <client> <tx>"));
+                };
+                let record_type = match command {
+                    "dispute" => RecordType::Dispute,
+                    "resolve" => RecordType::Resolve,
+                    _ => RecordType::Chargeback,
+                };
+                self.apply(record_type, client, tx, None)
+            }
+            "show" => {
+                let [client] = args else {
+                    return Err(anyhow!("Usage: show <client>"));
+                };
+                let client: u16 = client.parse().context("Invalid client id")?;
+                self.ledger
+                    .query(AccountFilter::default())?
+                    .find(|record| record.client == client)
+                    .map(|record| format_account(&record))
+                    .ok_or(anyhow!("Unknown client {client}"))
+            }
+            "accounts" => Ok(self
+                .ledger
+                .query(AccountFilter::default())?
+                .map(|record| format_account(&record))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "tx" => {
+                let [tx] = args else {
+                    return Err(anyhow!("Usage: tx <tx>"));
+                };
+                let tx: u32 = tx.parse().context("Invalid tx id")?;
+                let info = self
+                    .ledger
+                    .get_transaction(tx)
+                    .ok_or(anyhow!("Unknown transaction {tx}"))?;
+                Ok(format!(
+                    "tx {tx}: client {}, {:?} of {}, {:?}",
+                    info.client, info.kind, info.amount, info.state
+                ))
+            }
+            "save" => {
+                let [path] = args else {
+                    return Err(anyhow!("Usage: save <path>"));
+                };
+                self.ledger.snapshot().write_to_path(path)?;
+                Ok(format!("saved snapshot to {path}"))
+            }
+            "help" => Ok(HELP.to_string()),
+            other => Err(anyhow!("Unknown command {other}, try help")),
+        }
+    }
+
+    fn apply(
+        &mut self,
+        record_type: RecordType,
+        client: &str,
+        tx: &str,
+        amount: Option<Amount>,
+    ) -> anyhow::Result<String> {
+        let record = Record {
+            record_type,
+            client: client.parse().context("Invalid client id")?,
+            tx: tx.parse().context("Invalid tx id")?,
+            amount,
+            ..Default::default()
+        };
+        record.validate()?;
+        let applied = self.ledger.apply(&record)?;
+        let locked = if self.ledger.is_locked(record.client) {
+            ", account locked"
+        } else {
+            ""
+        };
+        Ok(match applied {
+            Applied::Deposited(amount) => format!("deposited {amount}"),
+            Applied::Withdrawn(amount) => format!("withdrew {amount}"),
+            Applied::Held(amount) => format!("held {amount}{locked}"),
+            Applied::Released(amount) => format!("released {amount}"),
+            Applied::ChargedBack(amount) => format!("charged back {amount}{locked}"),
+            Applied::Unlocked => "unlocked".to_string(),
+            Applied::Adjusted(amount) => format!("adjusted by {amount}{locked}"),
+            Applied::WrittenOff(amount) => format!("wrote off {amount}{locked}"),
+            Applied::EscrowHeld(amount) => format!("moved {amount} into escrow"),
+            Applied::EscrowReleased(amount) => format!("released {amount} from escrow"),
+        })
+    }
+}
+
+fn format_account(record: &ClientRecord) -> String {
+    format!(
+        "client {}: available {}, held {}, total {}{}",
+        record.client,
+        record.available,
+        record.held,
+        record.total,
+        if record.locked { ", locked" } else { "" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execute() -> anyhow::Result<()> {
+        let mut repl = Repl::new(Ledger::new());
+
+        assert_eq!(repl.execute("deposit 1 5 10.0")?, "deposited 10");
+        assert_eq!(repl.execute("dispute 1 5")?, "held 10");
+        assert_eq!(
+            repl.execute("show 1")?,
+            "client 1: available 0, held 10, total 10"
+        );
+        assert_eq!(
+            repl.execute("tx 5")?,
+            "tx 5: client 1, Deposit of 10, Disputed"
+        );
+        assert_eq!(
+            repl.execute("chargeback 1 5")?,
+            "charged back 10, account locked"
+        );
+        assert_eq!(
+            repl.execute("accounts")?,
+            "client 1: available 0, held 0, total 0, locked"
+        );
+        assert_eq!(repl.execute("  ")?, "");
+
+        assert!(repl.execute("deposit 1 6 1").is_err());
+        assert!(repl.execute("deposit 1 x 1").is_err());
+        assert!(repl.execute("deposit 1 7 NaN").is_err());
+        assert!(repl.execute("dispute 1").is_err());
+        assert!(repl.execute("show 2").is_err());
+        assert!(repl.execute("transfer 1 2").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run() -> anyhow::Result<()> {
+        let mut repl = Repl::new(Ledger::new());
+        let mut output = Vec::new();
+        repl.run(
+            "deposit 1 1 2\nwithdraw 1 2 3\nquit\ndeposit 1 3 1\n".as_bytes(),
+            &mut output,
+        )?;
+
+        assert_eq!(
+            String::from_utf8(output)?,
+            "> deposited 2\n> error: Insufficient funds\n> "
+        );
+        assert_eq!(repl.ledger().client_records()?[0].total, Amount::new(2, 0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Context};
+
+use crate::{
+    engine::Engine,
+    structs::{ClientRecord, Record},
+};
+
+/// Leaves out the records after the cutoff, in seconds since the unix epoch,
+/// to replay the input as of that time. Records without a timestamp cannot
+/// be placed in time and are left out as well.
+pub fn until(
+    records: impl IntoIterator<Item = anyhow::Result<Record>>,
+    cutoff: u64,
+) -> impl Iterator<Item = anyhow::Result<Record>> {
+    records.into_iter().filter(move |result| match result {
+        Ok(record) => record
+            .timestamp
+            .is_some_and(|timestamp| timestamp <= cutoff),
+        Err(_) => true,
+    })
+}
+
+/// The balances of the client as of the cutoff, by replaying the records up
+/// to it, e.g. to investigate a dispute. `None` if the client had no
+/// transactions by then.
+pub fn balance_at(
+    mut engine: Engine,
+    records: impl IntoIterator<Item = anyhow::Result<Record>>,
+    client: u16,
+    cutoff: u64,
+) -> anyhow::Result<Option<ClientRecord>> {
+    engine.run(until(records, cutoff))?;
+    Ok(engine.ledger().client_record(client)?)
+}
+
+/// Parses a point in time as seconds since the unix epoch, either as a number
+/// or as a UTC date like `2024-03-01` or `2024-03-01T00:00:00Z`.
+pub fn parse_time(s: &str) -> anyhow::Result<u64> {
+    if let Ok(seconds) = s.parse() {
+        return Ok(seconds);
+    }
+    let invalid = || anyhow!("Invalid time {s}, expected seconds or e.g. 2024-03-01T00:00:00Z");
+    let (date, time) = match s.split_once('T') {
+        Some((date, time)) => (date, time.strip_suffix('Z').ok_or_else(invalid)?),
+        None => (s, "00:00:00"),
+    };
+
+    let number = |part: Option<&str>| -> anyhow::Result<u64> {
+        part.ok_or_else(invalid)?.parse().with_context(invalid)
+    };
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = (
+        number(parts.next())?,
+        number(parts.next())?,
+        number(parts.next())?,
+    );
+    let mut parts = time.splitn(3, ':');
+    let (hour, minute, second) = (
+        number(parts.next())?,
+        number(parts.next())?,
+        number(parts.next())?,
+    );
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return Err(invalid());
+    }
+
+    Ok(days_since_epoch(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// Days from 1970-01-01 to the given date of the proleptic Gregorian
+/// calendar, after Howard Hinnant's `days_from_civil`.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Counts years from March, so that the leap day ends the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{amount::Amount, engine::EngineBuilder, structs::RecordType};
+
+    fn deposit(tx: u32, amount: Amount, timestamp: Option<u64>) -> anyhow::Result<Record> {
+        Ok(Record {
+            record_type: RecordType::Deposit,
+            client: 7,
+            tx,
+            amount: Some(amount),
+            timestamp,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_parse_time() -> anyhow::Result<()> {
+        assert_eq!(parse_time("1709251200")?, 1_709_251_200);
+        assert_eq!(parse_time("2024-03-01T00:00:00Z")?, 1_709_251_200);
+        assert_eq!(parse_time("2024-03-01")?, 1_709_251_200);
+        assert_eq!(parse_time("2024-02-29T23:59:59Z")?, 1_709_251_199);
+        assert_eq!(parse_time("1970-01-01T00:00:00Z")?, 0);
+        assert!(parse_time("2024-03-01T00:00:00").is_err());
+        assert!(parse_time("2024-13-01").is_err());
+        assert!(parse_time("yesterday").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_balance_at() -> anyhow::Result<()> {
+        let records = || {
+            vec![
+                deposit(1, Amount::new(1, 0), Some(100)),
+                deposit(2, Amount::new(2, 0), Some(200)),
+                deposit(3, Amount::new(4, 0), None),
+                deposit(4, Amount::new(8, 0), Some(150)),
+            ]
+        };
+
+        let balance = balance_at(EngineBuilder::new().build(), records(), 7, 150)?;
+        assert_eq!(
+            balance.map(|balance| balance.total),
+            Some(Amount::new(9, 0))
+        );
+        assert_eq!(
+            balance_at(EngineBuilder::new().build(), records(), 7, 99)?,
+            None
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+use std::{fs::File, io, path::Path};
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    amount::Amount,
+    input::{self, InputOptions, COLUMNS},
+    structs::RecordType,
+};
+
+/// The columns every input needs.
+const REQUIRED: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// A violation of the input schema, a row of the `--validate-schema` report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchemaViolation {
+    /// The input row, empty for violations of the header.
+    pub row: Option<u64>,
+    pub column: String,
+    pub value: String,
+    pub problem: String,
+}
+
+/// Checks the header names, the number of fields and the types of all values
+/// of a csv input, without applying any of its records.
+pub fn validate(
+    path: impl AsRef<Path>,
+    options: &InputOptions,
+) -> anyhow::Result<Vec<SchemaViolation>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    validate_reader(file, options)
+}
+
+fn validate_reader<R: io::Read>(
+    reader: R,
+    options: &InputOptions,
+) -> anyhow::Result<Vec<SchemaViolation>> {
+    let mut reader = input::csv_reader(reader, options);
+    let headers = input::headers(&mut reader, options)?;
+
+    let mut violations = Vec::new();
+    let mut header_violation = |column: &str, problem: &str| {
+        violations.push(SchemaViolation {
+            row: None,
+            column: column.to_string(),
+            value: String::new(),
+            problem: problem.to_string(),
+        })
+    };
+    for (index, header) in headers.iter().enumerate() {
+        if headers
+            .iter()
+            .take(index)
+            .any(|previous| previous == header)
+        {
+            header_violation(header, "duplicate column");
+        } else if !COLUMNS.contains(&header) && !options.extra_columns {
+            header_violation(header, "unknown column");
+        }
+    }
+    for column in REQUIRED {
+        if !headers.iter().any(|header| header == column) {
+            header_violation(column, "missing column");
+        }
+    }
+
+    for (index, row) in reader.records().enumerate() {
+        let row_number = index as u64 + 1;
+        let mut violation = |column: &str, value: &str, problem: String| {
+            violations.push(SchemaViolation {
+                row: Some(row_number),
+                column: column.to_string(),
+                value: value.to_string(),
+                problem,
+            })
+        };
+        let row = match row {
+            Ok(row) => row,
+            Err(err) => {
+                violation("", "", format!("unreadable row: {err}"));
+                continue;
+            }
+        };
+        if row.len() > headers.len() && !options.extra_columns {
+            violation(
+                "",
+                "",
+                format!(
+                    "{} fields, but the header has {} columns",
+                    row.len(),
+                    headers.len()
+                ),
+            );
+        }
+        for (index, column) in headers.iter().enumerate() {
+            let value = row.get(index).unwrap_or_default();
+            if let Some(problem) = check_value(column, value, options) {
+                violation(column, value, problem.to_string());
+            }
+        }
+    }
+    Ok(violations)
+}
+
+/// The problem with the value of a known column, if any.
+fn check_value(column: &str, value: &str, options: &InputOptions) -> Option<&'static str> {
+    if value.is_empty() {
+        return matches!(column, "type" | "client" | "tx").then_some("missing value");
+    }
+    match column {
+        "type" => {
+            let parses = parses::<RecordType>(value)
+                || !options.strict_types && RecordType::from_alias(value).is_some();
+            (!parses).then_some("unknown record type")
+        }
+        "client" => (!parses::<u16>(value)).then_some("not a client id between 0 and 65535"),
+        "tx" => (!parses::<u32>(value)).then_some("not a transaction id"),
+        "amount" => {
+            let parses = options
+                .number_format
+                .normalize(value)
+                .is_ok_and(|amount| parses::<Amount>(&amount));
+            (!parses).then_some("not an amount")
+        }
+        "timestamp" | "sequence" => (!parses::<u64>(value)).then_some("not a non-negative integer"),
+        _ => None,
+    }
+}
+
+fn parses<T: DeserializeOwned>(value: &str) -> bool {
+    csv::StringRecord::from(vec![value])
+        .deserialize::<T>(None)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_schema() -> anyhow::Result<()> {
+        let data = "\
+            type, client, tx, amount, memo
+            deposit, 1, 1, 1.5,
+            payment, 70000, 2, abc
+            dispute, 1, , , , x";
+
+        let violations = validate_reader(data.as_bytes(), &InputOptions::default())?;
+        assert_eq!(
+            violations
+                .iter()
+                .map(|violation| (violation.row, violation.column.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (None, "memo"),
+                (Some(2), "type"),
+                (Some(2), "client"),
+                (Some(2), "amount"),
+                (Some(3), ""),
+                (Some(3), "tx"),
+            ]
+        );
+        assert_eq!(violations[2].value, "70000");
+        assert_eq!(
+            violations[4].problem,
+            "6 fields, but the header has 5 columns"
+        );
+
+        let options = InputOptions {
+            extra_columns: true,
+            ..Default::default()
+        };
+        assert_eq!(validate_reader(data.as_bytes(), &options)?.len(), 4);
+        assert_eq!(
+            validate_reader("client, tx, tx\n".as_bytes(), &options)?
+                .iter()
+                .map(|violation| (violation.column.as_str(), violation.problem.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("tx", "duplicate column"),
+                ("type", "missing column"),
+                ("amount", "missing column"),
+            ]
+        );
+
+        Ok(())
+    }
+}
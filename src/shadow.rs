@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use serde::Serialize;
+
+use crate::{
+    account::Ledger,
+    amount::Amount,
+    engine::Engine,
+    structs::{ClientRecord, Record},
+};
+
+/// An account that ended up differently in the shadow run, a row of the
+/// `shadow` command. The balances are empty if the account does not exist
+/// in a run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountDiff {
+    pub client: u16,
+    pub primary_available: Option<Amount>,
+    pub primary_held: Option<Amount>,
+    pub primary_total: Option<Amount>,
+    pub primary_locked: Option<bool>,
+    pub shadow_available: Option<Amount>,
+    pub shadow_held: Option<Amount>,
+    pub shadow_total: Option<Amount>,
+    pub shadow_locked: Option<bool>,
+}
+
+/// Feeds the records into both engines in a single pass over the input, so
+/// they see exactly the same records.
+pub fn run(
+    primary: &mut Engine,
+    shadow: &mut Engine,
+    records: impl IntoIterator<Item = anyhow::Result<Record>>,
+) -> anyhow::Result<()> {
+    let mut primary_summary = primary.start();
+    let mut shadow_summary = shadow.start();
+    for (index, result) in records.into_iter().enumerate() {
+        let row = index as u64 + 1;
+        // Errors cannot be cloned, so the shadow gets a copy of the message.
+        let copy = match &result {
+            Ok(record) => Ok(record.clone()),
+            Err(err) => Err(anyhow!("{err:#}")),
+        };
+        primary.feed(&mut primary_summary, row, result)?;
+        shadow.feed(&mut shadow_summary, row, copy)?;
+    }
+    primary.finish(primary_summary)?;
+    shadow.finish(shadow_summary)?;
+    Ok(())
+}
+
+/// Lists the accounts whose balances or lock differ between the ledgers, in
+/// ascending client order.
+pub fn diff(primary: &Ledger, shadow: &Ledger) -> anyhow::Result<Vec<AccountDiff>> {
+    let mut accounts: BTreeMap<u16, (Option<ClientRecord>, Option<ClientRecord>)> = BTreeMap::new();
+    for account in primary.client_records()? {
+        let client = account.client;
+        accounts.entry(client).or_default().0 = Some(account);
+    }
+    for account in shadow.client_records()? {
+        let client = account.client;
+        accounts.entry(client).or_default().1 = Some(account);
+    }
+
+    Ok(accounts
+        .into_iter()
+        .filter(|(_, (primary, shadow))| primary != shadow)
+        .map(|(client, (primary, shadow))| AccountDiff {
+            client,
+            primary_available: primary.as_ref().map(|account| account.available),
+            primary_held: primary.as_ref().map(|account| account.held),
+            primary_total: primary.as_ref().map(|account| account.total),
+            primary_locked: primary.as_ref().map(|account| account.locked),
+            shadow_available: shadow.as_ref().map(|account| account.available),
+            shadow_held: shadow.as_ref().map(|account| account.held),
+            shadow_total: shadow.as_ref().map(|account| account.total),
+            shadow_locked: shadow.as_ref().map(|account| account.locked),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{account::RetentionPolicy, engine::EngineBuilder, structs::RecordType};
+
+    fn record(
+        record_type: RecordType,
+        client: u16,
+        tx: u32,
+        amount: Option<Amount>,
+    ) -> anyhow::Result<Record> {
+        Ok(Record {
+            record_type,
+            client,
+            tx,
+            amount,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_shadow() -> anyhow::Result<()> {
+        let mut primary = EngineBuilder::new().build();
+        let mut shadow = EngineBuilder::new()
+            .retention(RetentionPolicy::None)
+            .build();
+        run(
+            &mut primary,
+            &mut shadow,
+            [
+                record(RecordType::Deposit, 1, 1, Some(Amount::new(2, 0))),
+                record(RecordType::Deposit, 2, 2, Some(Amount::new(3, 0))),
+                Err(anyhow!("unreadable row")),
+                record(RecordType::Dispute, 2, 2, None),
+            ],
+        )?;
+
+        let diffs = diff(primary.ledger(), shadow.ledger())?;
+        assert_eq!(
+            diffs,
+            [AccountDiff {
+                client: 2,
+                primary_available: Some(Amount::ZERO),
+                primary_held: Some(Amount::new(3, 0)),
+                primary_total: Some(Amount::new(3, 0)),
+                primary_locked: Some(false),
+                shadow_available: Some(Amount::new(3, 0)),
+                shadow_held: Some(Amount::ZERO),
+                shadow_total: Some(Amount::new(3, 0)),
+                shadow_locked: Some(false),
+            }]
+        );
+
+        Ok(())
+    }
+}
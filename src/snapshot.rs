@@ -0,0 +1,772 @@
+use std::{fmt::Display, path::Path};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    account::RetentionPolicy,
+    amount::Amount,
+    error::LedgerError,
+    store::{TransactionKind, TransactionState},
+};
+
+/// Identifies snapshot files.
+const MAGIC: [u8; 8] = *b"TPESNAP\0";
+
+/// Format version written by this build.
+///
+/// Adding sections or appending fields to existing ones does not change the
+/// version, as older readers skip what they do not know. Only incompatible
+/// changes bump it: version 2 stores amounts as fixed-point `i64` instead of
+/// `f32`. Version 1 files can still be read.
+pub const VERSION: u16 = 2;
+
+const SECTION_META: u16 = 1;
+const SECTION_ACCOUNTS: u16 = 2;
+const SECTION_ADMIN: u16 = 3;
+
+/// The persisted state of a [`Ledger`](crate::account::Ledger).
+///
+/// A snapshot file starts with 8 magic bytes and a little-endian `u16`
+/// version, followed by sections until the end of the file. Every section
+/// has a header of a `u16` id, a `u32` payload length and a CRC-32 of the
+/// payload. Amounts are `i64` ten-thousandths, see [`Amount::units`]:
+///
+/// - `1` meta: the running totals as four amounts: opening, deposits,
+///   withdrawals and charged back funds, followed by a `u8` delta flag and
+///   the net adjustments and losses and a `u8` truncated flag.
+/// - `2` accounts: a `u32` count, followed by that many accounts, each
+///   prefixed with its `u32` length. An account is the `u16` client, the
+///   total and held balances, a `u8` locked flag and a `u32` count of
+///   transactions of a `u32` tx id, `u8` kind, amount and `u8` state,
+///   followed by a `u32` count of escrow buckets of a `u16` name length, the
+///   utf-8 name and the amount, and a `u32` count of the `u32` ids of
+///   transactions that are not stored.
+/// - `3` admin: a `u32` count, followed by that many `u32` tx ids of applied
+///   adjustments and write-offs.
+///
+/// Readers ignore unknown sections as well as trailing bytes within a
+/// section or an account, so new data can be added in a compatible way.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Snapshot {
+    pub opening: Amount,
+    pub deposits: Amount,
+    pub withdrawals: Amount,
+    pub charged_back: Amount,
+    pub adjustments: Amount,
+    pub losses: Amount,
+    /// Whether this snapshot only holds the accounts modified since the
+    /// previous one, see [`Snapshot::merge`].
+    pub delta: bool,
+    /// Whether transactions were dropped by a retention policy other than
+    /// `all`, while processing or by [`Snapshot::fold`], so the accounts lack
+    /// part of their history.
+    pub truncated: bool,
+    pub accounts: Vec<AccountSnapshot>,
+    /// Tx ids of the applied adjustments and write-offs, in ascending order.
+    pub admin_txs: Vec<u32>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AccountSnapshot {
+    pub client: u16,
+    pub total: Amount,
+    pub held: Amount,
+    pub locked: bool,
+    pub transactions: Vec<TransactionSnapshot>,
+    /// Funds in escrow buckets, by bucket name.
+    pub escrow: Vec<(String, Amount)>,
+    /// Ids of the transactions that are not stored, like those dropped by the
+    /// retention policy or by [`Snapshot::fold`] and of escrow operations, in
+    /// ascending order, to keep rejecting replays.
+    pub forgotten: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransactionSnapshot {
+    pub tx: u32,
+    pub kind: TransactionKind,
+    pub amount: Amount,
+    pub state: TransactionState,
+}
+
+/// A section of a snapshot file, as listed by [`inspect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionInfo {
+    pub id: u16,
+    pub length: u32,
+    pub checksum_ok: bool,
+}
+
+impl Display for SectionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self.id {
+            SECTION_META => "meta",
+            SECTION_ACCOUNTS => "accounts",
+            SECTION_ADMIN => "admin",
+            _ => "unknown",
+        };
+        let checksum = if self.checksum_ok { "ok" } else { "MISMATCH" };
+        write!(
+            f,
+            "section {} ({name}): {} bytes, checksum {checksum}",
+            self.id, self.length
+        )
+    }
+}
+
+impl Snapshot {
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read snapshot {}", path.display()))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn write_to_path(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes())
+            .with_context(|| format!("Failed to write snapshot {}", path.display()))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut meta = Vec::new();
+        for value in [
+            self.opening,
+            self.deposits,
+            self.withdrawals,
+            self.charged_back,
+        ] {
+            meta.extend(value.units().to_le_bytes());
+        }
+        meta.push(u8::from(self.delta));
+        meta.extend(self.adjustments.units().to_le_bytes());
+        meta.extend(self.losses.units().to_le_bytes());
+        meta.push(u8::from(self.truncated));
+
+        let mut accounts = Vec::new();
+        accounts.extend((self.accounts.len() as u32).to_le_bytes());
+        for account in &self.accounts {
+            let mut entry = Vec::new();
+            entry.extend(account.client.to_le_bytes());
+            entry.extend(account.total.units().to_le_bytes());
+            entry.extend(account.held.units().to_le_bytes());
+            entry.push(u8::from(account.locked));
+            entry.extend((account.transactions.len() as u32).to_le_bytes());
+            for transaction in &account.transactions {
+                entry.extend(transaction.tx.to_le_bytes());
+                entry.push(encode_kind(transaction.kind));
+                entry.extend(transaction.amount.units().to_le_bytes());
+                entry.push(encode_state(transaction.state));
+            }
+            entry.extend((account.escrow.len() as u32).to_le_bytes());
+            for (escrow, amount) in &account.escrow {
+                entry.extend((escrow.len() as u16).to_le_bytes());
+                entry.extend(escrow.as_bytes());
+                entry.extend(amount.units().to_le_bytes());
+            }
+            entry.extend((account.forgotten.len() as u32).to_le_bytes());
+            for tx in &account.forgotten {
+                entry.extend(tx.to_le_bytes());
+            }
+            accounts.extend((entry.len() as u32).to_le_bytes());
+            accounts.extend(entry);
+        }
+
+        let mut admin = Vec::new();
+        admin.extend((self.admin_txs.len() as u32).to_le_bytes());
+        for tx in &self.admin_txs {
+            admin.extend(tx.to_le_bytes());
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend(MAGIC);
+        bytes.extend(VERSION.to_le_bytes());
+        write_section(&mut bytes, SECTION_META, &meta);
+        write_section(&mut bytes, SECTION_ACCOUNTS, &accounts);
+        write_section(&mut bytes, SECTION_ADMIN, &admin);
+        bytes
+    }
+
+    /// Folds the transactions that can no longer be disputed under the
+    /// retention policy into the opening totals, shrinking the snapshot while
+    /// keeping the balances. Disputed transactions are always kept; with
+    /// `all`, only charged back transactions are folded. Returns the number
+    /// of folded transactions.
+    ///
+    /// The transactions of an account no longer add up to its total
+    /// afterwards, which [`check`](crate::check::check) accounts for through
+    /// the opening total.
+    pub fn fold(&mut self, retention: RetentionPolicy) -> Result<usize, LedgerError> {
+        let window = match retention {
+            RetentionPolicy::All => usize::MAX,
+            RetentionPolicy::DisputeWindow(window) => window,
+            RetentionPolicy::None => 0,
+        };
+        let mut folded = 0;
+        for account in &mut self.accounts {
+            let mut deposits = 0;
+            let mut kept = Vec::new();
+            // Latest first, so the window keeps the most recent deposits.
+            for transaction in account.transactions.iter().rev() {
+                let keep = match (transaction.kind, transaction.state) {
+                    (_, TransactionState::Disputed) => true,
+                    (_, TransactionState::ChargedBack) => false,
+                    (TransactionKind::Withdrawal, _) => retention == RetentionPolicy::All,
+                    (TransactionKind::Deposit, _) => {
+                        deposits += 1;
+                        deposits <= window
+                    }
+                };
+                if keep {
+                    kept.push(*transaction);
+                    continue;
+                }
+                folded += 1;
+                account.forgotten.push(transaction.tx);
+                let amount = transaction.amount;
+                match (transaction.kind, transaction.state) {
+                    (TransactionKind::Deposit, TransactionState::ChargedBack) => {
+                        self.deposits = self.deposits.checked_sub(amount)?;
+                        self.charged_back = self.charged_back.checked_sub(amount)?;
+                    }
+                    (TransactionKind::Deposit, _) => {
+                        self.opening = self.opening.checked_add(amount)?;
+                        self.deposits = self.deposits.checked_sub(amount)?;
+                    }
+                    (TransactionKind::Withdrawal, _) => {
+                        self.opening = self.opening.checked_sub(amount)?;
+                        self.withdrawals = self.withdrawals.checked_sub(amount)?;
+                    }
+                }
+            }
+            kept.reverse();
+            account.transactions = kept;
+            account.forgotten.sort_unstable();
+            account.forgotten.dedup();
+        }
+        // Folding only charged back deposits keeps the totals derivable.
+        self.truncated |= retention != RetentionPolicy::All && folded > 0;
+        Ok(folded)
+    }
+
+    /// Applies a later delta snapshot, replacing the accounts it contains and
+    /// taking over its totals and admin tx ids. Merging a chain of deltas into
+    /// the full snapshot they are based on compacts them into a single full
+    /// snapshot.
+    pub fn merge(&mut self, delta: &Snapshot) {
+        self.opening = delta.opening;
+        self.deposits = delta.deposits;
+        self.withdrawals = delta.withdrawals;
+        self.charged_back = delta.charged_back;
+        self.adjustments = delta.adjustments;
+        self.losses = delta.losses;
+        self.truncated |= delta.truncated;
+        self.admin_txs = delta.admin_txs.clone();
+        for account in &delta.accounts {
+            match self
+                .accounts
+                .binary_search_by_key(&account.client, |existing| existing.client)
+            {
+                Ok(index) => self.accounts[index] = account.clone(),
+                Err(index) => self.accounts.insert(index, account.clone()),
+            }
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut snapshot = Snapshot::default();
+        let (version, sections) = read_sections(bytes)?;
+        for section in sections {
+            if crc32(section.payload) != section.checksum {
+                return Err(anyhow!(
+                    "Checksum mismatch in snapshot section {}",
+                    section.id
+                ));
+            }
+            let mut payload = Reader(section.payload);
+            match section.id {
+                SECTION_META => {
+                    snapshot.opening = payload.amount(version)?;
+                    snapshot.deposits = payload.amount(version)?;
+                    snapshot.withdrawals = payload.amount(version)?;
+                    snapshot.charged_back = payload.amount(version)?;
+                    // Added after the first release of the format.
+                    snapshot.delta = !payload.0.is_empty() && payload.u8()? != 0;
+                    if !payload.0.is_empty() {
+                        snapshot.adjustments = payload.amount(version)?;
+                    }
+                    if !payload.0.is_empty() {
+                        snapshot.losses = payload.amount(version)?;
+                    }
+                    snapshot.truncated = !payload.0.is_empty() && payload.u8()? != 0;
+                }
+                SECTION_ACCOUNTS => {
+                    let count = payload.u32()?;
+                    for _ in 0..count {
+                        let length = payload.u32()? as usize;
+                        snapshot
+                            .accounts
+                            .push(decode_account(Reader(payload.take(length)?), version)?);
+                    }
+                }
+                SECTION_ADMIN => {
+                    let count = payload.u32()?;
+                    for _ in 0..count {
+                        snapshot.admin_txs.push(payload.u32()?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Tells snapshot files apart from other inputs by their magic bytes.
+pub fn is_snapshot(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Lists the sections of a snapshot file and whether their checksums match,
+/// without decoding them. Also returns the format version of the file.
+pub fn inspect(bytes: &[u8]) -> anyhow::Result<(u16, Vec<SectionInfo>)> {
+    let (version, sections) = read_sections(bytes)?;
+    let sections = sections
+        .into_iter()
+        .map(|section| SectionInfo {
+            id: section.id,
+            length: section.payload.len() as u32,
+            checksum_ok: crc32(section.payload) == section.checksum,
+        })
+        .collect();
+    Ok((version, sections))
+}
+
+struct Section<'a> {
+    id: u16,
+    checksum: u32,
+    payload: &'a [u8],
+}
+
+fn write_section(bytes: &mut Vec<u8>, id: u16, payload: &[u8]) {
+    bytes.extend(id.to_le_bytes());
+    bytes.extend((payload.len() as u32).to_le_bytes());
+    bytes.extend(crc32(payload).to_le_bytes());
+    bytes.extend(payload);
+}
+
+/// Splits a snapshot file into its version and sections.
+fn read_sections(bytes: &[u8]) -> anyhow::Result<(u16, Vec<Section<'_>>)> {
+    let mut reader = Reader(bytes);
+    if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(anyhow!("Not a snapshot file"));
+    }
+    let version = reader.u16()?;
+    if version > VERSION {
+        return Err(anyhow!(
+            "Snapshot version {version} is newer than the supported version {VERSION}"
+        ));
+    }
+
+    let mut sections = Vec::new();
+    while !reader.0.is_empty() {
+        let id = reader.u16()?;
+        let length = reader.u32()?;
+        let checksum = reader.u32()?;
+        let payload = reader.take(length as usize)?;
+        sections.push(Section {
+            id,
+            checksum,
+            payload,
+        });
+    }
+    Ok((version, sections))
+}
+
+fn decode_account(mut reader: Reader, version: u16) -> anyhow::Result<AccountSnapshot> {
+    let mut account = AccountSnapshot {
+        client: reader.u16()?,
+        total: reader.amount(version)?,
+        held: reader.amount(version)?,
+        locked: reader.u8()? != 0,
+        transactions: Vec::new(),
+        escrow: Vec::new(),
+        forgotten: Vec::new(),
+    };
+    let count = reader.u32()?;
+    for _ in 0..count {
+        account.transactions.push(TransactionSnapshot {
+            tx: reader.u32()?,
+            kind: decode_kind(reader.u8()?)?,
+            amount: reader.amount(version)?,
+            state: decode_state(reader.u8()?)?,
+        });
+    }
+    // Added after the first release of the format.
+    if !reader.0.is_empty() {
+        let count = reader.u32()?;
+        for _ in 0..count {
+            let length = reader.u16()? as usize;
+            let escrow = std::str::from_utf8(reader.take(length)?)?.to_string();
+            account.escrow.push((escrow, reader.amount(version)?));
+        }
+    }
+    if !reader.0.is_empty() {
+        let count = reader.u32()?;
+        for _ in 0..count {
+            account.forgotten.push(reader.u32()?);
+        }
+    }
+    Ok(account)
+}
+
+fn encode_kind(kind: TransactionKind) -> u8 {
+    match kind {
+        TransactionKind::Deposit => 0,
+        TransactionKind::Withdrawal => 1,
+    }
+}
+
+fn decode_kind(value: u8) -> anyhow::Result<TransactionKind> {
+    match value {
+        0 => Ok(TransactionKind::Deposit),
+        1 => Ok(TransactionKind::Withdrawal),
+        other => Err(anyhow!("Unknown transaction kind {other} in snapshot")),
+    }
+}
+
+fn encode_state(state: TransactionState) -> u8 {
+    match state {
+        TransactionState::Processed => 0,
+        TransactionState::Disputed => 1,
+        TransactionState::ChargedBack => 2,
+    }
+}
+
+fn decode_state(value: u8) -> anyhow::Result<TransactionState> {
+    match value {
+        0 => Ok(TransactionState::Processed),
+        1 => Ok(TransactionState::Disputed),
+        2 => Ok(TransactionState::ChargedBack),
+        other => Err(anyhow!("Unknown transaction state {other} in snapshot")),
+    }
+}
+
+/// Reads little-endian values from a byte slice.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < length {
+            return Err(anyhow!("Unexpected end of snapshot"));
+        }
+        let (value, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(value)
+    }
+
+    fn array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    /// Reads an amount, which version 1 stored as an `f32`.
+    fn amount(&mut self, version: u16) -> anyhow::Result<Amount> {
+        if version == 1 {
+            return Ok(Amount::from_f32(f32::from_le_bytes(self.array()?))?);
+        }
+        Ok(Amount::from_units(i64::from_le_bytes(self.array()?)))
+    }
+}
+
+/// CRC-32 (IEEE 802.3), as used by zip and png.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{account::Ledger, check::check};
+
+    fn sample() -> Snapshot {
+        Snapshot {
+            opening: Amount::new(1, 0),
+            deposits: Amount::new(55, 1),
+            withdrawals: Amount::new(2, 0),
+            charged_back: Amount::new(5, 1),
+            adjustments: Amount::new(25, 2),
+            losses: Amount::new(75, 2),
+            delta: false,
+            truncated: true,
+            accounts: vec![
+                AccountSnapshot {
+                    client: 1,
+                    total: Amount::new(4, 0),
+                    held: Amount::new(15, 1),
+                    locked: false,
+                    transactions: vec![
+                        TransactionSnapshot {
+                            tx: 1,
+                            kind: TransactionKind::Deposit,
+                            amount: Amount::new(15, 1),
+                            state: TransactionState::Disputed,
+                        },
+                        TransactionSnapshot {
+                            tx: 2,
+                            kind: TransactionKind::Withdrawal,
+                            amount: Amount::new(2, 0),
+                            state: TransactionState::Processed,
+                        },
+                    ],
+                    escrow: vec![("order-1".to_string(), Amount::new(5, 1))],
+                    forgotten: vec![0],
+                },
+                AccountSnapshot {
+                    client: 2,
+                    total: Amount::ZERO,
+                    held: Amount::ZERO,
+                    locked: true,
+                    transactions: vec![],
+                    escrow: vec![],
+                    forgotten: vec![],
+                },
+            ],
+            admin_txs: vec![3, 7],
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_roundtrip() -> anyhow::Result<()> {
+        let snapshot = sample();
+        assert!(is_snapshot(&snapshot.to_bytes()));
+        assert_eq!(Snapshot::from_bytes(&snapshot.to_bytes())?, snapshot);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detects_corruption() -> anyhow::Result<()> {
+        let mut bytes = sample().to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        assert!(Snapshot::from_bytes(&bytes).is_err());
+        let (_, sections) = inspect(&bytes)?;
+        assert!(sections[0].checksum_ok);
+        assert!(sections[1].checksum_ok);
+        assert!(!sections[2].checksum_ok);
+
+        assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 3]).is_err());
+        assert!(Snapshot::from_bytes(b"PK\x03\x04").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() -> anyhow::Result<()> {
+        let mut snapshot = sample();
+        let delta = Snapshot {
+            deposits: Amount::new(85, 1),
+            delta: true,
+            accounts: vec![
+                AccountSnapshot {
+                    client: 0,
+                    total: Amount::new(3, 0),
+                    ..Default::default()
+                },
+                AccountSnapshot {
+                    client: 2,
+                    total: Amount::new(1, 0),
+                    ..Default::default()
+                },
+            ],
+            admin_txs: vec![3, 7, 9],
+            ..sample()
+        };
+        assert_eq!(Snapshot::from_bytes(&delta.to_bytes())?, delta);
+
+        snapshot.merge(&delta);
+        assert!(!snapshot.delta);
+        assert_eq!(snapshot.deposits, Amount::new(85, 1));
+        assert_eq!(snapshot.admin_txs, [3, 7, 9]);
+        assert_eq!(
+            snapshot
+                .accounts
+                .iter()
+                .map(|account| (account.client, account.total))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, Amount::new(3, 0)),
+                (1, Amount::new(4, 0)),
+                (2, Amount::new(1, 0))
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fold() -> anyhow::Result<()> {
+        let mut ledger = Ledger::new();
+        ledger.deposit(1, 1, Amount::new(10, 0))?;
+        ledger.deposit(1, 2, Amount::new(5, 0))?;
+        ledger.withdraw(1, 3, Amount::new(3, 0))?;
+        ledger.deposit(1, 4, Amount::new(1, 0))?;
+        ledger.dispute(1, 2)?;
+        ledger.deposit(2, 5, Amount::new(7, 0))?;
+        ledger.dispute(2, 5)?;
+        ledger.chargeback(2, 5)?;
+        let snapshot = ledger.snapshot();
+
+        let mut all = snapshot.clone();
+        assert_eq!(all.fold(RetentionPolicy::All)?, 1);
+        assert_eq!(
+            all.accounts[0].transactions,
+            snapshot.accounts[0].transactions
+        );
+        assert!(!all.truncated);
+        assert_eq!(check(&all)?, []);
+
+        let mut folded = snapshot.clone();
+        assert_eq!(folded.fold(RetentionPolicy::DisputeWindow(1))?, 3);
+        assert_eq!(
+            folded.accounts[0]
+                .transactions
+                .iter()
+                .map(|transaction| transaction.tx)
+                .collect::<Vec<_>>(),
+            [2, 4]
+        );
+        assert!(folded.truncated);
+        assert_eq!(folded.opening, Amount::new(7, 0));
+        assert_eq!(folded.deposits, Amount::new(6, 0));
+        assert_eq!(folded.withdrawals, Amount::ZERO);
+        assert_eq!(folded.charged_back, Amount::ZERO);
+        assert_eq!(check(&folded)?, []);
+
+        assert_eq!(folded.accounts[0].forgotten, [1, 3]);
+        assert_eq!(folded.accounts[1].forgotten, [5]);
+
+        let mut restored = Ledger::new();
+        restored.restore(&folded)?;
+        restored.chargeback(1, 2)?;
+        assert!(restored.dispute(1, 1).is_err());
+        assert!(restored.deposit(1, 1, Amount::new(10, 0)).is_err());
+        assert_eq!(
+            restored.client_record(1)?.map(|account| account.total),
+            Some(Amount::new(8, 0))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_newer_version() {
+        let mut bytes = sample().to_bytes();
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+        assert!(Snapshot::from_bytes(&bytes).is_err());
+    }
+
+    /// Encodes a snapshot the way version 1 did, with `f32` amounts.
+    fn to_v1_bytes(snapshot: &Snapshot) -> Vec<u8> {
+        let amount = |value: Amount| (value.units() as f32 / 10_000.).to_le_bytes();
+
+        let mut meta = Vec::new();
+        for value in [
+            snapshot.opening,
+            snapshot.deposits,
+            snapshot.withdrawals,
+            snapshot.charged_back,
+        ] {
+            meta.extend(amount(value));
+        }
+        meta.push(u8::from(snapshot.delta));
+        meta.extend(amount(snapshot.adjustments));
+        meta.extend(amount(snapshot.losses));
+        meta.push(u8::from(snapshot.truncated));
+
+        let mut accounts = Vec::new();
+        accounts.extend((snapshot.accounts.len() as u32).to_le_bytes());
+        for account in &snapshot.accounts {
+            let mut entry = Vec::new();
+            entry.extend(account.client.to_le_bytes());
+            entry.extend(amount(account.total));
+            entry.extend(amount(account.held));
+            entry.push(u8::from(account.locked));
+            entry.extend((account.transactions.len() as u32).to_le_bytes());
+            for transaction in &account.transactions {
+                entry.extend(transaction.tx.to_le_bytes());
+                entry.push(encode_kind(transaction.kind));
+                entry.extend(amount(transaction.amount));
+                entry.push(encode_state(transaction.state));
+            }
+            entry.extend((account.escrow.len() as u32).to_le_bytes());
+            for (escrow, value) in &account.escrow {
+                entry.extend((escrow.len() as u16).to_le_bytes());
+                entry.extend(escrow.as_bytes());
+                entry.extend(amount(*value));
+            }
+            accounts.extend((entry.len() as u32).to_le_bytes());
+            accounts.extend(entry);
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend(MAGIC);
+        bytes.extend(1u16.to_le_bytes());
+        write_section(&mut bytes, SECTION_META, &meta);
+        write_section(&mut bytes, SECTION_ACCOUNTS, &accounts);
+        bytes
+    }
+
+    #[test]
+    fn test_reads_version_1() -> anyhow::Result<()> {
+        // Version 1 predates the admin section and the dropped tx ids.
+        let mut snapshot = Snapshot {
+            admin_txs: vec![],
+            ..sample()
+        };
+        for account in &mut snapshot.accounts {
+            account.forgotten.clear();
+        }
+        assert_eq!(Snapshot::from_bytes(&to_v1_bytes(&snapshot))?, snapshot);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_skips_unknown_data() -> anyhow::Result<()> {
+        let snapshot = sample();
+        let mut bytes = snapshot.to_bytes();
+        write_section(&mut bytes, 99, b"from the future");
+
+        let (version, sections) = inspect(&bytes)?;
+        assert_eq!(version, VERSION);
+        assert_eq!(sections.len(), 4);
+        assert_eq!(Snapshot::from_bytes(&bytes)?, snapshot);
+
+        Ok(())
+    }
+}
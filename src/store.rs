@@ -0,0 +1,324 @@
+use std::collections::VecDeque;
+
+use crate::amount::Amount;
+
+/// Whether a stored transaction added or removed funds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionKind {
+    Deposit,
+    Withdrawal,
+}
+
+/// The lifecycle state of a single stored transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionState {
+    Processed,
+    Disputed,
+    ChargedBack,
+}
+
+/// Compact storage for the transactions of a single customer.
+///
+/// Instead of a hash map, transactions are kept in parallel columns sorted
+/// by tx id. Transaction ids usually arrive in ascending order, so inserts
+/// are almost always a plain push, while lookups use a binary search.
+///
+/// The ids of transactions that are not or no longer stored, like those
+/// dropped by a retention policy or of escrow operations, are remembered in a
+/// sorted list of their own, so replayed transactions can still be rejected.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TransactionStore {
+    ids: Vec<u32>,
+    amounts: Vec<Amount>,
+    kinds: Vec<TransactionKind>,
+    states: Vec<TransactionState>,
+    /// Ids of the transactions inserted by [`TransactionStore::insert_latest`],
+    /// oldest first.
+    arrivals: VecDeque<u32>,
+    /// Ids of transactions that are not stored, in ascending order.
+    forgotten: Vec<u32>,
+}
+
+impl TransactionStore {
+    /// Inserts a new transaction in the `Processed` state.
+    /// Returns `false` if a transaction with this id is already stored.
+    pub fn insert(&mut self, tx: u32, kind: TransactionKind, amount: Amount) -> bool {
+        let index = match self.ids.last() {
+            Some(&last) if last < tx => self.ids.len(),
+            None => 0,
+            _ => match self.ids.binary_search(&tx) {
+                Ok(_) => return false,
+                Err(index) => index,
+            },
+        };
+
+        self.ids.insert(index, tx);
+        self.amounts.insert(index, amount);
+        self.kinds.insert(index, kind);
+        self.states.insert(index, TransactionState::Processed);
+
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn contains(&self, tx: u32) -> bool {
+        self.position(tx).is_some()
+    }
+
+    pub fn amount(&self, tx: u32) -> Option<Amount> {
+        self.position(tx).map(|index| self.amounts[index])
+    }
+
+    pub fn kind(&self, tx: u32) -> Option<TransactionKind> {
+        self.position(tx).map(|index| self.kinds[index])
+    }
+
+    pub fn state(&self, tx: u32) -> Option<TransactionState> {
+        self.position(tx).map(|index| self.states[index])
+    }
+
+    /// Updates the state of a transaction, doing nothing if it is not stored.
+    pub fn set_state(&mut self, tx: u32, state: TransactionState) {
+        if let Some(index) = self.position(tx) {
+            self.states[index] = state;
+        }
+    }
+
+    /// Iterates over all stored transactions in ascending tx id order.
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = (u32, TransactionKind, Amount, TransactionState)> + '_ {
+        (0..self.ids.len()).map(|index| {
+            (
+                self.ids[index],
+                self.kinds[index],
+                self.amounts[index],
+                self.states[index],
+            )
+        })
+    }
+
+    /// Inserts a transaction like [`TransactionStore::insert`], dropping the
+    /// oldest undisputed transactions inserted this way once more than twice
+    /// `window` of them are queued, so only the `window` most recent ones are
+    /// kept. Disputed transactions are queued again, charged back ones are
+    /// always kept.
+    pub fn insert_latest(
+        &mut self,
+        tx: u32,
+        kind: TransactionKind,
+        amount: Amount,
+        window: usize,
+    ) -> bool {
+        if !self.insert(tx, kind, amount) {
+            return false;
+        }
+        self.enqueue(tx);
+        if self.arrivals.len() <= window.saturating_mul(2) {
+            return true;
+        }
+
+        let mut dropped = Vec::new();
+        for _ in window..self.arrivals.len() {
+            let Some(oldest) = self.arrivals.pop_front() else {
+                break;
+            };
+            match self.state(oldest) {
+                Some(TransactionState::Processed) => dropped.push(oldest),
+                Some(TransactionState::Disputed) => self.arrivals.push_back(oldest),
+                Some(TransactionState::ChargedBack) | None => {}
+            }
+        }
+        if dropped.is_empty() {
+            return true;
+        }
+        dropped.sort_unstable();
+
+        let keep: Vec<bool> = self
+            .ids
+            .iter()
+            .map(|id| dropped.binary_search(id).is_err())
+            .collect();
+        let mut flags = keep.iter();
+        self.ids.retain(|_| *flags.next().unwrap_or(&true));
+        let mut flags = keep.iter();
+        self.amounts.retain(|_| *flags.next().unwrap_or(&true));
+        let mut flags = keep.iter();
+        self.kinds.retain(|_| *flags.next().unwrap_or(&true));
+        let mut flags = keep.iter();
+        self.states.retain(|_| *flags.next().unwrap_or(&true));
+        for tx in dropped {
+            self.forget(tx);
+        }
+
+        true
+    }
+
+    /// Queues a stored transaction for [`TransactionStore::insert_latest`] to
+    /// drop, as if it had been inserted by it.
+    pub fn enqueue(&mut self, tx: u32) {
+        self.arrivals.push_back(tx);
+    }
+
+    /// Remembers the id of a transaction that is not stored, so that
+    /// [`TransactionStore::seen`] rejects it from now on.
+    pub fn forget(&mut self, tx: u32) {
+        match self.forgotten.last() {
+            Some(&last) if last < tx => self.forgotten.push(tx),
+            None => self.forgotten.push(tx),
+            _ => {
+                if let Err(index) = self.forgotten.binary_search(&tx) {
+                    self.forgotten.insert(index, tx);
+                }
+            }
+        }
+    }
+
+    /// Whether a transaction with this id is stored or was dropped before.
+    pub fn seen(&self, tx: u32) -> bool {
+        self.contains(tx) || self.forgotten.binary_search(&tx).is_ok()
+    }
+
+    /// The ids of transactions that are not stored, in ascending order.
+    pub fn forgotten(&self) -> &[u32] {
+        &self.forgotten
+    }
+
+    fn position(&self, tx: u32) -> Option<usize> {
+        self.ids.binary_search(&tx).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_in_order() {
+        let mut store = TransactionStore::default();
+        assert!(store.insert(1, TransactionKind::Deposit, Amount::from_units(1)));
+        assert!(store.insert(2, TransactionKind::Deposit, Amount::from_units(2)));
+        assert!(store.insert(5, TransactionKind::Deposit, Amount::from_units(5)));
+
+        assert_eq!(store.ids.len(), 3);
+        assert_eq!(store.amount(2), Some(Amount::from_units(2)));
+        assert_eq!(store.kind(5), Some(TransactionKind::Deposit));
+        assert_eq!(store.state(5), Some(TransactionState::Processed));
+    }
+
+    #[test]
+    fn test_insert_out_of_order() {
+        let mut store = TransactionStore::default();
+        assert!(store.insert(5, TransactionKind::Deposit, Amount::from_units(5)));
+        assert!(store.insert(1, TransactionKind::Deposit, Amount::from_units(1)));
+        assert!(store.insert(3, TransactionKind::Deposit, Amount::from_units(3)));
+
+        assert_eq!(store.ids, vec![1, 3, 5]);
+        assert_eq!(
+            store.amounts,
+            vec![
+                Amount::from_units(1),
+                Amount::from_units(3),
+                Amount::from_units(5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_duplicate() {
+        let mut store = TransactionStore::default();
+        assert!(store.insert(2, TransactionKind::Deposit, Amount::from_units(2)));
+        assert!(store.insert(3, TransactionKind::Deposit, Amount::from_units(3)));
+
+        assert!(!store.insert(2, TransactionKind::Deposit, Amount::from_units(4)));
+        assert!(!store.insert(3, TransactionKind::Deposit, Amount::from_units(4)));
+        assert_eq!(store.ids.len(), 2);
+        assert_eq!(store.amount(2), Some(Amount::from_units(2)));
+    }
+
+    #[test]
+    fn test_bytes_per_transaction() {
+        let mut store = TransactionStore::default();
+        for tx in 0..1_000_000 {
+            store.insert(tx, TransactionKind::Deposit, Amount::from_units(1));
+        }
+
+        let bytes = store.ids.capacity() * size_of::<u32>()
+            + store.amounts.capacity() * size_of::<Amount>()
+            + store.kinds.capacity() * size_of::<TransactionKind>()
+            + store.states.capacity() * size_of::<TransactionState>();
+        // 14 bytes per transaction plus the spare capacity of the columns,
+        // where a HashMap<u32, Amount> takes about 36.
+        assert!(bytes / 1_000_000 < 15);
+    }
+
+    #[test]
+    fn test_set_state() {
+        let mut store = TransactionStore::default();
+        store.insert(1, TransactionKind::Deposit, Amount::from_units(1));
+
+        store.set_state(1, TransactionState::Disputed);
+        assert_eq!(store.state(1), Some(TransactionState::Disputed));
+
+        store.set_state(2, TransactionState::Disputed);
+        assert_eq!(store.state(2), None);
+        assert!(!store.contains(2));
+    }
+
+    #[test]
+    fn test_insert_latest() {
+        let mut store = TransactionStore::default();
+        for tx in 1..=2 {
+            store.insert_latest(
+                tx,
+                TransactionKind::Deposit,
+                Amount::from_units(tx.into()),
+                1,
+            );
+        }
+        store.set_state(1, TransactionState::Disputed);
+
+        store.insert_latest(3, TransactionKind::Deposit, Amount::from_units(3), 1);
+        assert_eq!(store.ids, vec![1, 3]);
+        assert_eq!(
+            store.amounts,
+            [Amount::from_units(1), Amount::from_units(3)]
+        );
+        assert_eq!(store.state(1), Some(TransactionState::Disputed));
+        assert_eq!(store.forgotten(), [2]);
+        assert!(store.seen(2));
+        assert!(!store.insert_latest(3, TransactionKind::Deposit, Amount::ZERO, 1));
+    }
+
+    #[test]
+    fn test_insert_latest_arrival_order() {
+        let mut store = TransactionStore::default();
+        for tx in (5..=9).rev() {
+            store.insert_latest(
+                tx,
+                TransactionKind::Deposit,
+                Amount::from_units(tx.into()),
+                2,
+            );
+        }
+
+        assert_eq!(store.ids, vec![5, 6]);
+        assert_eq!(store.forgotten(), [7, 8, 9]);
+    }
+
+    #[test]
+    fn test_forget() {
+        let mut store = TransactionStore::default();
+        store.forget(3);
+        store.forget(1);
+        store.forget(3);
+
+        assert_eq!(store.forgotten(), [1, 3]);
+        assert!(store.seen(1));
+        assert!(!store.seen(2));
+        assert!(!store.contains(1));
+    }
+}
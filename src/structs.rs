@@ -1,35 +1,255 @@
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display, rc::Rc, str::FromStr};
 
 use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    amount::{self, Amount},
+    error::AmountError,
+};
+
+/// Decimal places an amount may have, unless configured otherwise.
+pub const DEFAULT_MAX_DECIMALS: u32 = amount::DECIMALS;
+/// Largest amount a record may have, unless configured otherwise: 1e12.
+pub const DEFAULT_MAX_AMOUNT: Amount = Amount::from_units(10_i64.pow(12 + amount::DECIMALS));
+
 // CSV file contents
 
-#[derive(Debug, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Record {
     #[serde(rename = "type")]
     pub record_type: RecordType,
 
     pub client: u16,
     pub tx: u32,
-    pub amount: Option<f32>,
+    pub amount: Option<Amount>,
+    /// When the transaction happened, in seconds since the unix epoch. The
+    /// `timestamp` column is optional.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// The merchant or other counterparty of a deposit or withdrawal, from
+    /// the optional `counterparty` column.
+    #[serde(default)]
+    pub counterparty: Option<String>,
+    /// A free-text category of a deposit or withdrawal, from the optional
+    /// `tag` column.
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// The position of the record in the producer's stream, from the
+    /// optional `sequence` column. It should increase by one per record.
+    #[serde(default)]
+    pub sequence: Option<u64>,
+    /// The payment processor the record belongs to, from the optional
+    /// `tenant` column. Tenants are kept in isolated ledgers.
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Authorizes an admin operation, from the optional `signature` column,
+    /// see [`AdminKey`](crate::admin::AdminKey).
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// Why an adjustment was made, from the optional `reason_code` column,
+    /// e.g. `FEE_REFUND`. Required for adjustments.
+    #[serde(default)]
+    pub reason_code: Option<String>,
+    /// The escrow bucket of an escrow hold or release, from the optional
+    /// `escrow` column, e.g. `order-17`.
+    #[serde(default)]
+    pub escrow: Option<String>,
+    /// The values of input columns the engine does not know, by header, if
+    /// captured with `--extra-columns`.
+    #[serde(skip)]
+    pub extra: Option<BTreeMap<String, String>>,
+    /// Where the record was read from, if known.
+    #[serde(skip)]
+    pub source: Option<Source>,
+}
+
+/// The input file and line a record was read from, for investigators to find
+/// the original row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub file: Rc<str>,
+    pub line: u64,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// An empty deposit, to build records with struct update syntax.
+impl Default for Record {
+    fn default() -> Self {
+        Self {
+            record_type: RecordType::Deposit,
+            client: 0,
+            tx: 0,
+            amount: None,
+            timestamp: None,
+            counterparty: None,
+            tag: None,
+            sequence: None,
+            tenant: None,
+            signature: None,
+            reason_code: None,
+            escrow: None,
+            extra: None,
+            source: None,
+        }
+    }
 }
 
 impl Record {
+    /// Whether the record claims to be an admin operation: an unlock, a
+    /// write-off or a signed adjustment, which needs to be verified with an
+    /// [`AdminKey`](crate::admin::AdminKey). Unsigned adjustments are subject
+    /// to the same checks as customer transactions.
+    pub fn is_admin(&self) -> bool {
+        match self.record_type {
+            RecordType::Adjustment => self.signature.is_some(),
+            record_type => record_type.is_admin(),
+        }
+    }
+
+    /// Deserializes a csv row, normalizing the amount from the number format
+    /// and, unless `strict_types` is set, record type aliases, see
+    /// [`RecordType::from_alias`].
+    pub fn from_row(
+        row: &csv::StringRecord,
+        headers: &csv::StringRecord,
+        number_format: NumberFormat,
+        strict_types: bool,
+    ) -> anyhow::Result<Self> {
+        let type_index = headers.iter().position(|header| header == "type");
+        let record_type = type_index
+            .and_then(|index| row.get(index))
+            .filter(|_| !strict_types)
+            .and_then(|value| RecordType::from_alias(value).filter(|alias| alias.name() != value));
+        if number_format == NumberFormat::Standard && record_type.is_none() {
+            return Ok(row.deserialize(Some(headers))?);
+        }
+
+        let amount_index = headers.iter().position(|header| header == "amount");
+        let row = row
+            .iter()
+            .enumerate()
+            .map(|(index, field)| match (record_type, amount_index) {
+                (Some(record_type), _) if Some(index) == type_index => {
+                    Ok(record_type.name().to_string())
+                }
+                (_, Some(amount_index)) if index == amount_index && !field.is_empty() => {
+                    number_format.normalize(field)
+                }
+                _ => Ok(field.to_string()),
+            })
+            .collect::<anyhow::Result<csv::StringRecord>>()?;
+
+        Ok(row.deserialize(Some(headers))?)
+    }
+
     pub fn validate(&self) -> anyhow::Result<()> {
+        self.validate_with(&AmountLimits::default())
+    }
+
+    /// Validates the record, checking its amount against the given limits.
+    pub fn validate_with(&self, limits: &AmountLimits) -> anyhow::Result<()> {
+        match self.reason_code.as_deref() {
+            None if self.record_type == RecordType::Adjustment => {
+                return Err(anyhow!("Missing reason code in adjustment record"))
+            }
+            Some(reason_code) if !is_valid_reason_code(reason_code) => {
+                return Err(anyhow!("Invalid reason code {reason_code}"))
+            }
+            _ => {}
+        }
         match (&self.record_type, self.amount) {
             (RecordType::Deposit | RecordType::Withdrawal, None) => {
                 Err(anyhow!("Missing amount in record"))
             }
+            (RecordType::Deposit | RecordType::Withdrawal, Some(amount)) => {
+                Ok(limits.check(amount)?)
+            }
             (RecordType::Chargeback | RecordType::Resolve | RecordType::Dispute, Some(_)) => Err(
                 anyhow!("Chargeback / Resolve / Dispute records may not contain an amount"),
             ),
+            (RecordType::Adjustment, None) => Err(anyhow!("Missing amount in record")),
+            // Adjustments may take funds away, so only their size is checked.
+            (RecordType::Adjustment, Some(amount)) => Ok(limits.check(amount.checked_abs()?)?),
+            (RecordType::Unlock | RecordType::WriteOff, Some(_)) => Err(anyhow!(
+                "Unlock / Write-off records may not contain an amount"
+            )),
+            (RecordType::EscrowHold | RecordType::EscrowRelease, _)
+                if !self.escrow.as_deref().is_some_and(is_valid_escrow) =>
+            {
+                Err(anyhow!("Missing or invalid escrow bucket in record"))
+            }
+            (RecordType::EscrowHold | RecordType::EscrowRelease, None) => {
+                Err(anyhow!("Missing amount in record"))
+            }
+            (RecordType::EscrowHold | RecordType::EscrowRelease, Some(amount)) => {
+                Ok(limits.check(amount)?)
+            }
             _ => Ok(()),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
+/// Whether the reason code of an adjustment is ascii letters, digits, `-` and
+/// `_` only, e.g. `FEE_REFUND`.
+pub fn is_valid_reason_code(reason_code: &str) -> bool {
+    is_identifier(reason_code)
+}
+
+/// Whether the name of an escrow bucket is ascii letters, digits, `-` and `_`
+/// only, e.g. `order-17`.
+pub fn is_valid_escrow(escrow: &str) -> bool {
+    is_identifier(escrow)
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Bounds for the amounts of deposits and withdrawals.
+///
+/// Amounts are parsed with the precision of an [`Amount`], so they are
+/// checked after parsing: they must not be negative, at most `max_amount`
+/// and must not have more than `max_decimals` decimal places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountLimits {
+    pub max_decimals: u32,
+    pub max_amount: Amount,
+}
+
+impl Default for AmountLimits {
+    fn default() -> Self {
+        Self {
+            max_decimals: DEFAULT_MAX_DECIMALS,
+            max_amount: DEFAULT_MAX_AMOUNT,
+        }
+    }
+}
+
+impl AmountLimits {
+    pub fn check(&self, amount: Amount) -> Result<(), AmountError> {
+        if amount < Amount::ZERO {
+            return Err(AmountError::Negative);
+        }
+        if amount > self.max_amount {
+            return Err(AmountError::TooLarge(self.max_amount));
+        }
+        if !amount.has_decimals(self.max_decimals) {
+            return Err(AmountError::TooManyDecimals(self.max_decimals));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum RecordType {
     Deposit,
@@ -37,31 +257,389 @@ pub enum RecordType {
     Dispute,
     Resolve,
     Chargeback,
+    /// Unfreezes an account. Admin operation.
+    Unlock,
+    /// Adds a positive or negative amount to an account. Admin operation.
+    Adjustment,
+    /// Books the negative total of an account as a loss, bringing it back to
+    /// zero. Admin operation.
+    #[serde(rename = "writeoff")]
+    WriteOff,
+    /// Moves available funds into an escrow bucket.
+    #[serde(rename = "escrow_hold")]
+    EscrowHold,
+    /// Moves funds from an escrow bucket back to the available funds.
+    #[serde(rename = "escrow_release")]
+    EscrowRelease,
+}
+
+impl RecordType {
+    /// Parses the record types upstream systems use besides the canonical
+    /// ones, ignoring case, `_`, `-` and spaces, e.g. `DEPOSIT`, `withdraw` or
+    /// `charge_back`.
+    pub fn from_alias(alias: &str) -> Option<Self> {
+        let normalized: String = alias
+            .chars()
+            .filter(|c| !matches!(c, '_' | '-' | ' '))
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        match normalized.as_str() {
+            "deposit" => Some(RecordType::Deposit),
+            "withdrawal" | "withdraw" => Some(RecordType::Withdrawal),
+            "dispute" => Some(RecordType::Dispute),
+            "resolve" => Some(RecordType::Resolve),
+            "chargeback" => Some(RecordType::Chargeback),
+            "unlock" => Some(RecordType::Unlock),
+            "adjustment" | "adjust" => Some(RecordType::Adjustment),
+            "writeoff" => Some(RecordType::WriteOff),
+            "escrowhold" => Some(RecordType::EscrowHold),
+            "escrowrelease" => Some(RecordType::EscrowRelease),
+            _ => None,
+        }
+    }
+
+    /// The canonical name of the type in the input.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordType::Deposit => "deposit",
+            RecordType::Withdrawal => "withdrawal",
+            RecordType::Dispute => "dispute",
+            RecordType::Resolve => "resolve",
+            RecordType::Chargeback => "chargeback",
+            RecordType::Unlock => "unlock",
+            RecordType::Adjustment => "adjustment",
+            RecordType::WriteOff => "writeoff",
+            RecordType::EscrowHold => "escrow_hold",
+            RecordType::EscrowRelease => "escrow_release",
+        }
+    }
+
+    /// Whether records of this type can be admin operations, which must be
+    /// signed. Adjustments are only admin operations if they carry a
+    /// signature, see [`Record::is_admin`].
+    pub fn is_admin(&self) -> bool {
+        matches!(
+            self,
+            RecordType::Unlock | RecordType::Adjustment | RecordType::WriteOff
+        )
+    }
 }
 
 impl Display for RecordType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The decimal and thousands separators used for amounts in the input or the
+/// accounts output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// `1234.5678`
+    #[default]
+    Standard,
+    /// `1,234.5678`
+    English,
+    /// `1.234,5678`
+    German,
+    /// `1 234,5678`
+    French,
+    /// `1'234.5678`
+    Swiss,
+}
+
+impl NumberFormat {
+    /// Returns the thousands and the decimal separator of this format.
+    fn separators(self) -> (&'static [char], char) {
         match self {
-            RecordType::Deposit => write!(f, "deposit"),
-            RecordType::Withdrawal => write!(f, "withdrawal"),
-            RecordType::Dispute => write!(f, "dispute"),
-            RecordType::Resolve => write!(f, "resolve"),
-            RecordType::Chargeback => write!(f, "chargeback"),
+            NumberFormat::Standard => (&[], '.'),
+            NumberFormat::English => (&[','], '.'),
+            NumberFormat::German => (&['.'], ','),
+            NumberFormat::French => (&[' ', '\u{a0}', '\u{202f}'], ','),
+            NumberFormat::Swiss => (&['\''], '.'),
+        }
+    }
+
+    /// Rewrites an amount in this format into the standard format.
+    pub fn normalize(self, amount: &str) -> anyhow::Result<String> {
+        let (thousands, decimal) = self.separators();
+        let (integer, fraction) = match amount.split_once(decimal) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (amount, None),
+        };
+
+        if fraction
+            .is_some_and(|fraction| fraction.contains(decimal) || fraction.contains(thousands))
+        {
+            return Err(anyhow!("Malformed amount {amount}"));
+        }
+
+        // Thousands separators must group the digits by three, so that a
+        // decimal separator of another format is not silently dropped.
+        let digits = integer.trim_start_matches(['-', '+']);
+        if digits.contains(thousands) {
+            let mut groups = digits.split(thousands);
+            let first = groups.next().unwrap_or_default();
+            if !(1..=3).contains(&first.len()) || groups.any(|group| group.len() != 3) {
+                return Err(anyhow!("Malformed digit grouping in amount {amount}"));
+            }
+        }
+
+        let mut normalized: String = integer.chars().filter(|c| !thousands.contains(c)).collect();
+        if let Some(fraction) = fraction {
+            normalized.push('.');
+            normalized.push_str(fraction);
+        }
+        Ok(normalized)
+    }
+
+    /// Rewrites an amount in the standard format into this format, grouping
+    /// the thousands with the first thousands separator.
+    pub fn localize(self, amount: &str) -> String {
+        let (thousands, decimal) = self.separators();
+        let Some(&separator) = thousands.first() else {
+            return amount.to_string();
+        };
+        let (sign, digits) = match amount.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", amount),
+        };
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (digits, None),
+        };
+
+        let mut localized = sign.to_string();
+        for (index, digit) in integer.chars().enumerate() {
+            if index > 0 && (integer.len() - index) % 3 == 0 {
+                localized.push(separator);
+            }
+            localized.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            localized.push(decimal);
+            localized.push_str(fraction);
+        }
+        localized
+    }
+}
+
+impl FromStr for NumberFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(NumberFormat::Standard),
+            "en" => Ok(NumberFormat::English),
+            "de" => Ok(NumberFormat::German),
+            "fr" => Ok(NumberFormat::French),
+            "ch" => Ok(NumberFormat::Swiss),
+            other => Err(anyhow!(
+                "Unknown number format {other}, expected one of: standard, en, de, fr, ch"
+            )),
         }
     }
 }
 
 // Outputs
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ClientRecord {
     pub client: u16,
-    pub available: f32,
-    pub held: f32,
-    pub total: f32,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+    /// Funds in escrow buckets, which are neither available nor held.
+    /// Missing in the output of earlier versions, which is read as zero.
+    #[serde(default)]
+    pub escrowed: Amount,
+}
+
+/// An account with the details of how it got locked, for the `--extended`
+/// output.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtendedClientRecord {
+    pub client: u16,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+    pub chargebacks: u32,
+    /// The freeze rule that locked the account, if any.
+    pub frozen_by: Option<String>,
+    /// When a temporary freeze ends, in seconds since the unix epoch.
+    pub frozen_until: Option<u64>,
+    /// Funds in escrow buckets, which are neither available nor held.
+    pub escrowed: Amount,
+}
+
+/// A row of the accounts output with account groups: either an account with
+/// its group, or the summed up accounts of a group without a client.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupedClientRecord {
+    pub client: Option<u16>,
+    pub group: Option<String>,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    /// For group rows, whether any member is locked.
+    pub locked: bool,
+}
+
+/// The balances of a client after a transaction, see
+/// [`BalanceHistory`](crate::history::BalanceHistory).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryRecord {
+    pub client: u16,
+    pub timestamp: Option<u64>,
+    pub tx: u32,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
     pub locked: bool,
 }
 
+/// A row of the `concentration` report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConcentrationRecord {
+    /// Whether the accounts are ranked by `total` or `held` funds.
+    pub by: &'static str,
+    pub rank: usize,
+    pub client: u16,
+    pub amount: Amount,
+    /// Share of the funds of all accounts.
+    pub share: f32,
+    /// Share of the funds of this and all higher ranked accounts.
+    pub cumulative_share: f32,
+}
+
+/// A row of the `counterparties` report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CounterpartyRecord {
+    pub counterparty: String,
+    pub deposits: u64,
+    pub deposited: Amount,
+    pub withdrawals: u64,
+    pub withdrawn: Amount,
+    /// Funds of disputed deposits that are currently held.
+    pub held: Amount,
+    pub chargebacks: u64,
+    pub charged_back: Amount,
+    /// Chargebacks per deposit.
+    pub chargeback_rate: f32,
+}
+
+/// A row of the `tags` report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TagRecord {
+    pub client: u16,
+    pub tag: String,
+    pub deposits: u64,
+    pub deposited: Amount,
+    pub withdrawals: u64,
+    pub withdrawn: Amount,
+    pub charged_back: Amount,
+    /// Deposited minus withdrawn and charged back funds.
+    pub net: Amount,
+}
+
+/// A row of the `adjustments` report: an adjustment or write-off, for audit.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdjustmentRecord {
+    pub client: u16,
+    pub tx: u32,
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    pub amount: Amount,
+    pub reason_code: Option<String>,
+    /// Whether the record was a signed admin operation, which bypasses the
+    /// locked and insufficient funds checks.
+    pub admin: bool,
+}
+
+/// A row of the quarantine file: a record held back by a risk check, with
+/// its input row and the reason, for manual review.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantineRecord {
+    pub row: u64,
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    pub client: u16,
+    pub tx: u32,
+    pub amount: Option<Amount>,
+    pub timestamp: Option<u64>,
+    pub counterparty: Option<String>,
+    pub tag: Option<String>,
+    pub sequence: Option<u64>,
+    pub tenant: Option<String>,
+    pub signature: Option<String>,
+    pub reason_code: Option<String>,
+    pub escrow: Option<String>,
+    pub reason: String,
+    /// Set to `true` by the reviewer to have `apply-quarantine` apply the
+    /// record.
+    pub approved: bool,
+}
+
+impl QuarantineRecord {
+    pub fn new(row: u64, record: &Record, reason: &str) -> Self {
+        Self {
+            row,
+            record_type: record.record_type,
+            client: record.client,
+            tx: record.tx,
+            amount: record.amount,
+            timestamp: record.timestamp,
+            counterparty: record.counterparty.clone(),
+            tag: record.tag.clone(),
+            sequence: record.sequence,
+            tenant: record.tenant.clone(),
+            signature: record.signature.clone(),
+            reason_code: record.reason_code.clone(),
+            escrow: record.escrow.clone(),
+            reason: reason.to_string(),
+            approved: false,
+        }
+    }
+
+    pub fn into_record(self) -> Record {
+        Record {
+            record_type: self.record_type,
+            client: self.client,
+            tx: self.tx,
+            amount: self.amount,
+            timestamp: self.timestamp,
+            counterparty: self.counterparty,
+            tag: self.tag,
+            sequence: self.sequence,
+            tenant: self.tenant,
+            signature: self.signature,
+            reason_code: self.reason_code,
+            escrow: self.escrow,
+            ..Default::default()
+        }
+    }
+}
+
+/// Control totals across all clients, produced by the `report` command.
+#[derive(Debug, Serialize)]
+pub struct TrialBalance {
+    /// Balances carried over from a previous run.
+    pub opening: Amount,
+    pub deposits: Amount,
+    pub withdrawals: Amount,
+    pub charged_back: Amount,
+    /// Net funds added by adjustments.
+    pub adjustments: Amount,
+    /// Negative balances written off as losses.
+    pub written_off: Amount,
+    pub held: Amount,
+    /// Total funds of all clients: opening balances plus deposits,
+    /// adjustments and write-offs, minus withdrawals and chargebacks.
+    pub net: Amount,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,54 +663,163 @@ mod tests {
                     record_type: RecordType::Deposit,
                     client: 1,
                     tx: 1,
-                    amount: Some(1.0)
+                    amount: Some(Amount::new(1, 0)),
+                    ..Default::default()
                 },
                 Record {
                     record_type: RecordType::Deposit,
                     client: 2,
                     tx: 2,
-                    amount: Some(2.0)
+                    amount: Some(Amount::new(2, 0)),
+                    ..Default::default()
                 },
                 Record {
                     record_type: RecordType::Deposit,
                     client: 3,
                     tx: 3,
-                    amount: Some(4.1234)
+                    amount: Some(Amount::new(41_234, 4)),
+                    ..Default::default()
                 },
                 Record {
                     record_type: RecordType::Withdrawal,
                     client: 3,
                     tx: 4,
-                    amount: Some(4.0)
+                    amount: Some(Amount::new(4, 0)),
+                    ..Default::default()
                 },
                 Record {
                     record_type: RecordType::Dispute,
                     client: 1,
                     tx: 1,
-                    amount: None
+                    amount: None,
+                    ..Default::default()
                 },
                 Record {
                     record_type: RecordType::Resolve,
                     client: 1,
                     tx: 1,
-                    amount: None
+                    amount: None,
+                    ..Default::default()
                 },
                 Record {
                     record_type: RecordType::Dispute,
                     client: 2,
                     tx: 2,
-                    amount: None
+                    amount: None,
+                    ..Default::default()
                 },
                 Record {
                     record_type: RecordType::Chargeback,
                     client: 2,
                     tx: 2,
-                    amount: None
+                    amount: None,
+                    ..Default::default()
                 },
             ]
         );
     }
 
+    #[test]
+    fn test_record_type_aliases() -> anyhow::Result<()> {
+        assert_eq!(RecordType::from_alias("DEPOSIT"), Some(RecordType::Deposit));
+        assert_eq!(
+            RecordType::from_alias("withdraw"),
+            Some(RecordType::Withdrawal)
+        );
+        assert_eq!(
+            RecordType::from_alias("charge_back"),
+            Some(RecordType::Chargeback)
+        );
+        assert_eq!(
+            RecordType::from_alias("Escrow-Hold"),
+            Some(RecordType::EscrowHold)
+        );
+        assert_eq!(RecordType::from_alias("payment"), None);
+
+        let headers = csv::StringRecord::from(vec!["type", "client", "tx", "amount"]);
+        let row = csv::StringRecord::from(vec!["Charge_Back", "1", "2", ""]);
+        let record = Record::from_row(&row, &headers, NumberFormat::Standard, false)?;
+        assert_eq!(record.record_type, RecordType::Chargeback);
+        assert!(Record::from_row(&row, &headers, NumberFormat::Standard, true).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_number_format_localize() {
+        assert_eq!(NumberFormat::Standard.localize("1234.56"), "1234.56");
+        assert_eq!(NumberFormat::English.localize("1234567.5"), "1,234,567.5");
+        assert_eq!(NumberFormat::German.localize("-1234.56"), "-1.234,56");
+        assert_eq!(NumberFormat::French.localize("123456"), "123 456");
+        assert_eq!(NumberFormat::Swiss.localize("999.25"), "999.25");
+        assert_eq!(NumberFormat::German.localize("0.5"), "0,5");
+    }
+
+    #[test]
+    fn test_number_format_normalize() -> anyhow::Result<()> {
+        assert_eq!(NumberFormat::Standard.normalize("1234.56")?, "1234.56");
+        assert_eq!(NumberFormat::English.normalize("1,234.56")?, "1234.56");
+        assert_eq!(NumberFormat::German.normalize("1.234,56")?, "1234.56");
+        assert_eq!(NumberFormat::German.normalize("234,5")?, "234.5");
+        assert_eq!(NumberFormat::French.normalize("1 234 567,8")?, "1234567.8");
+        assert_eq!(NumberFormat::Swiss.normalize("1'234.5")?, "1234.5");
+        assert_eq!(NumberFormat::German.normalize("12")?, "12");
+
+        assert!(NumberFormat::German.normalize("1,234,5").is_err());
+        assert!(NumberFormat::English.normalize("1.234,5").is_err());
+        assert_eq!(NumberFormat::German.normalize("1.000.000,5")?, "1000000.5");
+        assert!(NumberFormat::German.normalize("1.5").is_err());
+        assert!(NumberFormat::German.normalize("12.34").is_err());
+        assert!(NumberFormat::German.normalize("1234.567").is_err());
+        assert!(NumberFormat::German.normalize(".123").is_err());
+        assert!(NumberFormat::English.normalize("1,23.5").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_from_row() -> anyhow::Result<()> {
+        let data = "\
+            type; client; tx; amount
+            deposit; 1; 1; 1.234,5
+            dispute; 1; 1;";
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .delimiter(b';')
+            .flexible(true)
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let headers = reader.headers()?.clone();
+
+        let results = reader
+            .records()
+            .map(|row| Record::from_row(&row?, &headers, NumberFormat::German, false))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        assert_eq!(
+            results,
+            vec![
+                Record {
+                    record_type: RecordType::Deposit,
+                    client: 1,
+                    tx: 1,
+                    amount: Some(Amount::new(12_345, 1)),
+                    ..Default::default()
+                },
+                Record {
+                    record_type: RecordType::Dispute,
+                    client: 1,
+                    tx: 1,
+                    amount: None,
+                    ..Default::default()
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_record_valid() {
         let data = "\
@@ -151,6 +838,59 @@ mod tests {
         assert!(results.into_iter().all(|record| record.validate().is_ok()));
     }
 
+    #[test]
+    fn test_amount_limits() -> anyhow::Result<()> {
+        let limits = AmountLimits::default();
+        for amount in ["0", "0.0001", "1.2345", "99.9999", "1000000000000"] {
+            assert_eq!(limits.check(amount.parse()?), Ok(()), "{amount}");
+        }
+        assert_eq!(limits.check("-1".parse()?), Err(AmountError::Negative));
+        assert_eq!(
+            limits.check("1000000000000.0001".parse()?),
+            Err(AmountError::TooLarge(DEFAULT_MAX_AMOUNT))
+        );
+
+        let limits = AmountLimits {
+            max_decimals: 2,
+            max_amount: Amount::new(100, 0),
+        };
+        assert_eq!(limits.check(Amount::new(125, 2)), Ok(()));
+        assert_eq!(
+            limits.check(Amount::new(1_255, 3)),
+            Err(AmountError::TooManyDecimals(2))
+        );
+        assert_eq!(
+            limits.check(Amount::new(10_001, 2)),
+            Err(AmountError::TooLarge(Amount::new(100, 0)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_invalid_amount() -> anyhow::Result<()> {
+        let data = "\
+            type, client, tx, amount
+            deposit, 1, 1, NaN
+            deposit, 1, 2, inf
+            withdrawal, 1, 3, 1e40
+            deposit, 1, 4, 1e-7
+            deposit, 1, 5, 1000";
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let results = reader
+            .deserialize::<Record>()
+            .map(|record| record.is_ok_and(|record| record.validate().is_ok()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(results, [false, false, false, false, true]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_record_invalid() {
         let data = "\
@@ -201,4 +941,52 @@ mod tests {
             3
         );
     }
+
+    #[test]
+    fn test_record_reason_code() -> anyhow::Result<()> {
+        let data = "\
+            type, client, tx, amount, reason_code
+            adjustment, 1, 1, -1.5, FEE_REFUND
+            adjustment, 1, 2, 1.5,
+            adjustment, 1, 3, 1.5, fee refund
+            writeoff, 1, 4, ,
+            writeoff, 1, 5, , BANKRUPTCY";
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let results = reader
+            .deserialize::<Record>()
+            .map(|record| Ok(record?.validate().is_ok()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        assert_eq!(results, [true, false, false, true, true]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_escrow() -> anyhow::Result<()> {
+        let data = "\
+            type, client, tx, amount, escrow
+            escrow_hold, 1, 1, 2.5, order-1
+            escrow_release, 1, 2, 2.5, order-1
+            escrow_hold, 1, 3, 2.5,
+            escrow_release, 1, 4, , order-1
+            escrow_hold, 1, 5, 2.5, order 1";
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let results = reader
+            .deserialize::<Record>()
+            .map(|record| Ok(record?.validate().is_ok()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        assert_eq!(results, [true, true, false, false, false]);
+
+        Ok(())
+    }
 }
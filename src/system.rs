@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Context};
+
+use crate::{
+    amount::Amount,
+    journal::{Journal, PostingAccount},
+    structs::ClientRecord,
+};
+
+/// The internal accounts of the journal, which receive the other side of
+/// deposits, withdrawals and chargebacks (`clearing`), opening balances
+/// (`opening`), adjustments such as fees (`adjustments`) and write-offs
+/// (`losses`).
+const INTERNAL: [PostingAccount; 4] = [
+    PostingAccount::Clearing,
+    PostingAccount::Opening,
+    PostingAccount::Adjustments,
+    PostingAccount::Losses,
+];
+
+/// Client ids reserved for the internal accounts of the journal, so their
+/// balances can be listed after the accounts of the clients. Together, the
+/// totals of all listed accounts add up to zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemAccounts {
+    accounts: Vec<(PostingAccount, u16)>,
+}
+
+impl SystemAccounts {
+    /// The client ids of the internal accounts.
+    pub fn clients(&self) -> impl Iterator<Item = u16> + '_ {
+        self.accounts.iter().map(|&(_, client)| client)
+    }
+
+    /// The balances of the internal accounts as accounts of their client
+    /// ids, in the order clearing, opening, adjustments and losses. Nothing
+    /// is ever held or locked.
+    pub fn records(&self, journal: &Journal) -> anyhow::Result<Vec<ClientRecord>> {
+        let mut balances = [Amount::ZERO; INTERNAL.len()];
+        for posting in journal.postings() {
+            if let Some(index) = INTERNAL
+                .iter()
+                .position(|account| *account == posting.account)
+            {
+                balances[index] = balances[index].checked_add(posting.amount)?;
+            }
+        }
+        Ok(self
+            .accounts
+            .iter()
+            .map(|&(account, client)| {
+                let index = INTERNAL
+                    .iter()
+                    .position(|internal| *internal == account)
+                    .expect("system accounts are internal accounts");
+                let balance = balances[index];
+                ClientRecord {
+                    client,
+                    available: balance,
+                    held: Amount::ZERO,
+                    total: balance,
+                    locked: false,
+                    escrowed: Amount::ZERO,
+                }
+            })
+            .collect())
+    }
+}
+
+impl std::str::FromStr for SystemAccounts {
+    type Err = anyhow::Error;
+
+    /// Parses a list like `clearing=65535,opening=65534,adjustments=65533,losses=65532`,
+    /// which has to assign every internal account a distinct client id.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut accounts = Vec::new();
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, client) = setting
+                .split_once('=')
+                .ok_or(anyhow!("Expected account=client, got {setting}"))?;
+            let account = INTERNAL
+                .into_iter()
+                .find(|account| account.to_string() == name.trim())
+                .ok_or(anyhow!(
+                    "Unknown system account {name}, expected one of: clearing, opening, \
+                     adjustments, losses"
+                ))?;
+            let client: u16 = client
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid client id of system account {name}"))?;
+            if accounts.iter().any(|&(other, _)| other == account) {
+                return Err(anyhow!("System account {name} is assigned twice"));
+            }
+            if accounts.iter().any(|&(_, other)| other == client) {
+                return Err(anyhow!(
+                    "Client {client} is assigned to two system accounts"
+                ));
+            }
+            accounts.push((account, client));
+        }
+        if let Some(missing) = INTERNAL
+            .iter()
+            .find(|account| !accounts.iter().any(|(assigned, _)| assigned == *account))
+        {
+            return Err(anyhow!("Missing client id of system account {missing}"));
+        }
+        accounts.sort_unstable_by_key(|(account, _)| {
+            INTERNAL.iter().position(|internal| internal == account)
+        });
+        Ok(Self { accounts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Ledger;
+
+    #[test]
+    fn test_system_accounts() -> anyhow::Result<()> {
+        let system: SystemAccounts = "losses=9, clearing=6,opening=7,adjustments=8".parse()?;
+        assert_eq!(system.clients().collect::<Vec<_>>(), [6, 7, 8, 9]);
+
+        let mut ledger = Ledger::new().with_journal();
+        ledger.open_account(&ClientRecord {
+            client: 1,
+            available: Amount::new(1, 0),
+            held: Amount::ZERO,
+            total: Amount::new(1, 0),
+            locked: false,
+            escrowed: Amount::ZERO,
+        })?;
+        ledger.deposit(1, 1, Amount::new(10, 0))?;
+        ledger.withdraw(1, 2, Amount::new(8, 0))?;
+        ledger.dispute(1, 1)?;
+        ledger.chargeback(1, 1)?;
+        ledger.write_off(1, 3)?;
+        ledger.deposit(2, 4, Amount::new(5, 0))?;
+        ledger.adjust(2, 5, Amount::new(-5, 1))?;
+
+        let journal = ledger.journal().ok_or(anyhow!("journal is not recorded"))?;
+        let records = system.records(journal)?;
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| (record.client, record.total))
+                .collect::<Vec<_>>(),
+            [
+                (6, Amount::new(3, 0)),
+                (7, Amount::new(-1, 0)),
+                (8, Amount::new(5, 1)),
+                (9, Amount::new(-7, 0))
+            ]
+        );
+        let net = ledger
+            .client_records()?
+            .iter()
+            .chain(&records)
+            .try_fold(Amount::ZERO, |net, record| net.checked_add(record.total))?;
+        assert_eq!(net, Amount::ZERO);
+
+        assert!("clearing=6,opening=7,adjustments=8"
+            .parse::<SystemAccounts>()
+            .is_err());
+        assert!("clearing=6,opening=6,adjustments=8,losses=9"
+            .parse::<SystemAccounts>()
+            .is_err());
+        assert!("clearing=6,fees=7,adjustments=8,losses=9"
+            .parse::<SystemAccounts>()
+            .is_err());
+
+        Ok(())
+    }
+}
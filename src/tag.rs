@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use crate::{account::Applied, amount::Amount, error::LedgerError, structs};
+
+/// Funds moved by a client under a single tag.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct TagTotals {
+    deposits: u64,
+    deposited: Amount,
+    withdrawals: u64,
+    withdrawn: Amount,
+    charged_back: Amount,
+}
+
+/// Sums up the deposits and withdrawals of every client per tag, e.g. for
+/// budgeting by category.
+///
+/// Chargebacks are attributed to the tag of the deposit they reference, so
+/// the tag of every transaction that has one is remembered for as long as
+/// the ledger lives.
+#[derive(Debug, Default)]
+pub struct Tags {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+    totals: HashMap<(u16, u32), TagTotals>,
+    transactions: HashMap<u32, u32>,
+}
+
+impl Tags {
+    /// Accounts for a record that was applied to the ledger.
+    pub fn record(
+        &mut self,
+        record: &structs::Record,
+        applied: Applied,
+    ) -> Result<(), LedgerError> {
+        let id = match (&record.tag, applied) {
+            (Some(tag), Applied::Deposited(_) | Applied::Withdrawn(_)) => {
+                let id = self.intern(tag);
+                self.transactions.insert(record.tx, id);
+                id
+            }
+            (_, Applied::ChargedBack(_)) => match self.transactions.get(&record.tx) {
+                Some(&id) => id,
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+
+        let totals = self.totals.entry((record.client, id)).or_default();
+        match applied {
+            Applied::Deposited(amount) => {
+                totals.deposits += 1;
+                totals.deposited = totals.deposited.checked_add(amount)?;
+            }
+            Applied::Withdrawn(amount) => {
+                totals.withdrawals += 1;
+                totals.withdrawn = totals.withdrawn.checked_add(amount)?;
+            }
+            Applied::ChargedBack(amount) => {
+                totals.charged_back = totals.charged_back.checked_add(amount)?
+            }
+            Applied::Held(_)
+            | Applied::Released(_)
+            | Applied::Unlocked
+            | Applied::Adjusted(_)
+            | Applied::WrittenOff(_)
+            | Applied::EscrowHeld(_)
+            | Applied::EscrowReleased(_) => {}
+        }
+        Ok(())
+    }
+
+    /// One row per client and tag, ordered by client and tag.
+    pub fn report(&self) -> Result<Vec<structs::TagRecord>, LedgerError> {
+        let mut report = self
+            .totals
+            .iter()
+            .map(|(&(client, id), totals)| {
+                Ok(structs::TagRecord {
+                    client,
+                    tag: self.names[id as usize].clone(),
+                    deposits: totals.deposits,
+                    deposited: totals.deposited,
+                    withdrawals: totals.withdrawals,
+                    withdrawn: totals.withdrawn,
+                    charged_back: totals.charged_back,
+                    net: (totals.deposited)
+                        .checked_sub(totals.withdrawn)?
+                        .checked_sub(totals.charged_back)?,
+                })
+            })
+            .collect::<Result<Vec<_>, LedgerError>>()?;
+        report.sort_unstable_by(|a, b| (a.client, &a.tag).cmp(&(b.client, &b.tag)));
+        Ok(report)
+    }
+
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{account::Ledger, structs::RecordType};
+
+    #[test]
+    fn test_report() -> anyhow::Result<()> {
+        let mut tracker = Ledger::new();
+        let record =
+            |record_type, client, tx, amount: Option<Amount>, tag: Option<&str>| structs::Record {
+                record_type,
+                client,
+                tx,
+                amount,
+                tag: tag.map(str::to_string),
+                ..Default::default()
+            };
+        for record in [
+            record(
+                RecordType::Deposit,
+                1,
+                1,
+                Some(Amount::new(100, 0)),
+                Some("salary"),
+            ),
+            record(
+                RecordType::Deposit,
+                1,
+                2,
+                Some(Amount::new(20, 0)),
+                Some("refund"),
+            ),
+            record(
+                RecordType::Withdrawal,
+                1,
+                3,
+                Some(Amount::new(30, 0)),
+                Some("groceries"),
+            ),
+            record(
+                RecordType::Withdrawal,
+                1,
+                4,
+                Some(Amount::new(10, 0)),
+                Some("groceries"),
+            ),
+            record(RecordType::Withdrawal, 1, 5, Some(Amount::new(5, 0)), None),
+            record(
+                RecordType::Deposit,
+                2,
+                6,
+                Some(Amount::new(7, 0)),
+                Some("salary"),
+            ),
+            record(RecordType::Dispute, 1, 2, None, None),
+            record(RecordType::Chargeback, 1, 2, None, None),
+        ] {
+            tracker.apply(&record)?;
+        }
+
+        let report = tracker.tags().report()?;
+        let rows: Vec<_> = report
+            .iter()
+            .map(|row| (row.client, row.tag.as_str(), row.net))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (1, "groceries", Amount::new(-40, 0)),
+                (1, "refund", Amount::ZERO),
+                (1, "salary", Amount::new(100, 0)),
+                (2, "salary", Amount::new(7, 0)),
+            ]
+        );
+        assert_eq!(
+            (report[0].withdrawals, report[0].withdrawn),
+            (2, Amount::new(40, 0))
+        );
+        assert_eq!(report[1].charged_back, Amount::new(20, 0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+
+use crate::structs::ClientRecord;
+
+/// The fields of an account that templates can refer to.
+const FIELDS: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+/// A text layout that is rendered once per account.
+///
+/// Placeholders like `{total}` are replaced by the field of the account.
+/// They can be followed by a format spec of an optional alignment (`<` for
+/// left, the default, or `>` for right), a width and a precision for amounts,
+/// e.g. `{client:>6}` or `{available:>12.2}`. Literal braces are written as
+/// `{{` and `}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field {
+        name: &'static str,
+        right_align: bool,
+        width: usize,
+        precision: Option<usize>,
+    },
+}
+
+impl Template {
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let template = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read template {}", path.display()))?;
+        template.parse()
+    }
+
+    pub fn render(&self, record: &ClientRecord) -> String {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => output.push_str(text),
+                &Part::Field {
+                    name,
+                    right_align,
+                    width,
+                    precision,
+                } => {
+                    let value = match (name, precision) {
+                        ("client", _) => record.client.to_string(),
+                        ("locked", _) => record.locked.to_string(),
+                        (name, precision) => {
+                            let amount = match name {
+                                "available" => record.available,
+                                "held" => record.held,
+                                _ => record.total,
+                            };
+                            match precision {
+                                Some(precision) => format!("{amount:.precision$}"),
+                                None => amount.to_string(),
+                            }
+                        }
+                    };
+                    if right_align {
+                        output.push_str(&format!("{value:>width$}"));
+                    } else {
+                        output.push_str(&format!("{value:<width$}"));
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+impl std::str::FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(anyhow!("Unclosed {{ in template")),
+                        }
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(parse_placeholder(&placeholder)?);
+                }
+                '}' => return Err(anyhow!("Unmatched }} in template")),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Self { parts })
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> anyhow::Result<Part> {
+    let (name, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+    let name = FIELDS
+        .into_iter()
+        .find(|field| *field == name.trim())
+        .ok_or(anyhow!(
+            "Unknown template field {name}, expected one of: {}",
+            FIELDS.join(", ")
+        ))?;
+
+    let (right_align, spec) = match spec.strip_prefix('>') {
+        Some(spec) => (true, spec),
+        None => (false, spec.strip_prefix('<').unwrap_or(spec)),
+    };
+    let (width, precision) = spec.split_once('.').unwrap_or((spec, ""));
+    let invalid_spec = || anyhow!("Invalid format spec in template placeholder {{{placeholder}}}");
+
+    Ok(Part::Field {
+        name,
+        right_align,
+        width: match width {
+            "" => 0,
+            width => width.parse().map_err(|_| invalid_spec())?,
+        },
+        precision: match precision {
+            "" => None,
+            precision => Some(precision.parse().map_err(|_| invalid_spec())?),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+
+    fn record() -> ClientRecord {
+        ClientRecord {
+            client: 7,
+            available: Amount::new(15, 1),
+            held: Amount::new(25, 2),
+            total: Amount::new(175, 2),
+            locked: false,
+            escrowed: Amount::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_render() -> anyhow::Result<()> {
+        let template: Template = "Client {client}: {total} ({locked})\n".parse()?;
+        assert_eq!(template.render(&record()), "Client 7: 1.75 (false)\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_format_spec() -> anyhow::Result<()> {
+        let template: Template = "{client:>4}|{available:<6.2}|{held:>8.3}|{{x}}".parse()?;
+        assert_eq!(template.render(&record()), "   7|1.50  |   0.250|{x}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!("{balance}".parse::<Template>().is_err());
+        assert!("{total:x}".parse::<Template>().is_err());
+        assert!("{total:>4.y}".parse::<Template>().is_err());
+        assert!("total}".parse::<Template>().is_err());
+        assert!("{total".parse::<Template>().is_err());
+    }
+}
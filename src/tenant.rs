@@ -0,0 +1,325 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    account::{FreezePolicy, Ledger},
+    amount::{self, Amount},
+    client_ids::ClientIdRanges,
+    engine::{Engine, RunSummary},
+    structs::{AmountLimits, Record},
+};
+
+/// Tenant of the records without a `tenant` column.
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Whether the tenant id can be used as a file name: ascii letters, digits,
+/// `-` and `_` only.
+pub fn is_valid_tenant(tenant: &str) -> bool {
+    !tenant.is_empty()
+        && tenant
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Configuration overrides per tenant, on top of the command line options.
+///
+/// Settings files are plain text files of `tenant.setting=value` lines:
+///
+/// ```text
+/// acme.freeze=chargebacks=3,held=1000
+/// acme.max-amount=5000
+/// globex.max-decimals=2
+/// globex.allowed-clients=2000-2999
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TenantSettings {
+    overrides: BTreeMap<String, TenantOverrides>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct TenantOverrides {
+    freeze: Vec<String>,
+    max_amount: Option<Amount>,
+    max_decimals: Option<u32>,
+    allowed_clients: Option<String>,
+}
+
+impl TenantSettings {
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tenant settings {}", path.display()))?;
+        contents.parse()
+    }
+
+    /// The freeze policy of the tenant: the given one with the tenant's
+    /// rules applied.
+    pub fn freeze(&self, tenant: &str, mut freeze: FreezePolicy) -> anyhow::Result<FreezePolicy> {
+        if let Some(overrides) = self.overrides.get(tenant) {
+            for rules in &overrides.freeze {
+                freeze.set_rules(rules)?;
+            }
+        }
+        Ok(freeze)
+    }
+
+    /// The amount limits of the tenant: the given ones with the tenant's
+    /// overrides applied.
+    pub fn amount_limits(&self, tenant: &str, mut limits: AmountLimits) -> AmountLimits {
+        if let Some(overrides) = self.overrides.get(tenant) {
+            limits.max_amount = overrides.max_amount.unwrap_or(limits.max_amount);
+            limits.max_decimals = overrides.max_decimals.unwrap_or(limits.max_decimals);
+        }
+        limits
+    }
+
+    /// The client id ranges of the tenant: the given ones with the tenant's
+    /// allowed ranges instead of the given allowed ranges, if it has any.
+    /// Reserved ranges apply to all tenants.
+    pub fn client_ids(
+        &self,
+        tenant: &str,
+        mut client_ids: ClientIdRanges,
+    ) -> anyhow::Result<ClientIdRanges> {
+        if let Some(allowed) = self
+            .overrides
+            .get(tenant)
+            .and_then(|overrides| overrides.allowed_clients.as_deref())
+        {
+            client_ids.set_allowed(allowed)?;
+        }
+        Ok(client_ids)
+    }
+}
+
+impl std::str::FromStr for TenantSettings {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut settings = Self::default();
+
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(anyhow!("Unexpected tenant setting line: {line}"))?;
+            let (tenant, setting) = key
+                .trim()
+                .split_once('.')
+                .ok_or(anyhow!("Expected tenant.setting in line: {line}"))?;
+            if !is_valid_tenant(tenant) {
+                return Err(anyhow!("Invalid tenant id {tenant} in line: {line}"));
+            }
+            let value = value.trim();
+            let overrides = settings.overrides.entry(tenant.to_string()).or_default();
+            match setting {
+                "freeze" => {
+                    FreezePolicy::default().set_rules(value)?;
+                    overrides.freeze.push(value.to_string());
+                }
+                "max-amount" => {
+                    let max_amount: Amount = value
+                        .parse()
+                        .with_context(|| format!("Invalid max-amount for tenant {tenant}"))?;
+                    if max_amount < Amount::ZERO {
+                        return Err(anyhow!("Invalid max-amount for tenant {tenant}"));
+                    }
+                    overrides.max_amount = Some(max_amount);
+                }
+                "max-decimals" => {
+                    let max_decimals: u32 = value
+                        .parse()
+                        .with_context(|| format!("Invalid max-decimals for tenant {tenant}"))?;
+                    if max_decimals > amount::DECIMALS {
+                        return Err(anyhow!("Invalid max-decimals for tenant {tenant}"));
+                    }
+                    overrides.max_decimals = Some(max_decimals);
+                }
+                "allowed-clients" => {
+                    ClientIdRanges::default()
+                        .set_allowed(value)
+                        .with_context(|| format!("Invalid allowed-clients for tenant {tenant}"))?;
+                    overrides.allowed_clients = Some(value.to_string());
+                }
+                other => {
+                    return Err(anyhow!(
+                        "Unknown tenant setting {other}, expected one of: freeze, max-amount, \
+                         max-decimals, allowed-clients"
+                    ))
+                }
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Builds the engine of a tenant.
+type EngineFactory = Box<dyn FnMut(&str) -> anyhow::Result<Engine>>;
+
+/// Keeps an isolated engine and ledger per tenant and routes records to them
+/// by their `tenant` column.
+///
+/// Engines are created on the first record of their tenant. Records without
+/// a tenant and rows that could not be read go to [`DEFAULT_TENANT`].
+pub struct Tenants {
+    engines: BTreeMap<String, (Engine, RunSummary)>,
+    build: EngineFactory,
+}
+
+impl Tenants {
+    /// Creates the router with a function building the engine of a tenant.
+    pub fn new(build: impl FnMut(&str) -> anyhow::Result<Engine> + 'static) -> Self {
+        Self {
+            engines: BTreeMap::new(),
+            build: Box::new(build),
+        }
+    }
+
+    /// Applies all records to the ledgers of their tenants and returns the
+    /// ledgers and run summaries by tenant. Rows are numbered across all
+    /// tenants.
+    pub fn run(
+        mut self,
+        records: impl IntoIterator<Item = anyhow::Result<Record>>,
+    ) -> anyhow::Result<BTreeMap<String, (Ledger, RunSummary)>> {
+        for (index, result) in records.into_iter().enumerate() {
+            let (tenant, result) = match result {
+                Ok(record) => match record.tenant.as_deref() {
+                    None => (DEFAULT_TENANT.to_string(), Ok(record)),
+                    Some(tenant) if is_valid_tenant(tenant) => (tenant.to_string(), Ok(record)),
+                    Some(tenant) => (
+                        DEFAULT_TENANT.to_string(),
+                        Err(anyhow!("Invalid tenant id {tenant}")),
+                    ),
+                },
+                Err(err) => (DEFAULT_TENANT.to_string(), Err(err)),
+            };
+            if !self.engines.contains_key(&tenant) {
+                let mut engine = (self.build)(&tenant)
+                    .with_context(|| format!("Failed to configure tenant {tenant}"))?;
+                let summary = engine.start();
+                self.engines.insert(tenant.clone(), (engine, summary));
+            }
+            let (engine, summary) = self
+                .engines
+                .get_mut(&tenant)
+                .expect("the engine was just created");
+            engine
+                .feed(summary, index as u64 + 1, result)
+                .with_context(|| format!("Failed in tenant {tenant}"))?;
+        }
+
+        self.engines
+            .into_iter()
+            .map(|(tenant, (mut engine, summary))| {
+                let summary = engine
+                    .finish(summary)
+                    .with_context(|| format!("Failed in tenant {tenant}"))?;
+                Ok((tenant, (engine.into_ledger(), summary)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::EngineBuilder, structs::RecordType};
+
+    fn deposit(tx: u32, amount: Amount, tenant: Option<&str>) -> anyhow::Result<Record> {
+        Ok(Record {
+            record_type: RecordType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(amount),
+            tenant: tenant.map(str::to_string),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_settings() -> anyhow::Result<()> {
+        let settings: TenantSettings = "\
+            acme.freeze=chargebacks=3
+            acme.max-amount=100
+            globex.max-decimals=2
+            globex.allowed-clients=2000-2999
+        "
+        .parse()?;
+
+        let freeze = settings.freeze("acme", FreezePolicy::default())?;
+        assert_eq!(freeze.chargebacks, Some(3));
+        assert_eq!(
+            settings.freeze("globex", FreezePolicy::default())?,
+            FreezePolicy::default()
+        );
+        assert_eq!(
+            settings.amount_limits("acme", AmountLimits::default()),
+            AmountLimits {
+                max_amount: Amount::new(100, 0),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            settings
+                .amount_limits("globex", AmountLimits::default())
+                .max_decimals,
+            2
+        );
+
+        let mut client_ids = ClientIdRanges::default();
+        client_ids.set_allowed("1-999")?;
+        assert!(settings
+            .client_ids("acme", client_ids.clone())?
+            .check(1)
+            .is_ok());
+        let globex = settings.client_ids("globex", client_ids)?;
+        assert!(globex.check(1).is_err());
+        assert!(globex.check(2000).is_ok());
+
+        assert!("acme=1".parse::<TenantSettings>().is_err());
+        assert!("acme.unknown=1".parse::<TenantSettings>().is_err());
+        assert!("../etc.max-amount=1".parse::<TenantSettings>().is_err());
+        assert!("acme.freeze=often".parse::<TenantSettings>().is_err());
+        assert!("acme.max-decimals=5".parse::<TenantSettings>().is_err());
+        assert!("acme.allowed-clients=9-1"
+            .parse::<TenantSettings>()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tenants() -> anyhow::Result<()> {
+        let settings: TenantSettings = "acme.max-amount=100".parse()?;
+        let tenants = Tenants::new(move |tenant| {
+            Ok(EngineBuilder::new()
+                .amount_limits(settings.amount_limits(tenant, AmountLimits::default()))
+                .build())
+        });
+        let ledgers = tenants.run([
+            deposit(1, Amount::new(10, 0), Some("acme")),
+            deposit(1, Amount::new(20, 0), Some("globex")),
+            deposit(2, Amount::new(500, 0), Some("acme")),
+            deposit(2, Amount::new(500, 0), Some("globex")),
+            deposit(3, Amount::new(1, 0), None),
+            deposit(4, Amount::new(1, 0), Some("../x")),
+        ])?;
+
+        assert_eq!(
+            ledgers.keys().collect::<Vec<_>>(),
+            ["acme", "default", "globex"]
+        );
+        let (acme, summary) = &ledgers["acme"];
+        assert_eq!(acme.client_records()?[0].total, Amount::new(10, 0));
+        assert_eq!((summary.rows, summary.failed), (2, 1));
+        let (globex, _) = &ledgers["globex"];
+        assert_eq!(globex.client_records()?[0].total, Amount::new(520, 0));
+        let (default, summary) = &ledgers[DEFAULT_TENANT];
+        assert_eq!(default.client_records()?[0].total, Amount::new(1, 0));
+        assert_eq!((summary.applied, summary.failed), (1, 1));
+
+        Ok(())
+    }
+}